@@ -1,7 +1,7 @@
-use anyhow::{Result, anyhow};
-use clap::Parser;
-use std::{iter, path::Path, path::PathBuf, process};
-use study_sync::*;
+use anyhow::{Context, Result, anyhow};
+use clap::{Parser, Subcommand};
+use std::{io::Write, iter, path::Path, path::PathBuf, process};
+use study_sync::{config::required, *};
 use tokio::{select, signal, sync::mpsc, try_join};
 use tracing::{error, info};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -9,46 +9,49 @@ use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 #[derive(Parser, Debug)]
 struct Args {
     #[arg(long)]
-    listen: String,
+    config: Option<PathBuf>,
 
-    #[arg(long)]
-    plays_database: PathBuf,
-
-    #[arg(long)]
-    games_database: PathBuf,
-
-    #[arg(long)]
-    trim_game_prefix: Option<String>,
-
-    #[arg(long)]
-    intake_url: String,
-
-    #[arg(long)]
-    screenshot_url: String,
-
-    #[arg(long)]
-    save_url: String,
-
-    #[arg(long)]
-    extra_directory: String,
-
-    #[clap(long, required = true, num_args = 1.., value_delimiter = ',')]
-    watch_screenshots: Vec<PathBuf>,
-
-    #[clap(long, required = true, num_args = 1.., value_delimiter = ',')]
-    watch_saves: Vec<PathBuf>,
-
-    #[arg(long)]
-    pending_screenshots: PathBuf,
-
-    #[arg(long)]
-    pending_saves: PathBuf,
+    #[command(flatten)]
+    settings: config::Config,
 
-    #[arg(long)]
-    keep_saves: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-    #[arg(long)]
-    led_path: PathBuf,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage the games database
+    #[command(subcommand)]
+    Games(games::Command),
+
+    /// Print the history of plays
+    Export {
+        #[arg(long, value_enum, default_value_t)]
+        format: export::Format,
+
+        #[command(flatten)]
+        filter: export::Filter,
+    },
+
+    /// List and correct plays, through the running study-sync
+    #[command(subcommand)]
+    Plays(plays::Command),
+
+    /// Remove kept saves outside the [retention] policy
+    Prune {
+        /// List what would be removed without removing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Print playtime totals, streaks, and longest sessions
+    Stats {
+        #[arg(long, value_enum, default_value_t)]
+        period: stats::Period,
+
+        #[command(flatten)]
+        filter: export::Filter,
+    },
 }
 
 #[tokio::main]
@@ -60,37 +63,142 @@ async fn main() -> Result<()> {
 
     let args = Args::parse();
 
-    if !args.led_path.is_file() {
-        return Err(anyhow!("led-path {:?} not a file", args.led_path));
+    let config = match &args.config {
+        Some(path) => args.settings.merge(config::Config::from_file(path)?),
+        None => args.settings,
+    };
+
+    match args.command {
+        Some(Command::Games(command)) => {
+            let games_database = required(config.games_database, "games_database")?;
+            return games::run(
+                command,
+                &games_database,
+                config.plays_database.as_deref(),
+                config.trim_game_prefix.as_deref(),
+            );
+        }
+        Some(Command::Export { format, filter }) => {
+            let plays_database = required(config.plays_database, "plays_database")?;
+            let games_database = required(config.games_database, "games_database")?;
+            let (notify_tx, _notify_rx) = mpsc::unbounded_channel();
+            let dbh = database::connect(plays_database, games_database, notify_tx).await?;
+            let output = export::export(&dbh, format, &filter).await?;
+            std::io::stdout().write_all(&output)?;
+            return Ok(());
+        }
+        Some(Command::Stats { period, filter }) => {
+            let plays_database = required(config.plays_database, "plays_database")?;
+            let games_database = required(config.games_database, "games_database")?;
+            let (notify_tx, _notify_rx) = mpsc::unbounded_channel();
+            let dbh = database::connect(plays_database, games_database, notify_tx).await?;
+            print!("{}", stats::stats(&dbh, period, &filter).await?);
+            return Ok(());
+        }
+        Some(Command::Prune { dry_run }) => {
+            let plays_database = required(config.plays_database, "plays_database")?;
+            let games_database = required(config.games_database, "games_database")?;
+            let keep_saves = required(config.keep_saves, "keep_saves")?;
+            let pending_saves = required(config.pending_saves, "pending_saves")?;
+            let policy = required(config.retention, "retention")?;
+            let (notify_tx, _notify_rx) = mpsc::unbounded_channel();
+            let dbh = database::connect(plays_database, games_database, notify_tx).await?;
+            // The store is left to the running study-sync, which may be
+            // keeping a save in it
+            let report =
+                retention::prune_saves(&dbh, &keep_saves, &pending_saves, &policy, dry_run).await?;
+            for path in &report.pruned {
+                println!(
+                    "{} {}",
+                    if dry_run { "would prune" } else { "pruned" },
+                    path.display()
+                );
+            }
+            for path in &report.unconfirmed {
+                println!("not uploaded {}", path.display());
+            }
+            return Ok(());
+        }
+        Some(Command::Plays(command)) => {
+            let listen = required(config.listen, "listen")?;
+            return plays::run(command, &listen).await;
+        }
+        None => {}
+    }
+
+    let request_timeout = config.request_timeout();
+    let upload_timeout = config.upload_timeout();
+    let upload_chunk_size = config.upload_chunk_size();
+    let disk_thresholds = config.disk_thresholds();
+    let intake_retry = config.retry_policy(&config.retry.intake);
+    let screenshots_retry = config.retry_policy(&config.retry.screenshots);
+    let saves_retry = config.retry_policy(&config.retry.saves);
+    let latest_screenshot_name = config.latest_screenshot_name().to_owned();
+    let pending_extra_name = config.pending_extra_name().to_owned();
+    let failed_directory_name = config.failed_directory_name().to_owned();
+    let provisional_directory_name = config.provisional_directory_name().to_owned();
+    let pause_policy = config.pause_policy.unwrap_or_default();
+    let duplicate_saves = config.duplicate_saves.unwrap_or_default();
+    let intake_batch_size = config.intake_batch_size();
+
+    let listen = required(config.listen, "listen")?;
+    let plays_database = required(config.plays_database, "plays_database")?;
+    let games_database = required(config.games_database, "games_database")?;
+    let trim_game_prefix = config.trim_game_prefix;
+    let intake_backend = match config.intake_backend {
+        Some(backend) => backend,
+        None => intake::BackendConfig::Service {
+            url: required(config.intake_url, "intake_url")?,
+        },
+    };
+    let intake_service = intake::ServiceOptions {
+        languages: config.intake_languages.unwrap_or_default(),
+        batch_url: config.intake_batch_url,
+        batch_size: intake_batch_size,
+    };
+    let screenshot_url = required(config.screenshot_url, "screenshot_url")?;
+    let save_url = required(config.save_url, "save_url")?;
+    let extra_directory = required(config.extra_directory, "extra_directory")?;
+    let watch_screenshots = required(config.watch_screenshots, "watch_screenshots")?;
+    let watch_saves = required(config.watch_saves, "watch_saves")?;
+    let pending_screenshots = required(config.pending_screenshots, "pending_screenshots")?;
+    let pending_saves = required(config.pending_saves, "pending_saves")?;
+    let keep_saves = required(config.keep_saves, "keep_saves")?;
+    let led_path = required(config.led_path, "led_path")?;
+
+    if !led_path.is_file() {
+        return Err(anyhow!("`led_path` {led_path:?} not a file"));
     }
 
-    let listen = args.listen.parse()?;
+    let listen = listen
+        .parse()
+        .with_context(|| format!("`listen` {listen:?} is not a socket address"))?;
 
-    for (flag, path) in args
-        .watch_screenshots
+    for (key, path) in watch_screenshots
         .iter()
-        .map(|d| ("--watch-screenshots", d))
-        .chain(args.watch_saves.iter().map(|d| ("--watch-saves", d)))
-        .chain(iter::once((
-            "--pending-screenshots",
-            &args.pending_screenshots,
-        )))
-        .chain(iter::once(("--pending-saves", &args.pending_saves)))
-        .chain(iter::once(("--keep-saves", &args.keep_saves)))
+        .map(|d| ("watch_screenshots", d))
+        .chain(watch_saves.iter().map(|d| ("watch_saves", d)))
+        .chain(iter::once(("pending_screenshots", &pending_screenshots)))
+        .chain(iter::once(("pending_saves", &pending_saves)))
+        .chain(iter::once(("keep_saves", &keep_saves)))
     {
         if !path.is_dir() {
-            return Err(anyhow!("{flag:?} {path:?} is not a directory"));
+            return Err(anyhow!("`{key}` {path:?} is not a directory"));
         }
     }
 
-    let latest_screenshot = args.pending_screenshots.join("latest.png");
-    let extra_directory = args.pending_screenshots.join("extra/");
-    if !extra_directory.is_dir() {
+    let latest_screenshot = pending_screenshots.join(latest_screenshot_name);
+    let pending_extra_directory = pending_screenshots.join(&pending_extra_name);
+    if !pending_extra_directory.is_dir() {
         return Err(anyhow!(
-            "{extra_directory:?} (derived from --pending-screenshots) is not a directory"
+            "{pending_extra_directory:?} (derived from `pending_screenshots` and `pending_extra_name`) is not a directory"
         ));
     }
 
+    let provisional_directory = pending_screenshots.join(&provisional_directory_name);
+    let failed_screenshots = pending_screenshots.join(&failed_directory_name);
+    let failed_saves = pending_saves.join(&failed_directory_name);
+
     let is_online = true;
 
     let (server, server_tx) = server::prepare();
@@ -102,30 +210,49 @@ async fn main() -> Result<()> {
     let (saves, saves_tx) = saves::prepare();
     let (notify, notify_tx) = notify::prepare();
 
-    let dbh =
-        database::connect(args.plays_database, args.games_database, notify_tx.clone()).await?;
+    let dbh = database::connect(plays_database, games_database, notify_tx.clone()).await?;
 
-    let server = server.start(&listen, orchestrator_tx.clone(), notify_tx.clone());
+    let statuses = server::Statuses {
+        orchestrator: orchestrator.status(),
+        intake: intake.status(),
+        screenshots: screenshots.status(),
+        saves: saves.status(),
+        notify: notify.status(),
+    };
+
+    let server = server.start(
+        &listen,
+        orchestrator_tx.clone(),
+        notify_tx.clone(),
+        statuses,
+        dbh.clone(),
+    );
     let screenshot_watcher = screenshot_watcher.start(
-        &args.watch_screenshots,
+        &watch_screenshots,
         watcher::WatchTarget::Screenshots,
         orchestrator_tx.clone(),
         notify_tx.clone(),
     );
     let save_watcher = save_watcher.start(
-        &args.watch_saves,
+        &watch_saves,
         watcher::WatchTarget::SaveFiles,
         orchestrator_tx.clone(),
         notify_tx.clone(),
     );
     let orchestrator = orchestrator.start(
         dbh,
-        args.pending_screenshots,
-        args.pending_saves,
-        args.keep_saves,
-        extra_directory,
+        pending_screenshots,
+        pending_saves,
+        keep_saves,
+        pending_extra_directory,
+        provisional_directory,
         latest_screenshot,
-        args.trim_game_prefix,
+        trim_game_prefix,
+        pause_policy,
+        duplicate_saves,
+        config.retention,
+        disk_thresholds,
+        failed_directory_name,
         intake_tx,
         screenshots_tx,
         saves_tx,
@@ -137,23 +264,34 @@ async fn main() -> Result<()> {
     let intake = intake.start(
         orchestrator_tx.clone(),
         notify_tx.clone(),
-        args.intake_url,
+        intake_backend,
+        intake_service,
         is_online,
+        intake_retry,
+        request_timeout,
     );
     let screenshots = screenshots.start(
         orchestrator_tx.clone(),
         notify_tx.clone(),
-        args.screenshot_url,
-        args.extra_directory,
+        screenshot_url,
+        extra_directory,
         is_online,
+        screenshots_retry,
+        upload_timeout,
+        failed_screenshots,
+        upload_chunk_size,
     );
     let saves = saves.start(
         orchestrator_tx.clone(),
         notify_tx.clone(),
-        args.save_url,
+        save_url,
         is_online,
+        saves_retry,
+        upload_timeout,
+        failed_saves,
+        upload_chunk_size,
     );
-    let notify = notify.start(args.led_path.clone());
+    let notify = notify.start(led_path.clone());
     let signal = shutdown_signal(orchestrator_tx);
 
     let res = try_join!(
@@ -170,7 +308,7 @@ async fn main() -> Result<()> {
     .map(|_| ());
 
     if let Err(e) = &res {
-        emergency(&format!("fatal error: {e:?}"), &args.led_path, notify_tx).await;
+        emergency(&format!("fatal error: {e:?}"), &led_path, notify_tx).await;
     } else {
         info!("main gracefully shut down");
     }
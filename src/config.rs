@@ -0,0 +1,294 @@
+use crate::{
+    intake::BackendConfig,
+    internal::{channel::RetryPolicy, disk::Thresholds},
+    orchestrator::{DuplicateSaves, PausePolicy},
+    retention::RetentionPolicy,
+};
+use anyhow::{Context, Result, anyhow};
+use clap::Args;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+// Every setting can come from either a command-line flag or the TOML config
+// file (using the field name as the key); flags take precedence
+#[derive(Args, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[arg(long)]
+    pub listen: Option<String>,
+
+    #[arg(long)]
+    pub plays_database: Option<PathBuf>,
+
+    #[arg(long)]
+    pub games_database: Option<PathBuf>,
+
+    #[arg(long)]
+    pub trim_game_prefix: Option<String>,
+
+    #[arg(long)]
+    pub intake_url: Option<String>,
+
+    #[arg(long)]
+    pub intake_batch_url: Option<String>,
+
+    #[arg(long)]
+    pub intake_batch_size: Option<usize>,
+
+    #[arg(long)]
+    pub screenshot_url: Option<String>,
+
+    #[arg(long)]
+    pub save_url: Option<String>,
+
+    #[arg(long)]
+    pub extra_directory: Option<String>,
+
+    #[arg(long, num_args = 1.., value_delimiter = ',')]
+    pub watch_screenshots: Option<Vec<PathBuf>>,
+
+    #[arg(long, num_args = 1.., value_delimiter = ',')]
+    pub watch_saves: Option<Vec<PathBuf>>,
+
+    #[arg(long)]
+    pub pending_screenshots: Option<PathBuf>,
+
+    #[arg(long)]
+    pub pending_saves: Option<PathBuf>,
+
+    #[arg(long)]
+    pub keep_saves: Option<PathBuf>,
+
+    #[arg(long)]
+    pub led_path: Option<PathBuf>,
+
+    #[arg(long)]
+    pub request_timeout_secs: Option<u64>,
+
+    #[arg(long)]
+    pub upload_timeout_secs: Option<u64>,
+
+    #[arg(long)]
+    pub upload_chunk_kib: Option<u64>,
+
+    #[arg(long)]
+    pub disk_warning_mib: Option<u64>,
+
+    #[arg(long)]
+    pub disk_low_mib: Option<u64>,
+
+    #[arg(long)]
+    pub latest_screenshot_name: Option<String>,
+
+    #[arg(long)]
+    pub pending_extra_name: Option<String>,
+
+    #[arg(long)]
+    pub failed_directory_name: Option<String>,
+
+    #[arg(long)]
+    pub provisional_directory_name: Option<String>,
+
+    #[arg(long, value_enum)]
+    pub pause_policy: Option<PausePolicy>,
+
+    #[arg(long, value_enum)]
+    pub duplicate_saves: Option<DuplicateSaves>,
+
+    #[arg(skip)]
+    pub intake_backend: Option<BackendConfig>,
+
+    // Language tags to the intake service's codes for them
+    #[arg(skip)]
+    pub intake_languages: Option<HashMap<String, String>>,
+
+    #[arg(skip)]
+    pub retry: RetryConfig,
+
+    #[arg(skip)]
+    pub retention: Option<RetentionPolicy>,
+}
+
+// Only settable in the config file, e.g. under [retry.screenshots]. A
+// component without its own table uses [retry.default]
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    pub default: Option<RetryPolicy>,
+    pub intake: Option<RetryPolicy>,
+    pub screenshots: Option<RetryPolicy>,
+    pub saves: Option<RetryPolicy>,
+}
+
+impl Config {
+    pub fn from_file(path: &Path) -> Result<Config> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("reading config file {path:?}"))?;
+        toml::from_str(&contents).with_context(|| format!("parsing config file {path:?}"))
+    }
+
+    pub fn merge(self, fallback: Config) -> Config {
+        Config {
+            listen: self.listen.or(fallback.listen),
+            plays_database: self.plays_database.or(fallback.plays_database),
+            games_database: self.games_database.or(fallback.games_database),
+            trim_game_prefix: self.trim_game_prefix.or(fallback.trim_game_prefix),
+            intake_url: self.intake_url.or(fallback.intake_url),
+            intake_batch_url: self.intake_batch_url.or(fallback.intake_batch_url),
+            intake_batch_size: self.intake_batch_size.or(fallback.intake_batch_size),
+            screenshot_url: self.screenshot_url.or(fallback.screenshot_url),
+            save_url: self.save_url.or(fallback.save_url),
+            extra_directory: self.extra_directory.or(fallback.extra_directory),
+            watch_screenshots: self.watch_screenshots.or(fallback.watch_screenshots),
+            watch_saves: self.watch_saves.or(fallback.watch_saves),
+            pending_screenshots: self.pending_screenshots.or(fallback.pending_screenshots),
+            pending_saves: self.pending_saves.or(fallback.pending_saves),
+            keep_saves: self.keep_saves.or(fallback.keep_saves),
+            led_path: self.led_path.or(fallback.led_path),
+            request_timeout_secs: self.request_timeout_secs.or(fallback.request_timeout_secs),
+            upload_timeout_secs: self.upload_timeout_secs.or(fallback.upload_timeout_secs),
+            upload_chunk_kib: self.upload_chunk_kib.or(fallback.upload_chunk_kib),
+            disk_warning_mib: self.disk_warning_mib.or(fallback.disk_warning_mib),
+            disk_low_mib: self.disk_low_mib.or(fallback.disk_low_mib),
+            latest_screenshot_name: self
+                .latest_screenshot_name
+                .or(fallback.latest_screenshot_name),
+            pending_extra_name: self.pending_extra_name.or(fallback.pending_extra_name),
+            failed_directory_name: self
+                .failed_directory_name
+                .or(fallback.failed_directory_name),
+            provisional_directory_name: self
+                .provisional_directory_name
+                .or(fallback.provisional_directory_name),
+            pause_policy: self.pause_policy.or(fallback.pause_policy),
+            duplicate_saves: self.duplicate_saves.or(fallback.duplicate_saves),
+            intake_backend: fallback.intake_backend,
+            intake_languages: fallback.intake_languages,
+            retry: fallback.retry,
+            retention: fallback.retention,
+        }
+    }
+
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_secs(self.request_timeout_secs.unwrap_or(10))
+    }
+
+    pub fn upload_timeout(&self) -> Duration {
+        Duration::from_secs(self.upload_timeout_secs.unwrap_or(30))
+    }
+
+    // Files up to this size are uploaded whole
+    pub fn upload_chunk_size(&self) -> u64 {
+        self.upload_chunk_kib.unwrap_or(1024).max(1) * 1024
+    }
+
+    // Free space below which to warn, and below which to stop keeping saves
+    pub fn disk_thresholds(&self) -> Thresholds {
+        Thresholds {
+            warning_bytes: self.disk_warning_mib.unwrap_or(512) * 1024 * 1024,
+            low_bytes: self.disk_low_mib.unwrap_or(128) * 1024 * 1024,
+        }
+    }
+
+    // Only used with intake_batch_url
+    pub fn intake_batch_size(&self) -> usize {
+        self.intake_batch_size.unwrap_or(50)
+    }
+
+    pub fn retry_policy(&self, policy: &Option<RetryPolicy>) -> RetryPolicy {
+        policy
+            .as_ref()
+            .or(self.retry.default.as_ref())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn latest_screenshot_name(&self) -> &str {
+        self.latest_screenshot_name
+            .as_deref()
+            .unwrap_or("latest.png")
+    }
+
+    pub fn pending_extra_name(&self) -> &str {
+        self.pending_extra_name.as_deref().unwrap_or("extra")
+    }
+
+    // Uploads that fail permanently are moved into this directory under
+    // pending_screenshots or pending_saves
+    pub fn failed_directory_name(&self) -> &str {
+        self.failed_directory_name.as_deref().unwrap_or("failed")
+    }
+
+    // Screenshots of games missing from the games database are held in this
+    // directory under pending_screenshots until the game is added
+    pub fn provisional_directory_name(&self) -> &str {
+        self.provisional_directory_name
+            .as_deref()
+            .unwrap_or("unknown")
+    }
+}
+
+pub fn required<T>(value: Option<T>, key: &str) -> Result<T> {
+    value.ok_or_else(|| {
+        anyhow!(
+            "missing setting `{key}`: pass --{} or set `{key}` in the config file",
+            key.replace('_', "-")
+        )
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let file: Config = toml::from_str(
+            r#"
+            listen = "0.0.0.0:8080"
+            intake_url = "http://file/intake"
+            request_timeout_secs = 20
+            watch_saves = ["/file/saves"]
+
+            [retry.default]
+            max_attempts = 3
+            "#,
+        )
+        .unwrap();
+        let flags = Config {
+            listen: Some("127.0.0.1:9090".to_string()),
+            watch_saves: Some(vec![PathBuf::from("/flag/saves")]),
+            ..Config::default()
+        };
+
+        let config = flags.merge(file);
+        assert_eq!(config.listen.as_deref(), Some("127.0.0.1:9090"));
+        assert_eq!(config.watch_saves, Some(vec![PathBuf::from("/flag/saves")]));
+        assert_eq!(config.intake_url.as_deref(), Some("http://file/intake"));
+        assert_eq!(config.request_timeout(), Duration::from_secs(20));
+        assert_eq!(config.retry_policy(&None).max_attempts, Some(3));
+        assert_eq!(config.upload_timeout(), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_required() {
+        let config = Config {
+            plays_database: Some(PathBuf::from("plays.db")),
+            ..Config::default()
+        };
+        assert_eq!(
+            required(config.plays_database, "plays_database").unwrap(),
+            PathBuf::from("plays.db")
+        );
+
+        let err = required(config.games_database, "games_database").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "missing setting `games_database`: pass --games-database or set `games_database` in the config file"
+        );
+    }
+}
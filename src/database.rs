@@ -1,26 +1,52 @@
 use crate::{
+    export::{PlayRecord, SyncFields, sync_state},
     intake,
-    internal::notifier::Notifier,
-    notify,
-    orchestrator::{Game, Language, Play},
+    internal::{idempotency::new_key, notifier::Notifier},
+    language::Language,
+    migrations, notify,
+    orchestrator::{Game, Play, Upload, UploadKind},
 };
 use anyhow::Result;
 use futures::future::try_join_all;
 use itertools::Itertools;
 use rusqlite::{OptionalExtension, params};
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::{join, sync::mpsc};
 use tokio_rusqlite::Connection;
 use tracing::{error, info};
 
+#[derive(Clone)]
 pub struct Database {
     plays_dbh: Connection,
     games_dbh: Connection,
     notify_tx: mpsc::UnboundedSender<notify::Event>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct FailedUpload {
+    pub id: i64,
+    pub kind: &'static str,
+    pub path: PathBuf,
+    pub failed_path: PathBuf,
+    pub directory: String,
+    pub attempts: u64,
+    pub last_error: Option<String>,
+    pub failed_time: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FailedPlay {
+    pub id: i64,
+    pub game: String,
+    pub start_time: u64,
+    pub end_time: Option<u64>,
+    pub intake_id: Option<String>,
+    pub error: String,
+}
+
 pub async fn connect<P>(
     plays_path: P,
     games_path: P,
@@ -29,8 +55,10 @@ pub async fn connect<P>(
 where
     P: AsRef<std::path::Path> + std::fmt::Debug,
 {
-    let plays_dbh =
-        Connection::open_with_flags(&plays_path, rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE);
+    let plays_dbh = Connection::open_with_flags(
+        &plays_path,
+        rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE | rusqlite::OpenFlags::SQLITE_OPEN_CREATE,
+    );
     let games_dbh =
         Connection::open_with_flags(&games_path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY);
 
@@ -38,6 +66,7 @@ where
 
     let plays_dbh = plays_dbh?;
     let games_dbh = games_dbh?;
+    plays_dbh.call(migrations::migrate).await?;
     info!("Connected to databases (plays {plays_path:?}, games {games_path:?})");
 
     Ok(Database {
@@ -47,6 +76,15 @@ where
     })
 }
 
+// Seconds a play spent paused, not counting a pause that's still going
+const PAUSED_SECS: &str = "(SELECT COALESCE(SUM(COALESCE(pauses.end_time, plays.end_time) - pauses.start_time), 0) FROM pauses WHERE pauses.play = plays.rowid)";
+
+pub fn active_duration(start_time: u64, end_time: u64, paused_secs: u64) -> u64 {
+    end_time
+        .saturating_sub(start_time)
+        .saturating_sub(paused_secs)
+}
+
 async fn save_currently_playing(dbh: Connection, id: Option<i64>) -> Result<()> {
     Ok(dbh
         .call(move |conn| {
@@ -60,27 +98,52 @@ async fn save_currently_playing(dbh: Connection, id: Option<i64>) -> Result<()>
 }
 
 impl Database {
-    pub async fn game_for_path(&self, path: &Path) -> Result<Game> {
+    // A game whose language isn't a valid tag is treated as missing, so its
+    // plays are still recorded, provisionally, until it's fixed
+    pub async fn find_game(&self, path: &Path) -> Result<Option<Game>> {
         let path = PathBuf::from(path);
-        Ok(self
+        let path_param = path.clone();
+        let row = self
             .games_dbh
-            .call(|conn| {
+            .call(move |conn| {
                 let mut stmt = conn.prepare_cached(
                     "SELECT rowid, directory, language, label FROM games WHERE path = ?",
                 )?;
 
-                let path_param = path.clone();
-                Ok(stmt.query_row(params![&path_param.to_str()], |row| {
-                    Ok(Game {
-                        id: row.get(0)?,
-                        path,
-                        directory: row.get(1)?,
-                        language: row.get(2)?,
-                        label: row.get(3)?,
+                Ok(stmt
+                    .query_row(params![&path_param.to_str()], |row| {
+                        Ok((
+                            row.get(0)?,
+                            row.get(1)?,
+                            row.get::<_, String>(2)?,
+                            row.get(3)?,
+                        ))
                     })
-                }))
+                    .optional())
             })
-            .await??)
+            .await??;
+
+        let Some((id, directory, language, label)) = row else {
+            return Ok(None);
+        };
+        let language = match Language::parse(&language) {
+            Ok(language) => language,
+            Err(e) => {
+                self.notify_error(&format!(
+                    "Treating game {path:?} as missing until its language is fixed with `study-sync games set`: {e}"
+                ));
+                return Ok(None);
+            }
+        };
+
+        Ok(Some(Game {
+            id,
+            path,
+            directory,
+            language,
+            label,
+            provisional: false,
+        }))
     }
 
     pub async fn started_playing(&self, game: Game) -> Result<Play> {
@@ -89,12 +152,14 @@ impl Database {
             .unwrap()
             .as_secs();
 
+        let idempotency_key = new_key();
+
         Ok(self
             .plays_dbh
             .call(move |conn| {
                 conn.execute(
-                    "INSERT INTO plays (game, start_time) VALUES (?, ?)",
-                    params![game.path.to_str(), start_time],
+                    "INSERT INTO plays (game, start_time, provisional, idempotency_key) VALUES (?, ?, ?, ?)",
+                    params![game.path.to_str(), start_time, game.provisional, idempotency_key],
                 )?;
                 let id = conn.last_insert_rowid();
                 Ok(Play {
@@ -102,10 +167,16 @@ impl Database {
                     game,
                     start_time,
                     end_time: None,
+                    end_estimated: false,
+                    last_seen: None,
                     intake_id: None,
                     submitted_start: None,
                     submitted_end: None,
                     skipped: false,
+                    revision: 0,
+                    idempotency_key,
+                    paused_secs: 0,
+                    paused_since: None,
                 })
             })
             .await?)
@@ -117,21 +188,105 @@ impl Database {
             .unwrap()
             .as_secs();
 
+        self.end_play(play, end_time, false).await
+    }
+
+    // For a play that was never ended, e.g. because the device lost power
+    pub async fn estimated_end(&self, play: Play, end_time: u64) -> Result<Play> {
+        self.end_play(play, end_time, true).await
+    }
+
+    // A pause still open when the play ends lasts until the end
+    async fn end_play(&self, play: Play, end_time: u64, end_estimated: bool) -> Result<Play> {
         Ok(self
             .plays_dbh
             .call(move |conn| {
-                conn.execute(
-                    "UPDATE plays SET end_time=? WHERE rowid=?",
+                let tx = conn.transaction()?;
+                tx.execute(
+                    "UPDATE plays SET end_time=?, end_estimated=? WHERE rowid=?",
+                    params![end_time, end_estimated, play.id],
+                )?;
+                tx.execute(
+                    "UPDATE pauses SET end_time=? WHERE play=? AND end_time IS NULL",
                     params![end_time, play.id],
                 )?;
+                if end_estimated {
+                    tx.execute("DELETE FROM current WHERE play=?", params![play.id])?;
+                }
+                tx.commit()?;
+
+                let paused_secs = play.paused_secs
+                    + play
+                        .paused_since
+                        .map_or(0, |since| end_time.saturating_sub(since));
                 Ok(Play {
                     end_time: Some(end_time),
+                    end_estimated,
+                    paused_secs,
+                    paused_since: None,
                     ..play
                 })
             })
             .await?)
     }
 
+    pub async fn heartbeat(&self, play_id: i64) -> Result<u64> {
+        let last_seen = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        self.plays_dbh
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE plays SET last_seen=? WHERE rowid=?",
+                    params![last_seen, play_id],
+                )?;
+                Ok(())
+            })
+            .await?;
+
+        Ok(last_seen)
+    }
+
+    pub async fn paused(&self, play_id: i64) -> Result<u64> {
+        let start_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        self.plays_dbh
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO pauses (play, start_time) VALUES (?, ?)",
+                    params![play_id, start_time],
+                )?;
+                Ok(())
+            })
+            .await?;
+
+        Ok(start_time)
+    }
+
+    pub async fn resumed(&self, play_id: i64) -> Result<u64> {
+        let end_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        self.plays_dbh
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE pauses SET end_time=? WHERE play=? AND end_time IS NULL",
+                    params![end_time, play_id],
+                )?;
+                Ok(())
+            })
+            .await?;
+
+        Ok(end_time)
+    }
+
     pub fn detach_save_currently_playing(&self, id: Option<i64>) {
         let db = self.plays_dbh.clone();
 
@@ -149,76 +304,335 @@ impl Database {
     }
 
     pub async fn load_previously_playing(&self) -> Result<Option<Play>> {
-        struct Current {
-            rowid: i64,
+        let rowid: Option<i64> = self
+            .plays_dbh
+            .call(|conn| {
+                Ok(conn
+                    .query_row("SELECT play FROM current", [], |row| row.get(0))
+                    .optional()?)
+            })
+            .await?;
+
+        match rowid {
+            Some(rowid) => self.load_play(rowid).await,
+            None => Ok(None),
+        }
+    }
+
+    // Plays that were never ended: any besides the current play, and the
+    // current play too if it began before the device booted or its heartbeat
+    // stopped before stale_before
+    pub async fn orphaned_plays(
+        &self,
+        booted: Option<u64>,
+        stale_before: u64,
+    ) -> Result<Vec<Play>> {
+        let rowids: Vec<i64> = self
+            .plays_dbh
+            .call(move |conn| {
+                let mut stmt = conn.prepare("SELECT rowid FROM plays WHERE end_time IS NULL AND (rowid IS NOT (SELECT play FROM current) OR start_time < ? OR last_seen < ?) ORDER BY start_time")?;
+                let rowids = stmt
+                    .query_map(params![booted, stale_before], |row| row.get(0))?
+                    .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+                Ok::<_, tokio_rusqlite::Error>(rowids)
+            })
+            .await?;
+
+        let mut plays = Vec::with_capacity(rowids.len());
+        for rowid in rowids {
+            if let Some(play) = self.load_play(rowid).await? {
+                plays.push(play);
+            }
+        }
+        Ok(plays)
+    }
+
+    pub async fn load_play(&self, rowid: i64) -> Result<Option<Play>> {
+        struct Row {
             game_path: String,
             start_time: u64,
             end_time: Option<u64>,
+            end_estimated: bool,
+            last_seen: Option<u64>,
             intake_id: Option<String>,
             submitted_start: Option<u64>,
             submitted_end: Option<u64>,
             skipped: bool,
+            revision: u64,
+            idempotency_key: String,
+            paused_secs: u64,
+            paused_since: Option<u64>,
         }
 
-        let current: Option<Current> = self
+        let row: Option<Row> = self
             .plays_dbh
-            .call(|conn| {
-                let mut stmt = conn.prepare_cached("SELECT rowid, game, start_time, end_time, intake_id, submitted_start, submitted_end, skipped FROM plays WHERE rowid = (SELECT play FROM current)")?;
+            .call(move |conn| {
+                let mut stmt = conn.prepare_cached(&format!("SELECT game, start_time, end_time, end_estimated, last_seen, intake_id, submitted_start, submitted_end, skipped, revision, idempotency_key, {PAUSED_SECS}, (SELECT start_time FROM pauses WHERE play = plays.rowid AND end_time IS NULL) FROM plays WHERE rowid = ?"))?;
 
-                let current = stmt.query_row([], |row| Ok(Current {
-                    rowid: row.get(0)?,
-                    game_path: row.get(1)?,
-                    start_time: row.get(2)?,
-                    end_time: row.get(3)?,
-                    intake_id: row.get(4)?,
-                    submitted_start: row.get(5)?,
-                    submitted_end: row.get(6)?,
-                    skipped: row.get(7)?,
+                let row = stmt.query_row(params![rowid], |row| Ok(Row {
+                    game_path: row.get(0)?,
+                    start_time: row.get(1)?,
+                    end_time: row.get(2)?,
+                    end_estimated: row.get(3)?,
+                    last_seen: row.get(4)?,
+                    intake_id: row.get(5)?,
+                    submitted_start: row.get(6)?,
+                    submitted_end: row.get(7)?,
+                    skipped: row.get(8)?,
+                    revision: row.get(9)?,
+                    idempotency_key: row.get(10)?,
+                    paused_secs: row.get(11)?,
+                    paused_since: row.get(12)?,
                 })).optional()?;
 
-                Ok::<_, tokio_rusqlite::Error>(current)
+                Ok::<_, tokio_rusqlite::Error>(row)
             })
             .await?;
-        let current = match current {
-            Some(c) => c,
+        let row = match row {
+            Some(r) => r,
             None => return Ok(None),
         };
 
-        let game = self
-            .game_for_path(&PathBuf::from(current.game_path))
-            .await?;
+        let game_path = PathBuf::from(row.game_path);
+        let game = match self.find_game(&game_path).await? {
+            Some(game) => game,
+            None => Game::provisional(game_path),
+        };
 
         Ok(Some(Play {
-            id: current.rowid,
+            id: rowid,
             game,
-            start_time: current.start_time,
-            end_time: current.end_time,
-            intake_id: current.intake_id,
-            submitted_start: current.submitted_start,
-            submitted_end: current.submitted_end,
-            skipped: current.skipped,
+            start_time: row.start_time,
+            end_time: row.end_time,
+            end_estimated: row.end_estimated,
+            last_seen: row.last_seen,
+            intake_id: row.intake_id,
+            submitted_start: row.submitted_start,
+            submitted_end: row.submitted_end,
+            skipped: row.skipped,
+            revision: row.revision,
+            idempotency_key: row.idempotency_key,
+            paused_secs: row.paused_secs,
+            paused_since: row.paused_since,
         }))
     }
 
-    pub async fn load_intake_backlog(&self) -> Result<Vec<intake::Event>> {
+    // Pauses are trimmed to the new times. The play stops being provisional
+    // only if its game is in the games database
+    pub async fn adjust_play(
+        &self,
+        play_id: i64,
+        start_time: u64,
+        end_time: u64,
+        game_path: &Path,
+    ) -> Result<()> {
+        let resolved = self.find_game(game_path).await?.is_some();
+        let game_path = game_path.to_str().map(str::to_owned);
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                tx.execute(
+                    "UPDATE plays SET start_time=?, end_time=?, game=?, provisional=CASE WHEN ? THEN 0 ELSE provisional END, revision=revision+1 WHERE rowid=?",
+                    params![start_time, end_time, game_path, resolved, play_id],
+                )?;
+                tx.execute(
+                    "DELETE FROM pauses WHERE play=? AND (end_time <= ? OR start_time >= ?)",
+                    params![play_id, start_time, end_time],
+                )?;
+                tx.execute(
+                    "UPDATE pauses SET start_time=MAX(start_time, ?), end_time=MIN(end_time, ?) WHERE play=?",
+                    params![start_time, end_time, play_id],
+                )?;
+                tx.commit()?;
+                Ok(())
+            })
+            .await?)
+    }
+
+    pub async fn skip_play(&self, play_id: i64) -> Result<()> {
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE plays SET skipped=1, revision=revision+1 WHERE rowid=?",
+                    params![play_id],
+                )?;
+                Ok(())
+            })
+            .await?)
+    }
+
+    // The new play, from `at` to the original end, takes the pauses after
+    // `at`. Returns its id
+    pub async fn split_play(&self, play_id: i64, at: u64) -> Result<i64> {
+        let idempotency_key = new_key();
+
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                tx.execute(
+                    "INSERT INTO plays (game, start_time, end_time, end_estimated, provisional, last_seen, idempotency_key) SELECT game, ?, end_time, end_estimated, provisional, last_seen, ? FROM plays WHERE rowid=?",
+                    params![at, idempotency_key, play_id],
+                )?;
+                let new_id = tx.last_insert_rowid();
+                tx.execute(
+                    "UPDATE plays SET end_time=?, end_estimated=0, revision=revision+1 WHERE rowid=?",
+                    params![at, play_id],
+                )?;
+                tx.execute(
+                    "INSERT INTO pauses (play, start_time, end_time) SELECT ?, ?, end_time FROM pauses WHERE play=? AND start_time < ? AND end_time > ?",
+                    params![new_id, at, play_id, at, at],
+                )?;
+                tx.execute(
+                    "UPDATE pauses SET end_time=? WHERE play=? AND start_time < ? AND end_time > ?",
+                    params![at, play_id, at, at],
+                )?;
+                tx.execute(
+                    "UPDATE pauses SET play=? WHERE play=? AND start_time >= ?",
+                    params![new_id, play_id, at],
+                )?;
+                tx.commit()?;
+                Ok(new_id)
+            })
+            .await?)
+    }
+
+    // Extends the earlier play over the later one, which is skipped. Any gap
+    // between them becomes a pause
+    pub async fn merge_plays(&self, earlier: &Play, later: &Play) -> Result<()> {
+        let (earlier_id, later_id) = (earlier.id, later.id);
+        let gap = earlier
+            .end_time
+            .filter(|&end_time| end_time < later.start_time)
+            .map(|end_time| (end_time, later.start_time));
+        let end_time = earlier.end_time.max(later.end_time);
+
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                let tx = conn.transaction()?;
+                tx.execute(
+                    "UPDATE plays SET end_time=?, revision=revision+1 WHERE rowid=?",
+                    params![end_time, earlier_id],
+                )?;
+                tx.execute(
+                    "UPDATE pauses SET play=? WHERE play=?",
+                    params![earlier_id, later_id],
+                )?;
+                if let Some((start, end)) = gap {
+                    tx.execute(
+                        "INSERT INTO pauses (play, start_time, end_time) VALUES (?, ?, ?)",
+                        params![earlier_id, start, end],
+                    )?;
+                }
+                tx.execute(
+                    "UPDATE plays SET skipped=1, revision=revision+1 WHERE rowid=?",
+                    params![later_id],
+                )?;
+                tx.commit()?;
+                Ok(())
+            })
+            .await?)
+    }
+
+    pub async fn next_play_start(&self, start_time: u64) -> Result<Option<u64>> {
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                Ok(conn.query_row(
+                    "SELECT MIN(start_time) FROM plays WHERE start_time > ?",
+                    params![start_time],
+                    |row| row.get(0),
+                )?)
+            })
+            .await?)
+    }
+
+    // Latest screenshot or save queued for upload for the game in the range
+    pub async fn last_upload_time(
+        &self,
+        game_path: &Path,
+        since: u64,
+        until: u64,
+    ) -> Result<Option<u64>> {
+        let game_path = game_path.to_str().map(str::to_owned);
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                Ok(conn.query_row(
+                    "SELECT MAX(created_time) FROM uploads WHERE game = ? AND created_time BETWEEN ? AND ?",
+                    params![game_path, since, until],
+                    |row| row.get(0),
+                )?)
+            })
+            .await?)
+    }
+
+    // Whether the file at path was uploaded
+    pub async fn upload_done(&self, path: &Path) -> Result<bool> {
+        let path = path.to_str().map(str::to_owned);
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                Ok(conn.query_row(
+                    "SELECT COUNT(*) > 0 FROM uploads WHERE path = ? AND done_time IS NOT NULL",
+                    params![path],
+                    |row| row.get(0),
+                )?)
+            })
+            .await?)
+    }
+
+    // Whether a file of this kind with this digest was uploaded
+    pub async fn digest_uploaded(&self, kind: UploadKind, digest: &str) -> Result<bool> {
+        let digest = digest.to_owned();
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                Ok(conn.query_row(
+                    "SELECT COUNT(*) > 0 FROM uploads WHERE kind = ? AND digest = ? AND done_time IS NOT NULL",
+                    params![kind, digest],
+                    |row| row.get(0),
+                )?)
+            })
+            .await?)
+    }
+
+    pub async fn load_intake_backlog(&self, play_id: Option<i64>) -> Result<Vec<intake::Event>> {
         struct PartialPlay {
             rowid: i64,
             game_path: String,
             start_time: u64,
             end_time: Option<u64>,
             intake_id: Option<String>,
+            submitted_end: Option<u64>,
+            skipped: bool,
+            revision: u64,
+            idempotency_key: String,
+            paused_secs: u64,
+            create_attempted: bool,
         }
 
-        let plays = self.plays_dbh.call(|conn| {
-            let mut stmt = conn.prepare("SELECT rowid, game, start_time, end_time, intake_id FROM plays WHERE submitted_end IS NULL AND skipped = 0")?;
+        // Plays not yet fully submitted, submitted plays edited since, and
+        // skipped plays intake has a record of
+        let plays = self.plays_dbh.call(move |conn| {
+            let mut stmt = conn.prepare(&format!("SELECT rowid, game, start_time, end_time, intake_id, submitted_end, skipped, revision, idempotency_key, {PAUSED_SECS}, create_attempted FROM plays WHERE ((submitted_end IS NULL AND skipped = 0) OR (submitted_end IS NOT NULL AND revision > submitted_revision) OR (skipped = 1 AND intake_id IS NOT NULL AND revision > submitted_revision)) AND failed IS NULL AND provisional = 0 AND (?1 IS NULL OR rowid = ?1)"))?;
 
-            let plays = stmt.query_map([], |row| {
+            let plays = stmt.query_map(params![play_id], |row| {
                 Ok(PartialPlay{
                     rowid: row.get(0)?,
                     game_path: row.get(1)?,
                     start_time: row.get(2)?,
                     end_time: row.get(3)?,
                     intake_id: row.get(4)?,
+                    submitted_end: row.get(5)?,
+                    skipped: row.get(6)?,
+                    revision: row.get(7)?,
+                    idempotency_key: row.get(8)?,
+                    paused_secs: row.get(9)?,
+                    create_attempted: row.get(10)?,
                 })
             })?
             .collect::<Result<Vec<_>, rusqlite::Error>>()?;
@@ -235,12 +649,13 @@ impl Database {
                 .iter()
                 .map(|p| &p.game_path)
                 .unique()
-                .map(|p| self.game_for_path(Path::new(p))),
+                .map(|p| self.find_game(Path::new(p))),
         )
         .await?;
 
         let games: HashMap<String, Game> = games
             .into_iter()
+            .flatten()
             .map(|g| (g.path.to_str().unwrap().to_owned(), g))
             .collect();
 
@@ -266,6 +681,43 @@ impl Database {
                 let game_label = game_label.clone();
 
                 match p {
+                    PartialPlay {
+                        rowid,
+                        intake_id: Some(intake_id),
+                        skipped: true,
+                        revision,
+                        ..
+                    } => Some(intake::Event::Delete {
+                        play_id: rowid,
+                        intake_id,
+                        revision,
+                    }),
+
+                    PartialPlay {
+                        rowid,
+                        start_time,
+                        end_time: Some(end_time),
+                        intake_id: Some(intake_id),
+                        submitted_end: Some(_),
+                        revision,
+                        paused_secs,
+                        ..
+                    } => Some(intake::Event::Correct {
+                        play_id: rowid,
+                        intake_id,
+                        game_label,
+                        language,
+                        start_time,
+                        end_time,
+                        active_duration: active_duration(start_time, end_time, paused_secs),
+                        revision,
+                    }),
+
+                    PartialPlay {
+                        submitted_end: Some(_),
+                        ..
+                    } => None,
+
                     PartialPlay {
                         end_time: None,
                         intake_id: Some(_),
@@ -277,9 +729,13 @@ impl Database {
                         start_time,
                         end_time: None,
                         intake_id: None,
+                        idempotency_key,
+                        create_attempted,
                         ..
                     } => Some(intake::Event::SubmitStarted {
                         play_id: rowid,
+                        idempotency_key,
+                        create_attempted,
                         game_label,
                         language,
                         start_time,
@@ -287,13 +743,21 @@ impl Database {
 
                     PartialPlay {
                         rowid,
+                        start_time,
                         end_time: Some(end_time),
                         intake_id: Some(intake_id),
+                        revision,
+                        paused_secs,
                         ..
                     } => Some(intake::Event::SubmitEnded {
                         play_id: rowid,
                         intake_id,
+                        game_label,
+                        language,
+                        start_time,
                         end_time,
+                        active_duration: active_duration(start_time, end_time, paused_secs),
+                        revision,
                     }),
 
                     PartialPlay {
@@ -301,19 +765,40 @@ impl Database {
                         start_time,
                         end_time: Some(end_time),
                         intake_id: None,
+                        revision,
+                        idempotency_key,
+                        paused_secs,
+                        create_attempted,
                         ..
                     } => Some(intake::Event::SubmitFull {
                         play_id: rowid,
+                        idempotency_key,
+                        create_attempted,
                         game_label,
                         language,
                         start_time,
                         end_time,
+                        active_duration: active_duration(start_time, end_time, paused_secs),
+                        revision,
                     }),
                 }
             })
             .collect())
     }
 
+    pub async fn create_attempted(&self, play_id: i64) -> Result<()> {
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE plays SET create_attempted=1 WHERE rowid=?",
+                    params![play_id],
+                )?;
+                Ok(())
+            })
+            .await?)
+    }
+
     pub async fn initial_intake(
         &self,
         play_id: i64,
@@ -325,7 +810,7 @@ impl Database {
             .plays_dbh
             .call(move |conn| {
                 conn.execute(
-                    "UPDATE plays SET intake_id=?, submitted_start=? WHERE rowid=?",
+                    "UPDATE plays SET intake_id=?, submitted_start=?, failed=NULL WHERE rowid=?",
                     params![intake_id, submitted_start, play_id],
                 )?;
                 Ok(())
@@ -333,13 +818,18 @@ impl Database {
             .await?)
     }
 
-    pub async fn final_intake(&self, play_id: i64, submitted_end: u64) -> Result<()> {
+    pub async fn final_intake(
+        &self,
+        play_id: i64,
+        submitted_end: u64,
+        revision: u64,
+    ) -> Result<()> {
         Ok(self
             .plays_dbh
             .call(move |conn| {
                 conn.execute(
-                    "UPDATE plays SET submitted_end=? WHERE rowid=?",
-                    params![submitted_end, play_id],
+                    "UPDATE plays SET submitted_end=?, submitted_revision=MAX(submitted_revision, ?), failed=NULL WHERE rowid=?",
+                    params![submitted_end, revision, play_id],
                 )?;
                 Ok(())
             })
@@ -352,33 +842,849 @@ impl Database {
         intake_id: &str,
         submitted_start: u64,
         submitted_end: u64,
+        revision: u64,
     ) -> Result<()> {
         let intake_id = intake_id.to_string();
         Ok(self.plays_dbh
             .call(move |conn| {
                 conn.execute(
-                    "UPDATE plays SET intake_id=?, submitted_start=?, submitted_end=? WHERE rowid=?",
-                    params![intake_id, submitted_start, submitted_end, play_id],
+                    "UPDATE plays SET intake_id=?, submitted_start=?, submitted_end=?, submitted_revision=MAX(submitted_revision, ?), failed=NULL WHERE rowid=?",
+                    params![intake_id, submitted_start, submitted_end, revision, play_id],
                 )?;
                 Ok(())
             })
             .await?)
     }
-}
 
-impl rusqlite::types::FromSql for Language {
-    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
-        value.as_str().map(|v| match v {
-            "en" => Language::English,
-            "ja" => Language::Japanese,
-            "can" => Language::Cantonese,
-            _ => Language::Other(v.to_owned()),
-        })
+    pub async fn intake_corrected(&self, play_id: i64, revision: u64) -> Result<()> {
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE plays SET submitted_revision=MAX(submitted_revision, ?), failed=NULL WHERE rowid=?",
+                    params![revision, play_id],
+                )?;
+                Ok(())
+            })
+            .await?)
     }
-}
 
-impl Notifier for Database {
-    fn notify_tx(&self) -> &mpsc::UnboundedSender<notify::Event> {
-        &self.notify_tx
+    pub async fn enqueue_upload(
+        &self,
+        kind: UploadKind,
+        path: &Path,
+        directory: &str,
+        game: Option<&Path>,
+    ) -> Result<Upload> {
+        let created_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let upload = Upload {
+            id: 0,
+            kind,
+            path: path.to_owned(),
+            directory: directory.to_owned(),
+            game: game.map(Path::to_path_buf),
+            attempts: 0,
+            session: None,
+            session_offset: None,
+            digest: None,
+        };
+
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO uploads (kind, path, directory, game, created_time) VALUES (?, ?, ?, ?, ?)",
+                    params![
+                        upload.kind,
+                        upload.path.to_str(),
+                        upload.directory,
+                        upload.game.as_ref().and_then(|g| g.to_str()),
+                        created_time
+                    ],
+                )?;
+                Ok(Upload {
+                    id: conn.last_insert_rowid(),
+                    ..upload
+                })
+            })
+            .await?)
+    }
+
+    pub async fn load_upload_backlog(&self) -> Result<Vec<Upload>> {
+        Ok(self
+            .plays_dbh
+            .call(|conn| {
+                let mut stmt = conn.prepare("SELECT rowid, kind, path, directory, game, attempts, session, session_offset, digest FROM uploads WHERE done_time IS NULL AND failed_time IS NULL ORDER BY created_time, rowid")?;
+
+                let uploads = stmt
+                    .query_map([], |row| {
+                        Ok(Upload {
+                            id: row.get(0)?,
+                            kind: row.get(1)?,
+                            path: PathBuf::from(row.get::<_, String>(2)?),
+                            directory: row.get(3)?,
+                            game: row.get::<_, Option<String>>(4)?.map(PathBuf::from),
+                            attempts: row.get(5)?,
+                            session: row.get(6)?,
+                            session_offset: row.get(7)?,
+                            digest: row.get(8)?,
+                        })
+                    })?
+                    .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+
+                Ok::<_, tokio_rusqlite::Error>(uploads)
+            })
+            .await?)
+    }
+
+    pub async fn pending_upload_paths(&self) -> Result<HashSet<PathBuf>> {
+        Ok(self
+            .plays_dbh
+            .call(|conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT path FROM uploads WHERE done_time IS NULL AND failed_time IS NULL",
+                )?;
+
+                let paths = stmt
+                    .query_map([], |row| Ok(PathBuf::from(row.get::<_, String>(0)?)))?
+                    .collect::<Result<HashSet<_>, rusqlite::Error>>()?;
+
+                Ok::<_, tokio_rusqlite::Error>(paths)
+            })
+            .await?)
+    }
+
+    pub async fn upload_finished(&self, upload_id: i64) -> Result<()> {
+        let done_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE uploads SET done_time=? WHERE rowid=?",
+                    params![done_time, upload_id],
+                )?;
+                Ok(())
+            })
+            .await?)
+    }
+
+    pub async fn upload_failed(&self, upload_id: i64, error: &str) -> Result<()> {
+        let failure_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let error = error.to_owned();
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE uploads SET attempts=attempts+1, last_error=?, first_failure=COALESCE(first_failure, ?) WHERE rowid=?",
+                    params![error, failure_time, upload_id],
+                )?;
+                Ok(())
+            })
+            .await?)
+    }
+
+    pub async fn upload_progress(&self, upload_id: i64, session: &str, offset: u64) -> Result<()> {
+        let session = session.to_owned();
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE uploads SET session=?, session_offset=? WHERE rowid=?",
+                    params![session, offset, upload_id],
+                )?;
+                Ok(())
+            })
+            .await?)
+    }
+
+    pub async fn upload_digest(&self, upload_id: i64, digest: &str) -> Result<()> {
+        let digest = digest.to_owned();
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE uploads SET digest=? WHERE rowid=?",
+                    params![digest, upload_id],
+                )?;
+                Ok(())
+            })
+            .await?)
+    }
+
+    pub async fn upload_dead_lettered(&self, upload_id: i64, failed_path: &Path) -> Result<()> {
+        let failed_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let failed_path = failed_path.to_owned();
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE uploads SET failed_time=?, failed_path=? WHERE rowid=?",
+                    params![failed_time, failed_path.to_str(), upload_id],
+                )?;
+                Ok(())
+            })
+            .await?)
+    }
+
+    pub async fn export_plays(
+        &self,
+        since: Option<u64>,
+        until: Option<u64>,
+    ) -> Result<Vec<PlayRecord>> {
+        struct Row {
+            rowid: i64,
+            game_path: String,
+            start_time: u64,
+            end_time: Option<u64>,
+            end_estimated: bool,
+            intake_id: Option<String>,
+            submitted_start: Option<u64>,
+            submitted_end: Option<u64>,
+            skipped: bool,
+            failed: bool,
+            provisional: bool,
+            paused_secs: u64,
+        }
+
+        let rows = self.plays_dbh.call(move |conn| {
+            let mut stmt = conn.prepare(&format!("SELECT rowid, game, start_time, end_time, end_estimated, intake_id, submitted_start, submitted_end, skipped, failed IS NOT NULL, provisional, {PAUSED_SECS} FROM plays WHERE (?1 IS NULL OR start_time >= ?1) AND (?2 IS NULL OR start_time <= ?2) ORDER BY start_time"))?;
+
+            let rows = stmt.query_map(params![since, until], |row| {
+                Ok(Row {
+                    rowid: row.get(0)?,
+                    game_path: row.get(1)?,
+                    start_time: row.get(2)?,
+                    end_time: row.get(3)?,
+                    end_estimated: row.get(4)?,
+                    intake_id: row.get(5)?,
+                    submitted_start: row.get(6)?,
+                    submitted_end: row.get(7)?,
+                    skipped: row.get(8)?,
+                    failed: row.get(9)?,
+                    provisional: row.get(10)?,
+                    paused_secs: row.get(11)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+
+            Ok::<_, tokio_rusqlite::Error>(rows)
+        }).await?;
+
+        let games = try_join_all(
+            rows.iter()
+                .map(|r| &r.game_path)
+                .unique()
+                .map(|p| self.find_game(Path::new(p))),
+        )
+        .await?;
+
+        let games: HashMap<String, Game> = games
+            .into_iter()
+            .flatten()
+            .map(|g| (g.path.to_str().unwrap().to_owned(), g))
+            .collect();
+
+        Ok(rows
+            .into_iter()
+            .map(|r| {
+                let game = games
+                    .get(&r.game_path)
+                    .cloned()
+                    .unwrap_or_else(|| Game::provisional(PathBuf::from(&r.game_path)));
+                let sync_state = sync_state(&SyncFields {
+                    skipped: r.skipped,
+                    failed: r.failed,
+                    provisional: r.provisional,
+                    submitted_start: r.submitted_start,
+                    submitted_end: r.submitted_end,
+                    intake_id: r.intake_id.as_deref(),
+                });
+
+                PlayRecord {
+                    id: r.rowid,
+                    game: r.game_path,
+                    label: game.label,
+                    language: game.language.code().to_owned(),
+                    start_time: r.start_time,
+                    end_time: r.end_time,
+                    end_estimated: r.end_estimated,
+                    duration: r.end_time.map(|end| end.saturating_sub(r.start_time)),
+                    active_duration: r
+                        .end_time
+                        .map(|end| active_duration(r.start_time, end, r.paused_secs)),
+                    intake_id: r.intake_id,
+                    sync_state,
+                }
+            })
+            .collect())
+    }
+
+    pub async fn provisional_games(&self) -> Result<Vec<PathBuf>> {
+        Ok(self
+            .plays_dbh
+            .call(|conn| {
+                let mut stmt =
+                    conn.prepare("SELECT DISTINCT game FROM plays WHERE provisional = 1")?;
+                let games = stmt
+                    .query_map([], |row| row.get::<_, String>(0))?
+                    .map(|r| r.map(PathBuf::from))
+                    .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+                Ok(games)
+            })
+            .await?)
+    }
+
+    // Returns the plays that were waiting on the game
+    pub async fn game_resolved(&self, path: &Path) -> Result<Vec<i64>> {
+        let path = path.to_str().map(str::to_owned);
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                let mut stmt = conn.prepare(
+                    "UPDATE plays SET provisional = 0 WHERE game = ? AND provisional = 1 RETURNING rowid",
+                )?;
+                let plays = stmt
+                    .query_map(params![path], |row| row.get(0))?
+                    .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+                Ok(plays)
+            })
+            .await?)
+    }
+
+    pub async fn failed_uploads(&self) -> Result<Vec<FailedUpload>> {
+        Ok(self
+            .plays_dbh
+            .call(|conn| {
+                let mut stmt = conn.prepare("SELECT rowid, kind, path, failed_path, directory, attempts, last_error, failed_time FROM uploads WHERE failed_time IS NOT NULL AND done_time IS NULL ORDER BY failed_time")?;
+
+                let uploads = stmt
+                    .query_map([], |row| {
+                        Ok(FailedUpload {
+                            id: row.get(0)?,
+                            kind: row.get::<_, UploadKind>(1)?.as_str(),
+                            path: PathBuf::from(row.get::<_, String>(2)?),
+                            failed_path: PathBuf::from(row.get::<_, String>(3)?),
+                            directory: row.get(4)?,
+                            attempts: row.get(5)?,
+                            last_error: row.get(6)?,
+                            failed_time: row.get(7)?,
+                        })
+                    })?
+                    .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+
+                Ok::<_, tokio_rusqlite::Error>(uploads)
+            })
+            .await?)
+    }
+
+    pub async fn load_failed_upload(&self, upload_id: i64) -> Result<Option<(Upload, PathBuf)>> {
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                let mut stmt = conn.prepare("SELECT kind, path, directory, game, attempts, failed_path, session, session_offset, digest FROM uploads WHERE rowid = ? AND failed_time IS NOT NULL AND done_time IS NULL")?;
+
+                let upload = stmt
+                    .query_row(params![upload_id], |row| {
+                        let upload = Upload {
+                            id: upload_id,
+                            kind: row.get(0)?,
+                            path: PathBuf::from(row.get::<_, String>(1)?),
+                            directory: row.get(2)?,
+                            game: row.get::<_, Option<String>>(3)?.map(PathBuf::from),
+                            attempts: row.get(4)?,
+                            session: row.get(6)?,
+                            session_offset: row.get(7)?,
+                            digest: row.get(8)?,
+                        };
+                        Ok((upload, PathBuf::from(row.get::<_, String>(5)?)))
+                    })
+                    .optional()?;
+
+                Ok::<_, tokio_rusqlite::Error>(upload)
+            })
+            .await?)
+    }
+
+    pub async fn upload_requeued(&self, upload_id: i64) -> Result<()> {
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE uploads SET failed_time=NULL, failed_path=NULL WHERE rowid=?",
+                    params![upload_id],
+                )?;
+                Ok(())
+            })
+            .await?)
+    }
+
+    pub async fn intake_failed(&self, play_id: i64, error: &str) -> Result<()> {
+        let error = error.to_owned();
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE plays SET failed=? WHERE rowid=?",
+                    params![error, play_id],
+                )?;
+                Ok(())
+            })
+            .await?)
+    }
+
+    pub async fn failed_plays(&self) -> Result<Vec<FailedPlay>> {
+        Ok(self
+            .plays_dbh
+            .call(|conn| {
+                let mut stmt = conn.prepare("SELECT rowid, game, start_time, end_time, intake_id, failed FROM plays WHERE failed IS NOT NULL ORDER BY start_time")?;
+
+                let plays = stmt
+                    .query_map([], |row| {
+                        Ok(FailedPlay {
+                            id: row.get(0)?,
+                            game: row.get(1)?,
+                            start_time: row.get(2)?,
+                            end_time: row.get(3)?,
+                            intake_id: row.get(4)?,
+                            error: row.get(5)?,
+                        })
+                    })?
+                    .collect::<Result<Vec<_>, rusqlite::Error>>()?;
+
+                Ok::<_, tokio_rusqlite::Error>(plays)
+            })
+            .await?)
+    }
+
+    pub async fn play_requeued(&self, play_id: i64) -> Result<()> {
+        Ok(self
+            .plays_dbh
+            .call(move |conn| {
+                conn.execute(
+                    "UPDATE plays SET failed=NULL WHERE rowid=?",
+                    params![play_id],
+                )?;
+                Ok(())
+            })
+            .await?)
+    }
+}
+
+impl UploadKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            UploadKind::Screenshot => "screenshot",
+            UploadKind::Extra => "extra",
+            UploadKind::Save => "save",
+            UploadKind::SaveScreenshot => "save-screenshot",
+        }
+    }
+}
+
+impl rusqlite::types::ToSql for UploadKind {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+impl rusqlite::types::FromSql for UploadKind {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        value.as_str().and_then(|v| match v {
+            "screenshot" => Ok(UploadKind::Screenshot),
+            "extra" => Ok(UploadKind::Extra),
+            "save" => Ok(UploadKind::Save),
+            "save-screenshot" => Ok(UploadKind::SaveScreenshot),
+            _ => Err(rusqlite::types::FromSqlError::Other(
+                format!("unknown upload kind {v:?}").into(),
+            )),
+        })
+    }
+}
+
+impl Notifier for Database {
+    fn notify_tx(&self) -> &mpsc::UnboundedSender<notify::Event> {
+        &self.notify_tx
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    // A migrated plays database and a games database with the given (path,
+    // directory, label) games, both in memory
+    pub async fn open_in_memory(
+        games: &[(&str, &str, &str)],
+        notify_tx: mpsc::UnboundedSender<notify::Event>,
+    ) -> Database {
+        let plays_dbh = Connection::open_in_memory().await.unwrap();
+        plays_dbh.call(migrations::migrate).await.unwrap();
+
+        let games_dbh = Connection::open_in_memory().await.unwrap();
+        let games: Vec<_> = games
+            .iter()
+            .map(|(p, d, l)| (p.to_string(), d.to_string(), l.to_string()))
+            .collect();
+        games_dbh
+            .call(move |conn| {
+                conn.execute_batch(
+                    "CREATE TABLE games (path TEXT, directory TEXT, language TEXT, label TEXT)",
+                )?;
+                for (path, directory, label) in games {
+                    conn.execute(
+                        "INSERT INTO games (path, directory, language, label) VALUES (?, ?, 'en', ?)",
+                        params![path, directory, label],
+                    )?;
+                }
+                Ok(())
+            })
+            .await
+            .unwrap();
+
+        Database {
+            plays_dbh,
+            games_dbh,
+            notify_tx,
+        }
+    }
+
+    // For setting up times that would otherwise come from the clock
+    pub async fn execute(database: &Database, sql: String) {
+        database
+            .plays_dbh
+            .call(move |conn| Ok(conn.execute_batch(&sql)?))
+            .await
+            .unwrap();
+    }
+
+    pub async fn execute_games(database: &Database, sql: String) {
+        database
+            .games_dbh
+            .call(move |conn| Ok(conn.execute_batch(&sql)?))
+            .await
+            .unwrap();
+    }
+
+    pub async fn query(database: &Database, sql: String) -> i64 {
+        database
+            .plays_dbh
+            .call(move |conn| Ok(conn.query_row(&sql, [], |row| row.get(0))?))
+            .await
+            .unwrap()
+    }
+
+    async fn started(database: &Database, path: &str, start_time: u64) -> Play {
+        let path = Path::new(path);
+        let game = match database.find_game(path).await.unwrap() {
+            Some(game) => game,
+            None => Game::provisional(path.to_path_buf()),
+        };
+        let play = database.started_playing(game).await.unwrap();
+        execute(
+            database,
+            format!(
+                "UPDATE plays SET start_time={start_time} WHERE rowid={}",
+                play.id
+            ),
+        )
+        .await;
+        database.load_play(play.id).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pauses() {
+        let (notify_tx, _notify_rx) = mpsc::unbounded_channel();
+        let database = open_in_memory(&[("gb/A.gb", "a", "A")], notify_tx).await;
+
+        let play = started(&database, "gb/A.gb", 1000).await;
+        execute(
+            &database,
+            format!(
+                "INSERT INTO pauses (play, start_time, end_time) VALUES ({id}, 1100, 1200);
+                 INSERT INTO pauses (play, start_time) VALUES ({id}, 1500);",
+                id = play.id
+            ),
+        )
+        .await;
+
+        let play = database.load_play(play.id).await.unwrap().unwrap();
+        assert_eq!(play.paused_secs, 100);
+        assert_eq!(play.paused_since, Some(1500));
+
+        // The open pause lasts until the end
+        let play = database.estimated_end(play, 1600).await.unwrap();
+        assert_eq!(play.paused_secs, 200);
+        assert_eq!(play.active_duration(), Some(400));
+
+        let play = database.load_play(play.id).await.unwrap().unwrap();
+        assert_eq!(play.paused_secs, 200);
+        assert_eq!(play.paused_since, None);
+        assert_eq!(play.active_duration(), Some(400));
+    }
+
+    #[tokio::test]
+    async fn test_adjust_play() {
+        let (notify_tx, _notify_rx) = mpsc::unbounded_channel();
+        let database = open_in_memory(&[("gb/A.gb", "a", "A")], notify_tx).await;
+
+        let play = started(&database, "gb/Unknown.gb", 1000).await;
+        let play = database.estimated_end(play, 2000).await.unwrap();
+        execute(
+            &database,
+            format!(
+                "INSERT INTO pauses (play, start_time, end_time) VALUES ({id}, 1100, 1200);
+                 INSERT INTO pauses (play, start_time, end_time) VALUES ({id}, 1400, 1600);",
+                id = play.id
+            ),
+        )
+        .await;
+        let provisional = || {
+            query(
+                &database,
+                format!("SELECT provisional FROM plays WHERE rowid={}", play.id),
+            )
+        };
+        assert_eq!(provisional().await, 1);
+
+        // Still of a game that isn't known
+        database
+            .adjust_play(play.id, 1500, 1900, Path::new("gb/Unknown.gb"))
+            .await
+            .unwrap();
+        assert_eq!(provisional().await, 1);
+        let adjusted = database.load_play(play.id).await.unwrap().unwrap();
+        assert_eq!((adjusted.start_time, adjusted.end_time), (1500, Some(1900)));
+        assert_eq!(adjusted.paused_secs, 100);
+        assert_eq!(adjusted.revision, 1);
+
+        database
+            .adjust_play(play.id, 1500, 1900, Path::new("gb/A.gb"))
+            .await
+            .unwrap();
+        assert_eq!(provisional().await, 0);
+        let adjusted = database.load_play(play.id).await.unwrap().unwrap();
+        assert_eq!(adjusted.game.label, "A");
+        assert_eq!(adjusted.revision, 2);
+    }
+
+    #[tokio::test]
+    async fn test_split_play() {
+        let (notify_tx, _notify_rx) = mpsc::unbounded_channel();
+        let database = open_in_memory(&[("gb/A.gb", "a", "A")], notify_tx).await;
+
+        let play = started(&database, "gb/A.gb", 1000).await;
+        let play = database.estimated_end(play, 2000).await.unwrap();
+        execute(
+            &database,
+            format!(
+                "INSERT INTO pauses (play, start_time, end_time) VALUES ({id}, 1100, 1200);
+                 INSERT INTO pauses (play, start_time, end_time) VALUES ({id}, 1400, 1600);
+                 INSERT INTO pauses (play, start_time, end_time) VALUES ({id}, 1700, 1800);",
+                id = play.id
+            ),
+        )
+        .await;
+
+        let new_id = database.split_play(play.id, 1500).await.unwrap();
+
+        let earlier = database.load_play(play.id).await.unwrap().unwrap();
+        assert_eq!((earlier.start_time, earlier.end_time), (1000, Some(1500)));
+        assert!(!earlier.end_estimated);
+        assert_eq!(earlier.paused_secs, 200);
+        assert_eq!(earlier.revision, 1);
+
+        let later = database.load_play(new_id).await.unwrap().unwrap();
+        assert_eq!((later.start_time, later.end_time), (1500, Some(2000)));
+        assert_eq!(later.paused_secs, 200);
+        assert_eq!(later.revision, 0);
+        assert_ne!(later.idempotency_key, earlier.idempotency_key);
+    }
+
+    #[tokio::test]
+    async fn test_merge_submitted_plays() {
+        let (notify_tx, _notify_rx) = mpsc::unbounded_channel();
+        let database = open_in_memory(&[("gb/A.gb", "a", "A")], notify_tx).await;
+
+        let earlier = started(&database, "gb/A.gb", 1000).await;
+        let earlier = database.estimated_end(earlier, 2000).await.unwrap();
+        database
+            .full_intake(earlier.id, "i1", 1000, 2000, 0)
+            .await
+            .unwrap();
+        let later = started(&database, "gb/A.gb", 2500).await;
+        let later = database.estimated_end(later, 3000).await.unwrap();
+        database
+            .full_intake(later.id, "i2", 2500, 3000, 0)
+            .await
+            .unwrap();
+        assert!(database.load_intake_backlog(None).await.unwrap().is_empty());
+
+        database.merge_plays(&earlier, &later).await.unwrap();
+
+        let merged = database.load_play(earlier.id).await.unwrap().unwrap();
+        assert_eq!((merged.start_time, merged.end_time), (1000, Some(3000)));
+        assert_eq!(merged.paused_secs, 500);
+
+        let backlog = database.load_intake_backlog(None).await.unwrap();
+        assert_eq!(backlog.len(), 2);
+        assert!(matches!(
+            &backlog[0],
+            intake::Event::Correct { intake_id, end_time: 3000, active_duration: 1500, revision: 1, .. }
+                if intake_id == "i1"
+        ));
+        assert!(matches!(
+            &backlog[1],
+            intake::Event::Delete { intake_id, revision: 1, .. } if intake_id == "i2"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_create_attempted() {
+        let (notify_tx, _notify_rx) = mpsc::unbounded_channel();
+        let database = open_in_memory(&[("gb/A.gb", "a", "A")], notify_tx).await;
+        let play = started(&database, "gb/A.gb", 1000).await;
+
+        let attempted = async || match &database.load_intake_backlog(None).await.unwrap()[..] {
+            [
+                intake::Event::SubmitStarted {
+                    create_attempted, ..
+                },
+            ] => *create_attempted,
+            backlog => panic!("expected A to start, got {backlog:?}"),
+        };
+        assert!(!attempted().await);
+        database.create_attempted(play.id).await.unwrap();
+        assert!(attempted().await);
+    }
+
+    #[tokio::test]
+    async fn test_upload_queue() {
+        let (notify_tx, _notify_rx) = mpsc::unbounded_channel();
+        let database = open_in_memory(&[("gb/A.gb", "a", "A")], notify_tx).await;
+
+        let screenshot = database
+            .enqueue_upload(
+                UploadKind::Screenshot,
+                Path::new("/pending/a/1.png"),
+                "a",
+                Some(Path::new("gb/A.gb")),
+            )
+            .await
+            .unwrap();
+        let save = database
+            .enqueue_upload(UploadKind::Save, Path::new("/saves/a/1.srm"), "a", None)
+            .await
+            .unwrap();
+        let backlog = || async {
+            database
+                .load_upload_backlog()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|u| (u.id, u.attempts))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(backlog().await, [(screenshot.id, 0), (save.id, 0)]);
+
+        // Failing counts an attempt but keeps it queued
+        database
+            .upload_failed(screenshot.id, "timed out")
+            .await
+            .unwrap();
+        assert_eq!(backlog().await, [(screenshot.id, 1), (save.id, 0)]);
+
+        database.upload_digest(save.id, "abc").await.unwrap();
+        database.upload_finished(save.id).await.unwrap();
+        assert_eq!(backlog().await, [(screenshot.id, 1)]);
+        assert!(database.upload_done(&save.path).await.unwrap());
+        assert!(
+            database
+                .digest_uploaded(UploadKind::Save, "abc")
+                .await
+                .unwrap()
+        );
+        assert!(
+            !database
+                .digest_uploaded(UploadKind::Screenshot, "abc")
+                .await
+                .unwrap()
+        );
+
+        let failed_path = Path::new("/pending/failed/a/1.png");
+        database
+            .upload_dead_lettered(screenshot.id, failed_path)
+            .await
+            .unwrap();
+        assert!(backlog().await.is_empty());
+        assert!(database.pending_upload_paths().await.unwrap().is_empty());
+        let failed = database.failed_uploads().await.unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].id, screenshot.id);
+        assert_eq!(failed[0].failed_path, failed_path);
+        assert_eq!(failed[0].attempts, 1);
+        assert_eq!(failed[0].last_error.as_deref(), Some("timed out"));
+        let (upload, path) = database
+            .load_failed_upload(screenshot.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (upload.path, path),
+            (screenshot.path, failed_path.to_owned())
+        );
+
+        database.upload_requeued(screenshot.id).await.unwrap();
+        assert!(database.failed_uploads().await.unwrap().is_empty());
+        assert_eq!(backlog().await, [(screenshot.id, 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_skip_play() {
+        let (notify_tx, _notify_rx) = mpsc::unbounded_channel();
+        let database = open_in_memory(&[("gb/A.gb", "a", "A")], notify_tx).await;
+
+        // Never submitted, so there's nothing to delete
+        let unsubmitted = started(&database, "gb/A.gb", 1000).await;
+        let unsubmitted = database.estimated_end(unsubmitted, 2000).await.unwrap();
+        let backlog = database.load_intake_backlog(None).await.unwrap();
+        assert!(matches!(
+            backlog[..],
+            [intake::Event::SubmitFull { play_id, .. }] if play_id == unsubmitted.id
+        ));
+        database.skip_play(unsubmitted.id).await.unwrap();
+        assert!(database.load_intake_backlog(None).await.unwrap().is_empty());
+
+        // Only the start was submitted
+        let started = started(&database, "gb/A.gb", 3000).await;
+        database
+            .initial_intake(started.id, "i1", 3000)
+            .await
+            .unwrap();
+        let started = database.estimated_end(started, 4000).await.unwrap();
+        database.skip_play(started.id).await.unwrap();
+        let backlog = database.load_intake_backlog(None).await.unwrap();
+        assert!(matches!(
+            &backlog[..],
+            [intake::Event::Delete { intake_id, revision: 1, .. }] if intake_id == "i1"
+        ));
+
+        database.intake_corrected(started.id, 1).await.unwrap();
+        assert!(database.load_intake_backlog(None).await.unwrap().is_empty());
     }
 }
@@ -0,0 +1,285 @@
+use crate::database::Database;
+use anyhow::{Context, Result, anyhow};
+use chrono::prelude::*;
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Csv,
+    Jsonl,
+}
+
+#[derive(Args, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Filter {
+    /// Only plays that started on or after this date (YYYY-MM-DD) or Unix time
+    #[arg(long)]
+    pub since: Option<String>,
+
+    /// Only plays that started on or before this date (YYYY-MM-DD) or Unix time
+    #[arg(long)]
+    pub until: Option<String>,
+
+    /// Only plays of the game with this path or label
+    #[arg(long)]
+    pub game: Option<String>,
+
+    /// Only the most recent of the matching plays
+    #[arg(long)]
+    #[serde(deserialize_with = "deserialize_limit")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlayRecord {
+    pub id: i64,
+    pub game: String,
+    pub label: String,
+    pub language: String,
+    pub start_time: u64,
+    pub end_time: Option<u64>,
+    pub end_estimated: bool,
+    pub duration: Option<u64>,
+    // Duration less any time spent paused
+    pub active_duration: Option<u64>,
+    pub intake_id: Option<String>,
+    pub sync_state: &'static str,
+}
+
+pub struct SyncFields<'a> {
+    pub skipped: bool,
+    pub failed: bool,
+    pub provisional: bool,
+    pub submitted_start: Option<u64>,
+    pub submitted_end: Option<u64>,
+    pub intake_id: Option<&'a str>,
+}
+
+pub fn sync_state(fields: &SyncFields) -> &'static str {
+    if fields.skipped {
+        "skipped"
+    } else if fields.failed {
+        "failed"
+    } else if fields.provisional {
+        "provisional"
+    } else if fields.submitted_end.is_some() {
+        "synced"
+    } else if fields.submitted_start.is_some() || fields.intake_id.is_some() {
+        "started"
+    } else {
+        "pending"
+    }
+}
+
+// Query parameters flattened into Filter all arrive as strings
+fn deserialize_limit<'de, D>(deserializer: D) -> Result<Option<usize>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|limit| limit.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+impl Format {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv",
+            Format::Jsonl => "application/x-ndjson",
+        }
+    }
+}
+
+impl Filter {
+    pub fn range(&self) -> Result<(Option<u64>, Option<u64>)> {
+        let since = self
+            .since
+            .as_deref()
+            .map(|s| parse_time(s, false))
+            .transpose()?;
+        let until = self
+            .until
+            .as_deref()
+            .map(|s| parse_time(s, true))
+            .transpose()?;
+        Ok((since, until))
+    }
+}
+
+pub async fn records(database: &Database, filter: &Filter) -> Result<Vec<PlayRecord>> {
+    let (since, until) = filter.range()?;
+
+    let mut records: Vec<_> = database
+        .export_plays(since, until)
+        .await?
+        .into_iter()
+        .filter(|r| {
+            filter
+                .game
+                .as_ref()
+                .is_none_or(|g| &r.game == g || r.label.eq_ignore_ascii_case(g))
+        })
+        .collect();
+
+    if let Some(limit) = filter.limit {
+        records.drain(..records.len().saturating_sub(limit));
+    }
+
+    Ok(records)
+}
+
+pub async fn export(database: &Database, format: Format, filter: &Filter) -> Result<Vec<u8>> {
+    let records = records(database, filter).await?;
+    render(records.into_iter(), format)
+}
+
+fn render(records: impl Iterator<Item = PlayRecord>, format: Format) -> Result<Vec<u8>> {
+    match format {
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(vec![]);
+            for record in records {
+                writer.serialize(record)?;
+            }
+            Ok(writer.into_inner()?)
+        }
+        Format::Jsonl => {
+            let mut out = vec![];
+            for record in records {
+                serde_json::to_writer(&mut out, &record)?;
+                out.push(b'\n');
+            }
+            Ok(out)
+        }
+    }
+}
+
+// Accepts Unix time or a local date. A date used as the end of a range
+// includes that whole day
+fn parse_time(value: &str, end_of_range: bool) -> Result<u64> {
+    if let Ok(secs) = value.parse() {
+        return Ok(secs);
+    }
+
+    let mut date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .with_context(|| format!("{value:?} is neither YYYY-MM-DD nor Unix time"))?;
+    if end_of_range {
+        date = date
+            .succ_opt()
+            .ok_or_else(|| anyhow!("{value:?} is out of range"))?;
+    }
+
+    let time = date
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_local_timezone(Local)
+        .earliest()
+        .ok_or_else(|| anyhow!("{value:?} has no local midnight"))?;
+    let secs = time.timestamp().try_into()?;
+
+    Ok(if end_of_range { secs - 1 } else { secs })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn fields() -> SyncFields<'static> {
+        SyncFields {
+            skipped: false,
+            failed: false,
+            provisional: false,
+            submitted_start: None,
+            submitted_end: None,
+            intake_id: None,
+        }
+    }
+
+    #[test]
+    fn test_sync_state() {
+        assert_eq!(sync_state(&fields()), "pending");
+        assert_eq!(
+            sync_state(&SyncFields {
+                intake_id: Some("i1"),
+                submitted_start: Some(5),
+                ..fields()
+            }),
+            "started"
+        );
+        assert_eq!(
+            sync_state(&SyncFields {
+                submitted_start: Some(5),
+                submitted_end: Some(10),
+                ..fields()
+            }),
+            "synced"
+        );
+        assert_eq!(
+            sync_state(&SyncFields {
+                provisional: true,
+                ..fields()
+            }),
+            "provisional"
+        );
+        assert_eq!(
+            sync_state(&SyncFields {
+                failed: true,
+                submitted_start: Some(5),
+                ..fields()
+            }),
+            "failed"
+        );
+        assert_eq!(
+            sync_state(&SyncFields {
+                skipped: true,
+                failed: true,
+                ..fields()
+            }),
+            "skipped"
+        );
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("1700000000", false).unwrap(), 1700000000);
+        assert_eq!(parse_time("1700000000", true).unwrap(), 1700000000);
+
+        let start = parse_time("2024-03-01", false).unwrap();
+        let end = parse_time("2024-03-01", true).unwrap();
+        assert_eq!(end - start, 24 * 60 * 60 - 1);
+
+        assert!(parse_time("March 1", false).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let record = || PlayRecord {
+            id: 1,
+            game: "gb/Tetris.gb".to_owned(),
+            label: "Tetris, DX".to_owned(),
+            language: "ja".to_owned(),
+            start_time: 100,
+            end_time: Some(160),
+            end_estimated: false,
+            duration: Some(60),
+            active_duration: Some(50),
+            intake_id: None,
+            sync_state: "pending",
+        };
+
+        let csv = String::from_utf8(render([record()].into_iter(), Format::Csv).unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "id,game,label,language,start_time,end_time,end_estimated,duration,active_duration,intake_id,sync_state\n1,gb/Tetris.gb,\"Tetris, DX\",ja,100,160,false,60,50,,pending\n"
+        );
+
+        let jsonl =
+            String::from_utf8(render([record()].into_iter(), Format::Jsonl).unwrap()).unwrap();
+        assert_eq!(
+            jsonl,
+            "{\"id\":1,\"game\":\"gb/Tetris.gb\",\"label\":\"Tetris, DX\",\"language\":\"ja\",\"start_time\":100,\"end_time\":160,\"end_estimated\":false,\"duration\":60,\"active_duration\":50,\"intake_id\":null,\"sync_state\":\"pending\"}\n"
+        );
+    }
+}
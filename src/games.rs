@@ -0,0 +1,351 @@
+use crate::{config::required, language::Language};
+use anyhow::{Context, Result, anyhow};
+use clap::Subcommand;
+use regex::Regex;
+use rusqlite::{Connection, OpenFlags, OptionalExtension, params};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+// `path` is relative to trim_game_prefix, matching what RetroArch reports
+// once the prefix is trimmed. `directory` is where screenshots for the game
+// are uploaded; games without one only have their plays tracked
+const SCHEMA: &str = include_str!("games.schema");
+
+const ROM_EXTENSIONS: &[&str] = &[
+    "7z", "a26", "bin", "chd", "cue", "fds", "gb", "gba", "gbc", "gen", "gg", "iso", "lnx", "md",
+    "n64", "nds", "nes", "ngc", "pbp", "pce", "sfc", "smc", "sms", "v64", "ws", "wsc", "z64",
+    "zip",
+];
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Create the games table if it doesn't already exist
+    Init,
+
+    /// Propose games for the ROMs found under each directory
+    Scan {
+        #[arg(long)]
+        language: String,
+
+        /// Comma-separated list of ROM file extensions to include
+        #[arg(long, value_delimiter = ',')]
+        extensions: Option<Vec<String>>,
+
+        /// Add proposed games to the database rather than just listing them
+        #[arg(long)]
+        write: bool,
+
+        #[arg(required = true)]
+        directories: Vec<PathBuf>,
+    },
+
+    /// Add a game, or change the settings of an existing one
+    Set {
+        path: String,
+
+        #[arg(long)]
+        language: Option<String>,
+
+        #[arg(long)]
+        label: Option<String>,
+
+        #[arg(long)]
+        directory: Option<String>,
+    },
+
+    /// List every game
+    List,
+
+    /// List games that have been played but have no entry in the database
+    Unmapped,
+}
+
+#[derive(Debug, PartialEq)]
+struct Proposal {
+    path: String,
+    directory: String,
+    language: String,
+    label: String,
+}
+
+pub fn run(
+    command: Command,
+    games_database: &Path,
+    plays_database: Option<&Path>,
+    trim_game_prefix: Option<&str>,
+) -> Result<()> {
+    match command {
+        Command::Init => {
+            open_for_writing(games_database)?;
+            println!("Initialized {games_database:?}");
+        }
+
+        Command::Scan {
+            language,
+            extensions,
+            write,
+            directories,
+        } => {
+            let language = Language::parse(&language)?;
+            let extensions = extensions
+                .unwrap_or_else(|| ROM_EXTENSIONS.iter().map(|e| e.to_string()).collect());
+            let proposals = scan(&directories, &extensions, language.code(), trim_game_prefix)?;
+
+            let dbh = if write {
+                open_for_writing(games_database)?
+            } else {
+                open_read_only(games_database)?
+            };
+            let known = known_paths(&dbh)?;
+
+            let mut added = 0;
+            for proposal in proposals {
+                if known.contains(&proposal.path) {
+                    continue;
+                }
+
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    proposal.path, proposal.directory, proposal.language, proposal.label
+                );
+
+                if write {
+                    dbh.execute(
+                        "INSERT INTO games (path, directory, language, label) VALUES (?, ?, ?, ?)",
+                        params![
+                            proposal.path,
+                            proposal.directory,
+                            proposal.language,
+                            proposal.label
+                        ],
+                    )?;
+                    added += 1;
+                }
+            }
+
+            if write {
+                println!("Added {added} games");
+            }
+        }
+
+        Command::Set {
+            path,
+            language,
+            label,
+            directory,
+        } => {
+            let language = language
+                .map(|l| Language::parse(&l).map(|l| l.code().to_owned()))
+                .transpose()?;
+            let dbh = open_for_writing(games_database)?;
+            let existing = dbh
+                .query_row(
+                    "SELECT directory, language, label FROM games WHERE path = ?",
+                    params![path],
+                    |row| {
+                        Ok((
+                            row.get::<_, Option<String>>(0)?,
+                            row.get::<_, String>(1)?,
+                            row.get::<_, String>(2)?,
+                        ))
+                    },
+                )
+                .optional()?;
+
+            match existing {
+                Some((old_directory, old_language, old_label)) => {
+                    dbh.execute(
+                        "UPDATE games SET directory = ?, language = ?, label = ? WHERE path = ?",
+                        params![
+                            directory.or(old_directory),
+                            language.unwrap_or(old_language),
+                            label.unwrap_or(old_label),
+                            path
+                        ],
+                    )?;
+                    println!("Updated {path:?}");
+                }
+                None => {
+                    let language =
+                        language.ok_or_else(|| anyhow!("--language is required for a new game"))?;
+                    let label = label.unwrap_or_else(|| derive_label(Path::new(&path)));
+                    let directory =
+                        directory.unwrap_or_else(|| derive_directory(&language, &label));
+                    dbh.execute(
+                        "INSERT INTO games (path, directory, language, label) VALUES (?, ?, ?, ?)",
+                        params![path, directory, language, label],
+                    )?;
+                    println!("Added {path:?}");
+                }
+            }
+        }
+
+        Command::List => {
+            let dbh = open_read_only(games_database)?;
+            let mut stmt =
+                dbh.prepare("SELECT path, directory, language, label FROM games ORDER BY path")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?;
+
+            for row in rows {
+                let (path, directory, language, label) = row?;
+                println!(
+                    "{path}\t{}\t{language}\t{label}",
+                    directory.unwrap_or_default()
+                );
+            }
+        }
+
+        Command::Unmapped => {
+            let plays_database = required(plays_database, "plays_database")?;
+            let games_dbh = open_read_only(games_database)?;
+            let plays_dbh =
+                Connection::open_with_flags(plays_database, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                    .with_context(|| format!("opening plays database {plays_database:?}"))?;
+
+            let known = known_paths(&games_dbh)?;
+            let mut stmt = plays_dbh.prepare(
+                "SELECT game, COUNT(*), MAX(start_time) FROM plays GROUP BY game ORDER BY game",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, u64>(1)?,
+                    row.get::<_, u64>(2)?,
+                ))
+            })?;
+
+            for row in rows {
+                let (game, plays, last_played) = row?;
+                if !known.contains(&game) {
+                    println!("{game}\t{plays} plays\tlast played {last_played}");
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn open_for_writing(path: &Path) -> Result<Connection> {
+    let dbh = Connection::open(path).with_context(|| format!("opening games database {path:?}"))?;
+    dbh.execute_batch(SCHEMA)?;
+    Ok(dbh)
+}
+
+fn open_read_only(path: &Path) -> Result<Connection> {
+    Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("opening games database {path:?}"))
+}
+
+fn known_paths(dbh: &Connection) -> Result<HashSet<String>> {
+    let mut stmt = dbh.prepare("SELECT path FROM games")?;
+    let paths = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(paths)
+}
+
+fn scan(
+    directories: &[PathBuf],
+    extensions: &[String],
+    language: &str,
+    trim_game_prefix: Option<&str>,
+) -> Result<Vec<Proposal>> {
+    let mut proposals = Vec::new();
+
+    for directory in directories {
+        let directory = std::fs::canonicalize(directory)
+            .with_context(|| format!("canonicalizing {directory:?}"))?;
+
+        for path in crate::internal::fs::recursive_files_in(&directory, None) {
+            let is_rom = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| extensions.iter().any(|x| x.eq_ignore_ascii_case(e)));
+            if !is_rom {
+                continue;
+            }
+
+            let relative = match trim_game_prefix {
+                Some(prefix) => path
+                    .strip_prefix(prefix)
+                    .with_context(|| format!("trimming prefix {prefix:?} from {path:?}"))?,
+                None => &path,
+            };
+            let relative = relative
+                .to_str()
+                .ok_or_else(|| anyhow!("{relative:?} is not valid UTF-8"))?
+                .to_owned();
+
+            let label = derive_label(&path);
+            proposals.push(Proposal {
+                path: relative,
+                directory: derive_directory(language, &label),
+                language: language.to_owned(),
+                label,
+            });
+        }
+    }
+
+    Ok(proposals)
+}
+
+// Strips the extension and any No-Intro style tags, so that
+// "Tetris (World) (Rev 1) [!].gb" becomes "Tetris"
+pub fn derive_label(path: &Path) -> String {
+    static TAG_RE: OnceLock<Regex> = OnceLock::new();
+    let tag_re = TAG_RE.get_or_init(|| Regex::new(r"\([^)]*\)|\[[^\]]*\]").unwrap());
+
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy())
+        .unwrap_or_default();
+    let label = tag_re.replace_all(&stem, " ");
+    label.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn derive_directory(language: &str, label: &str) -> String {
+    format!("{language}/{}", label.replace('/', "-"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_derive_label() {
+        assert_eq!(derive_label(Path::new("gb/Tetris.gb")), "Tetris");
+        assert_eq!(
+            derive_label(Path::new("Tetris (World) (Rev 1) [!].gb")),
+            "Tetris"
+        );
+        assert_eq!(
+            derive_label(Path::new("snes/Rockman X (Japan) (Rev 1).sfc")),
+            "Rockman X"
+        );
+        assert_eq!(
+            derive_label(Path::new("Legend of Zelda, The (USA).nes")),
+            "Legend of Zelda, The"
+        );
+        assert_eq!(
+            derive_label(Path::new("Pokemon - Blue.gb")),
+            "Pokemon - Blue"
+        );
+    }
+
+    #[test]
+    fn test_derive_directory() {
+        assert_eq!(derive_directory("ja", "Tetris"), "ja/Tetris");
+        assert_eq!(derive_directory("en", "Yes/No"), "en/Yes-No");
+    }
+}
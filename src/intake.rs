@@ -1,19 +1,28 @@
+mod backend;
+mod jsonl;
+mod mirror;
+mod service;
+mod webhook;
+
+pub use backend::{BackendConfig, ServiceOptions};
+
 use crate::{
+    intake::backend::{Backend, IntakeBackend, Submission},
     internal::{
-        channel::{Action, PriorityRetryChannel},
+        channel::{Action, ChannelStatus, PriorityRetryChannel, RetryPolicy},
+        failure::action_for,
         notifier::Notifier,
         online::Online,
         requester::Requester,
     },
-    notify,
-    orchestrator::{self, Language},
+    language::Language,
+    notify, orchestrator,
 };
-use anyhow::{Result, anyhow};
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::sync::mpsc;
-use tracing::{error, info, warn};
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, watch};
+use tracing::{error, info};
 
 #[derive(Debug)]
 pub enum Event {
@@ -23,6 +32,10 @@ pub enum Event {
     },
     SubmitStarted {
         play_id: i64,
+        idempotency_key: String,
+        // Whether a create was sent for the play before, and so may have
+        // reached the backend
+        create_attempted: bool,
         game_label: String,
         language: Language,
         start_time: u64,
@@ -30,63 +43,101 @@ pub enum Event {
     SubmitEnded {
         play_id: i64,
         intake_id: String,
+        game_label: String,
+        language: Language,
+        start_time: u64,
         end_time: u64,
+        active_duration: u64,
+        revision: u64,
     },
     SubmitFull {
         play_id: i64,
+        idempotency_key: String,
+        create_attempted: bool,
         game_label: String,
         language: Language,
         start_time: u64,
         end_time: u64,
+        active_duration: u64,
+        revision: u64,
+    },
+    // Resends a submitted play after it was edited
+    Correct {
+        play_id: i64,
+        intake_id: String,
+        game_label: String,
+        language: Language,
+        start_time: u64,
+        end_time: u64,
+        active_duration: u64,
+        revision: u64,
+    },
+    // Removes a submitted play after it was skipped
+    Delete {
+        play_id: i64,
+        intake_id: String,
+        revision: u64,
     },
     IsOnline(bool),
     ForceSync,
     StartShutdown,
 }
 
-#[derive(Debug, Deserialize)]
-struct IntakeResponse {
-    message: Option<String>,
-    error: Option<String>,
-    object: Option<IntakeResponseObject>,
-}
-
-#[derive(Debug, Deserialize)]
-struct IntakeResponseObject {
-    id: String,
-}
-
 pub struct IntakePre {
     rx: mpsc::UnboundedReceiver<Event>,
+    status_tx: watch::Sender<ChannelStatus>,
 }
 
 pub struct Intake {
+    status_tx: watch::Sender<ChannelStatus>,
     orchestrator_tx: mpsc::UnboundedSender<orchestrator::Event>,
     notify_tx: mpsc::UnboundedSender<notify::Event>,
-    intake_url: String,
+    backend: Backend,
     play_to_intake: HashMap<i64, String>,
+    // Why each play's last attempt failed, for when it's dead-lettered
+    last_errors: HashMap<i64, String>,
+    // Plays a create has been sent for, which are looked up rather than
+    // batched in case it reached the backend
+    create_attempted: HashSet<i64>,
     is_online: bool,
+    retry_policy: RetryPolicy,
+    request_timeout: Duration,
 }
 
 pub fn prepare() -> (IntakePre, mpsc::UnboundedSender<Event>) {
     let (tx, rx) = mpsc::unbounded_channel();
-    (IntakePre { rx }, tx)
+    let (status_tx, _) = watch::channel(ChannelStatus::default());
+    (IntakePre { rx, status_tx }, tx)
 }
 
 impl IntakePre {
+    #![allow(clippy::too_many_arguments)]
+
+    pub fn status(&self) -> watch::Receiver<ChannelStatus> {
+        self.status_tx.subscribe()
+    }
+
     pub async fn start(
         self,
         orchestrator_tx: mpsc::UnboundedSender<orchestrator::Event>,
         notify_tx: mpsc::UnboundedSender<notify::Event>,
-        intake_url: String,
+        backend: BackendConfig,
+        service: ServiceOptions,
         is_online: bool,
+        retry_policy: RetryPolicy,
+        request_timeout: Duration,
     ) -> Result<()> {
         let intake = Intake {
+            status_tx: self.status_tx,
             orchestrator_tx,
             notify_tx,
-            intake_url,
+            backend: Backend::open(backend, service).await?,
             play_to_intake: HashMap::new(),
+            last_errors: HashMap::new(),
+            create_attempted: HashSet::new(),
             is_online,
+            retry_policy,
+            request_timeout,
         };
         intake.start(self.rx).await
     }
@@ -99,101 +150,186 @@ impl Intake {
         Ok(())
     }
 
-    async fn create_intake(
-        &self,
-        game_label: &str,
-        language: &Language,
-        start_time: u64,
-        end_time: Option<u64>,
-    ) -> Result<(String, u64)> {
-        #[derive(Debug, Serialize)]
-        struct Request<'a> {
-            #[serde(rename = "startTime")]
-            start_time: u64,
-            #[serde(rename = "endTime")]
-            end_time: Option<u64>,
-            #[serde(rename = "game")]
-            game_label: &'a str,
-            language: &'a str,
+    fn failed(&mut self, play_id: i64, error: &anyhow::Error) -> Action {
+        self.last_errors.insert(play_id, format!("{error:#}"));
+        action_for(error)
+    }
+
+    // The idempotency key and submission of an event that creates an intake
+    // record, rather than finishing or correcting one
+    fn creation<'a>(&self, event: &'a Event) -> Option<(&'a str, Submission<'a>)> {
+        match event {
+            Event::SubmitStarted {
+                play_id,
+                idempotency_key,
+                game_label,
+                language,
+                start_time,
+                ..
+            } => Some((
+                idempotency_key,
+                Submission {
+                    play_id: *play_id,
+                    game_label,
+                    language,
+                    start_time: *start_time,
+                    end_time: None,
+                    active_duration: None,
+                },
+            )),
+
+            Event::SubmitFull {
+                play_id,
+                idempotency_key,
+                game_label,
+                language,
+                start_time,
+                end_time,
+                active_duration,
+                ..
+            } if !self.play_to_intake.contains_key(play_id) => Some((
+                idempotency_key,
+                Submission {
+                    play_id: *play_id,
+                    game_label,
+                    language,
+                    start_time: *start_time,
+                    end_time: Some(*end_time),
+                    active_duration: Some(*active_duration),
+                },
+            )),
+
+            _ => None,
         }
+    }
 
-        let request = Request {
-            start_time,
-            end_time,
-            game_label,
-            language: language.intake_str(),
+    // Records that a create is about to be sent for the play, so that it's
+    // never batched after this, even once study-sync restarts
+    fn attempting(&mut self, event: &Event) {
+        let (Event::SubmitStarted {
+            play_id,
+            create_attempted,
+            ..
+        }
+        | Event::SubmitFull {
+            play_id,
+            create_attempted,
+            ..
+        }) = event
+        else {
+            return;
         };
 
-        let (submitted, IntakeResponseObject { id }) =
-            self.submit(reqwest::Method::POST, request).await?;
-        Ok((id, submitted))
+        if !self.create_attempted.insert(*play_id) || *create_attempted {
+            return;
+        }
+        let msg = orchestrator::Event::IntakeAttempted { play_id: *play_id };
+        if let Err(e) = self.orchestrator_tx.send(msg) {
+            self.notify_error(&format!("Could not send to orchestrator: {e:?}"));
+        }
+    }
+
+    async fn create(&mut self, event: &Event) -> Action {
+        let Some((idempotency_key, submission)) = self.creation(event) else {
+            return Action::Continue;
+        };
+
+        self.attempting(event);
+        let submitted = now();
+        match self
+            .backend
+            .create(self, idempotency_key, &submission)
+            .await
+        {
+            Ok(intake_id) => {
+                self.created(event, intake_id, submitted);
+                Action::Continue
+            }
+            Err(e) => {
+                error!("Could not create intake: {e:?}");
+                self.failed(submission.play_id, &e)
+            }
+        }
     }
 
-    async fn finish_intake(&self, intake_id: &str, end_time: u64) -> Result<u64> {
-        #[derive(Debug, Serialize)]
-        struct Request<'a> {
-            id: &'a str,
-            #[serde(rename = "endTime")]
-            end_time: u64,
+    // Submits the end of a play whose start intake already has
+    async fn finish(
+        &mut self,
+        intake_id: &str,
+        submission: &Submission<'_>,
+        revision: u64,
+    ) -> Action {
+        let play_id = submission.play_id;
+        let submitted_end = now();
+        if let Err(e) = self.backend.finish(self, intake_id, submission).await {
+            error!("Could not finish intake: {e:?}");
+            return self.failed(play_id, &e);
         }
 
-        let request = Request {
-            id: intake_id,
-            end_time,
+        self.play_to_intake.remove(&play_id);
+        self.last_errors.remove(&play_id);
+
+        let msg = orchestrator::Event::IntakeEnded {
+            play_id,
+            submitted_end,
+            revision,
         };
+        if let Err(e) = self.orchestrator_tx.send(msg) {
+            self.notify_error(&format!("Could not send to orchestrator: {e:?}"));
+        }
 
-        let (submitted, _) = self.submit(reqwest::Method::PATCH, request).await?;
-        Ok(submitted)
+        Action::Continue
     }
 
-    async fn submit<R>(
-        &self,
-        method: reqwest::Method,
-        request: R,
-    ) -> Result<(u64, IntakeResponseObject)>
-    where
-        R: Serialize + std::fmt::Debug + Send + Sync,
-    {
-        let url = &self.intake_url;
-
-        let submitted = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-
-        match self.request(url, &method, request).await? {
-            IntakeResponse {
-                error: Some(error), ..
-            } => Err(anyhow!("Error {method:?}ing {url:?} from server: {error}")),
-            IntakeResponse {
-                message: Some(message),
-                object: Some(obj),
-                ..
-            } => {
-                info!("Success {method:?}ing {url:?}: {message}");
-                Ok((submitted, obj))
+    fn created(&mut self, event: &Event, intake_id: String, submitted: u64) {
+        if let Event::SubmitStarted { play_id, .. } | Event::SubmitFull { play_id, .. } = event {
+            self.create_attempted.remove(play_id);
+            self.last_errors.remove(play_id);
+        }
+
+        let msg = match event {
+            Event::SubmitStarted { play_id, .. } => {
+                self.play_to_intake.insert(*play_id, intake_id.clone());
+                orchestrator::Event::IntakeStarted {
+                    play_id: *play_id,
+                    intake_id,
+                    submitted_start: submitted,
+                }
             }
-            res => Err(anyhow!(
-                "Error pattern-matching {method:?} {url:?} response: {res:?}"
-            )),
+            Event::SubmitFull {
+                play_id, revision, ..
+            } => orchestrator::Event::IntakeFull {
+                play_id: *play_id,
+                intake_id,
+                submitted_start: submitted,
+                submitted_end: submitted,
+                revision: *revision,
+            },
+            _ => return,
+        };
+
+        if let Err(e) = self.orchestrator_tx.send(msg) {
+            self.notify_error(&format!("Could not send to orchestrator: {e:?}"));
         }
     }
-}
 
-impl Language {
-    fn intake_str(&self) -> &str {
-        match self {
-            Language::English => "en",
-            Language::Japanese => "ja",
-            Language::Cantonese => "can",
-            Language::Other(lang) => {
-                warn!("Mapping intake language {lang} to en (English)");
-                "en"
-            }
+    fn corrected(&mut self, play_id: i64, revision: u64) {
+        self.last_errors.remove(&play_id);
+
+        let msg = orchestrator::Event::IntakeCorrected { play_id, revision };
+        if let Err(e) = self.orchestrator_tx.send(msg) {
+            self.notify_error(&format!("Could not send to orchestrator: {e:?}"));
         }
     }
 }
 
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
 impl Notifier for Intake {
     fn notify_tx(&self) -> &mpsc::UnboundedSender<notify::Event> {
         &self.notify_tx
@@ -217,6 +353,14 @@ impl PriorityRetryChannel for Intake {
         self.is_online
     }
 
+    fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    fn status_tx(&self) -> &watch::Sender<ChannelStatus> {
+        &self.status_tx
+    }
+
     fn is_high_priority(&self, event: &Event) -> bool {
         match event {
             Event::StartShutdown => true,
@@ -227,6 +371,125 @@ impl PriorityRetryChannel for Intake {
             Event::SubmitStarted { .. } => false,
             Event::SubmitEnded { .. } => false,
             Event::SubmitFull { .. } => false,
+            Event::Correct { .. } => false,
+            Event::Delete { .. } => false,
+        }
+    }
+
+    // A play's start must be submitted before its end
+    fn ordering_key(&self, event: &Event) -> Option<i64> {
+        match event {
+            Event::PreviousGame { play_id, .. } => Some(*play_id),
+            Event::SubmitStarted { play_id, .. } => Some(*play_id),
+            Event::SubmitEnded { play_id, .. } => Some(*play_id),
+            Event::SubmitFull { play_id, .. } => Some(*play_id),
+            Event::Correct { play_id, .. } => Some(*play_id),
+            Event::Delete { play_id, .. } => Some(*play_id),
+
+            Event::IsOnline(_) => None,
+            Event::ForceSync => None,
+            Event::StartShutdown => None,
+        }
+    }
+
+    // Only creates are batched, since they're what piles up offline, and
+    // only those never sent, since a batch skips the lookup by idempotency key
+    fn is_batchable(&self, event: &Event) -> bool {
+        if self.backend.batch_size() <= 1 {
+            return false;
+        }
+        match event {
+            Event::SubmitStarted {
+                create_attempted: true,
+                ..
+            }
+            | Event::SubmitFull {
+                create_attempted: true,
+                ..
+            } => false,
+            _ => self
+                .creation(event)
+                .is_some_and(|(_, s)| !self.create_attempted.contains(&s.play_id)),
+        }
+    }
+
+    fn batch_size(&self) -> usize {
+        self.backend.batch_size()
+    }
+
+    async fn handle_batch(&mut self, events: &[&Event]) -> Vec<Action> {
+        let creations: Vec<_> = events.iter().filter_map(|e| self.creation(e)).collect();
+        if creations.len() != events.len() {
+            let mut actions = Vec::with_capacity(events.len());
+            for event in events {
+                actions.push(self.handle(event).await);
+            }
+            return actions;
+        }
+
+        let play_ids: Vec<_> = creations.iter().map(|(_, s)| s.play_id).collect();
+        for event in events {
+            self.attempting(event);
+        }
+        let submitted = now();
+        let results = match self.backend.create_batch(self, &creations).await {
+            Ok(results) => results,
+            Err(e) => {
+                error!("Could not create intakes in batch: {e:?}");
+                return play_ids
+                    .into_iter()
+                    .map(|play_id| self.failed(play_id, &e))
+                    .collect();
+            }
+        };
+
+        let mut actions = Vec::with_capacity(events.len());
+        for ((event, play_id), result) in events.iter().zip(play_ids).zip(results) {
+            actions.push(match result {
+                Ok(intake_id) => {
+                    self.created(event, intake_id, submitted);
+                    Action::Continue
+                }
+                Err(e) => {
+                    error!("Could not create intake for play {play_id}: {e:?}");
+                    self.failed(play_id, &e)
+                }
+            });
+        }
+        actions
+    }
+
+    async fn dead_letter(&mut self, event: Event) {
+        if let Event::Correct { play_id, .. } | Event::Delete { play_id, .. } = event {
+            let error = self
+                .last_errors
+                .remove(&play_id)
+                .unwrap_or_else(|| "unknown error".to_owned());
+            self.notify_error(&format!(
+                "Gave up on correcting intake for play {play_id}: {error}"
+            ));
+            return;
+        }
+
+        let play_id = match event {
+            Event::SubmitStarted { play_id, .. }
+            | Event::SubmitEnded { play_id, .. }
+            | Event::SubmitFull { play_id, .. } => play_id,
+            _ => {
+                error!("Giving up on {event:?}");
+                return;
+            }
+        };
+
+        let error = self
+            .last_errors
+            .remove(&play_id)
+            .unwrap_or_else(|| "unknown error".to_owned());
+        self.notify_error(&format!("Gave up on intake for play {play_id}: {error}"));
+
+        let msg = orchestrator::Event::IntakeDeadLettered { play_id, error };
+        if let Err(e) = self.orchestrator_tx.send(msg) {
+            self.notify_error(&format!("Could not send to orchestrator: {e:?}"));
         }
     }
 
@@ -251,113 +514,102 @@ impl PriorityRetryChannel for Intake {
                 Action::Continue
             }
 
-            Event::SubmitStarted {
+            Event::SubmitStarted { .. } => self.create(event).await,
+
+            Event::SubmitEnded {
                 play_id,
+                intake_id,
                 game_label,
                 language,
                 start_time,
+                end_time,
+                active_duration,
+                revision,
             } => {
-                let (intake_id, submitted_start) = match self
-                    .create_intake(game_label, language, *start_time, None)
-                    .await
-                {
-                    Ok((i, s)) => (i, s),
-                    Err(e) => {
-                        error!("Could not create intake: {e:?}");
-                        return Action::Retry;
-                    }
-                };
-
-                self.play_to_intake.insert(*play_id, intake_id.clone());
-                let msg = orchestrator::Event::IntakeStarted {
+                let submission = Submission {
                     play_id: *play_id,
-                    intake_id,
-                    submitted_start,
+                    game_label,
+                    language,
+                    start_time: *start_time,
+                    end_time: Some(*end_time),
+                    active_duration: Some(*active_duration),
                 };
-                if let Err(e) = self.orchestrator_tx.send(msg) {
-                    self.notify_error(&format!("Could not send to orchestrator: {e:?}"));
-                }
-
-                Action::Continue
+                self.finish(intake_id, &submission, *revision).await
             }
 
-            Event::SubmitEnded {
+            Event::SubmitFull {
                 play_id,
-                intake_id,
+                game_label,
+                language,
+                start_time,
                 end_time,
+                active_duration,
+                revision,
+                ..
             } => {
-                let submitted_end = match self.finish_intake(intake_id, *end_time).await {
-                    Ok(e) => e,
-                    Err(e) => {
-                        error!("Could not finish intake: {e:?}");
-                        return Action::Retry;
-                    }
+                let Some(intake_id) = self.play_to_intake.get(play_id).cloned() else {
+                    return self.create(event).await;
                 };
 
-                self.play_to_intake.remove(play_id);
-
-                let msg = orchestrator::Event::IntakeEnded {
+                let submission = Submission {
                     play_id: *play_id,
-                    submitted_end,
+                    game_label,
+                    language,
+                    start_time: *start_time,
+                    end_time: Some(*end_time),
+                    active_duration: Some(*active_duration),
                 };
-                if let Err(e) = self.orchestrator_tx.send(msg) {
-                    self.notify_error(&format!("Could not send to orchestrator: {e:?}"));
-                }
-
-                Action::Continue
+                self.finish(&intake_id, &submission, *revision).await
             }
 
-            Event::SubmitFull {
+            Event::Correct {
                 play_id,
+                intake_id,
                 game_label,
                 language,
                 start_time,
                 end_time,
+                active_duration,
+                revision,
             } => {
-                let msg;
-                if let Some(intake_id) = self.play_to_intake.get(play_id) {
-                    let submitted_end = match self.finish_intake(intake_id, *end_time).await {
-                        Ok(e) => e,
-                        Err(e) => {
-                            error!("Could not finish intake: {e:?}");
-                            return Action::Retry;
-                        }
-                    };
-
-                    self.play_to_intake.remove(play_id);
-
-                    msg = orchestrator::Event::IntakeEnded {
-                        play_id: *play_id,
-                        submitted_end,
-                    };
-                } else {
-                    let (intake_id, submitted) = match self
-                        .create_intake(game_label, language, *start_time, Some(*end_time))
-                        .await
-                    {
-                        Ok((i, s)) => (i, s),
-                        Err(e) => {
-                            error!("Could not create intake: {e:?}");
-                            return Action::Retry;
-                        }
-                    };
-
-                    msg = orchestrator::Event::IntakeFull {
-                        play_id: *play_id,
-                        intake_id,
-                        submitted_start: submitted,
-                        submitted_end: submitted,
-                    };
+                let submission = Submission {
+                    play_id: *play_id,
+                    game_label,
+                    language,
+                    start_time: *start_time,
+                    end_time: Some(*end_time),
+                    active_duration: Some(*active_duration),
+                };
+                if let Err(e) = self.backend.correct(self, intake_id, &submission).await {
+                    error!("Could not correct intake: {e:?}");
+                    return self.failed(*play_id, &e);
                 }
 
-                if let Err(e) = self.orchestrator_tx.send(msg) {
-                    self.notify_error(&format!("Could not send to orchestrator: {e:?}"));
+                self.corrected(*play_id, *revision);
+                self.notify_success(true, &format!("Corrected intake for play {play_id}"));
+                Action::Continue
+            }
+
+            Event::Delete {
+                play_id,
+                intake_id,
+                revision,
+            } => {
+                if let Err(e) = self.backend.delete(self, intake_id).await {
+                    error!("Could not delete intake: {e:?}");
+                    return self.failed(*play_id, &e);
                 }
 
+                self.corrected(*play_id, *revision);
+                self.notify_success(true, &format!("Deleted intake for play {play_id}"));
                 Action::Continue
             }
         }
     }
 }
 
-impl Requester for Intake {}
+impl Requester for Intake {
+    fn request_timeout(&self) -> Duration {
+        self.request_timeout
+    }
+}
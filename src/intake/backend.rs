@@ -0,0 +1,210 @@
+use crate::{
+    intake::{jsonl::Jsonl, mirror::Mirror, service::Service, webhook::Webhook},
+    internal::requester::Requester,
+    language::Language,
+};
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::{collections::HashMap, future::Future, path::PathBuf};
+
+// Where plays are sent. Set with an [intake_backend] table in the config
+// file; without one, plays go to the intake service at intake_url
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum BackendConfig {
+    Service {
+        url: String,
+    },
+    Webhook {
+        url: String,
+        create: String,
+        finish: Option<String>,
+        correct: Option<String>,
+        delete: Option<String>,
+        // JSON pointer to the record's id in the create response. Without
+        // one, the play's own id is used
+        id_pointer: Option<String>,
+    },
+    Jsonl {
+        path: PathBuf,
+    },
+    Sqlite {
+        path: PathBuf,
+    },
+}
+
+// Settings for the intake service, from the config file's top level so
+// they also apply to intake_url
+#[derive(Debug, Default)]
+pub struct ServiceOptions {
+    pub languages: HashMap<String, String>,
+    pub batch_url: Option<String>,
+    pub batch_size: usize,
+}
+
+// Everything a backend might send about a play
+#[derive(Debug)]
+pub struct Submission<'a> {
+    pub play_id: i64,
+    pub game_label: &'a str,
+    pub language: &'a Language,
+    pub start_time: u64,
+    pub end_time: Option<u64>,
+    pub active_duration: Option<u64>,
+}
+
+// The requester is the intake component itself, for backends that talk to
+// a server and so report whether study-sync is online
+pub trait IntakeBackend {
+    // Returns the id of the new record
+    fn create<R>(
+        &self,
+        requester: &R,
+        idempotency_key: &str,
+        submission: &Submission,
+    ) -> impl Future<Output = Result<String>> + Send
+    where
+        R: Requester + Sync;
+
+    fn finish<R>(
+        &self,
+        requester: &R,
+        intake_id: &str,
+        submission: &Submission,
+    ) -> impl Future<Output = Result<()>> + Send
+    where
+        R: Requester + Sync;
+
+    fn correct<R>(
+        &self,
+        requester: &R,
+        intake_id: &str,
+        submission: &Submission,
+    ) -> impl Future<Output = Result<()>> + Send
+    where
+        R: Requester + Sync;
+
+    fn delete<R>(&self, requester: &R, intake_id: &str) -> impl Future<Output = Result<()>> + Send
+    where
+        R: Requester + Sync;
+}
+
+pub enum Backend {
+    Service(Service),
+    Webhook(Webhook),
+    Jsonl(Jsonl),
+    Mirror(Mirror),
+}
+
+impl Backend {
+    pub async fn open(config: BackendConfig, service: ServiceOptions) -> Result<Backend> {
+        Ok(match config {
+            BackendConfig::Service { url } => Backend::Service(Service::new(url, service)?),
+            BackendConfig::Webhook {
+                url,
+                create,
+                finish,
+                correct,
+                delete,
+                id_pointer,
+            } => Backend::Webhook(Webhook::new(
+                url, create, finish, correct, delete, id_pointer,
+            )),
+            BackendConfig::Jsonl { path } => Backend::Jsonl(Jsonl::new(path)),
+            BackendConfig::Sqlite { path } => Backend::Mirror(Mirror::open(path).await?),
+        })
+    }
+}
+
+impl Backend {
+    // How many creates create_batch takes at once. Backends without batch
+    // support take one, and so are never given a batch
+    pub fn batch_size(&self) -> usize {
+        match self {
+            Backend::Service(b) => b.batch_size(),
+            Backend::Webhook(_) | Backend::Jsonl(_) | Backend::Mirror(_) => 1,
+        }
+    }
+
+    // A result for each create, in order
+    pub async fn create_batch<R>(
+        &self,
+        requester: &R,
+        creations: &[(&str, Submission<'_>)],
+    ) -> Result<Vec<Result<String>>>
+    where
+        R: Requester + Sync,
+    {
+        match self {
+            Backend::Service(b) => b.create_batch(requester, creations).await,
+            Backend::Webhook(_) | Backend::Jsonl(_) | Backend::Mirror(_) => {
+                Err(anyhow!("intake backend can't create plays in batches"))
+            }
+        }
+    }
+}
+
+impl IntakeBackend for Backend {
+    async fn create<R>(
+        &self,
+        requester: &R,
+        idempotency_key: &str,
+        submission: &Submission<'_>,
+    ) -> Result<String>
+    where
+        R: Requester + Sync,
+    {
+        match self {
+            Backend::Service(b) => b.create(requester, idempotency_key, submission).await,
+            Backend::Webhook(b) => b.create(requester, idempotency_key, submission).await,
+            Backend::Jsonl(b) => b.create(requester, idempotency_key, submission).await,
+            Backend::Mirror(b) => b.create(requester, idempotency_key, submission).await,
+        }
+    }
+
+    async fn finish<R>(
+        &self,
+        requester: &R,
+        intake_id: &str,
+        submission: &Submission<'_>,
+    ) -> Result<()>
+    where
+        R: Requester + Sync,
+    {
+        match self {
+            Backend::Service(b) => b.finish(requester, intake_id, submission).await,
+            Backend::Webhook(b) => b.finish(requester, intake_id, submission).await,
+            Backend::Jsonl(b) => b.finish(requester, intake_id, submission).await,
+            Backend::Mirror(b) => b.finish(requester, intake_id, submission).await,
+        }
+    }
+
+    async fn correct<R>(
+        &self,
+        requester: &R,
+        intake_id: &str,
+        submission: &Submission<'_>,
+    ) -> Result<()>
+    where
+        R: Requester + Sync,
+    {
+        match self {
+            Backend::Service(b) => b.correct(requester, intake_id, submission).await,
+            Backend::Webhook(b) => b.correct(requester, intake_id, submission).await,
+            Backend::Jsonl(b) => b.correct(requester, intake_id, submission).await,
+            Backend::Mirror(b) => b.correct(requester, intake_id, submission).await,
+        }
+    }
+
+    async fn delete<R>(&self, requester: &R, intake_id: &str) -> Result<()>
+    where
+        R: Requester + Sync,
+    {
+        match self {
+            Backend::Service(b) => b.delete(requester, intake_id).await,
+            Backend::Webhook(b) => b.delete(requester, intake_id).await,
+            Backend::Jsonl(b) => b.delete(requester, intake_id).await,
+            Backend::Mirror(b) => b.delete(requester, intake_id).await,
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{
+    intake::backend::{IntakeBackend, Submission},
+    internal::requester::Requester,
+};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+
+// Appends a line for each change to a play, using the play's id as the
+// record's id. Replaying the file in order gives each play's latest state
+pub struct Jsonl {
+    path: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+struct Line<'a> {
+    action: &'a str,
+    time: u64,
+    id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    idempotency_key: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    game: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_time: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_time: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    active_duration: Option<u64>,
+}
+
+impl Jsonl {
+    pub fn new(path: PathBuf) -> Jsonl {
+        Jsonl { path }
+    }
+
+    async fn append(
+        &self,
+        action: &str,
+        id: &str,
+        idempotency_key: Option<&str>,
+        submission: Option<&Submission<'_>>,
+    ) -> Result<()> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let line = Line {
+            action,
+            time,
+            id,
+            idempotency_key,
+            game: submission.map(|s| s.game_label),
+            language: submission.map(|s| s.language.code()),
+            start_time: submission.map(|s| s.start_time),
+            end_time: submission.and_then(|s| s.end_time),
+            active_duration: submission.and_then(|s| s.active_duration),
+        };
+        let mut bytes = serde_json::to_vec(&line)?;
+        bytes.push(b'\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .with_context(|| format!("opening {:?}", self.path))?;
+        file.write_all(&bytes).await?;
+        file.sync_data().await?;
+        Ok(())
+    }
+}
+
+impl IntakeBackend for Jsonl {
+    async fn create<R>(
+        &self,
+        _requester: &R,
+        idempotency_key: &str,
+        submission: &Submission<'_>,
+    ) -> Result<String>
+    where
+        R: Requester + Sync,
+    {
+        let id = submission.play_id.to_string();
+        self.append("create", &id, Some(idempotency_key), Some(submission))
+            .await?;
+        Ok(id)
+    }
+
+    async fn finish<R>(
+        &self,
+        _requester: &R,
+        intake_id: &str,
+        submission: &Submission<'_>,
+    ) -> Result<()>
+    where
+        R: Requester + Sync,
+    {
+        self.append("finish", intake_id, None, Some(submission))
+            .await
+    }
+
+    async fn correct<R>(
+        &self,
+        _requester: &R,
+        intake_id: &str,
+        submission: &Submission<'_>,
+    ) -> Result<()>
+    where
+        R: Requester + Sync,
+    {
+        self.append("correct", intake_id, None, Some(submission))
+            .await
+    }
+
+    async fn delete<R>(&self, _requester: &R, intake_id: &str) -> Result<()>
+    where
+        R: Requester + Sync,
+    {
+        self.append("delete", intake_id, None, None).await
+    }
+}
@@ -0,0 +1,101 @@
+use crate::{
+    intake::backend::{IntakeBackend, Submission},
+    internal::requester::Requester,
+};
+use anyhow::Result;
+use rusqlite::params;
+use std::path::PathBuf;
+use tokio_rusqlite::Connection;
+use tracing::info;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS intakes (id TEXT PRIMARY KEY, game TEXT NOT NULL, language TEXT NOT NULL, start_time INTEGER NOT NULL, end_time INTEGER, active_duration INTEGER)";
+
+// Keeps a table of plays in a separate SQLite database, keyed by the play's
+// id, for other tools to read
+pub struct Mirror {
+    dbh: Connection,
+}
+
+impl Mirror {
+    pub async fn open(path: PathBuf) -> Result<Mirror> {
+        let dbh = Connection::open(&path).await?;
+        dbh.call(|conn| Ok(conn.execute_batch(SCHEMA)?)).await?;
+        info!("Opened intake mirror {path:?}");
+        Ok(Mirror { dbh })
+    }
+
+    async fn upsert(&self, id: String, submission: &Submission<'_>) -> Result<()> {
+        let game = submission.game_label.to_owned();
+        let language = submission.language.code().to_owned();
+        let (start_time, end_time, active_duration) = (
+            submission.start_time,
+            submission.end_time,
+            submission.active_duration,
+        );
+
+        Ok(self
+            .dbh
+            .call(move |conn| {
+                conn.execute(
+                    "INSERT INTO intakes (id, game, language, start_time, end_time, active_duration) VALUES (?1, ?2, ?3, ?4, ?5, ?6) ON CONFLICT (id) DO UPDATE SET game=?2, language=?3, start_time=?4, end_time=?5, active_duration=?6",
+                    params![id, game, language, start_time, end_time, active_duration],
+                )?;
+                Ok(())
+            })
+            .await?)
+    }
+}
+
+impl IntakeBackend for Mirror {
+    async fn create<R>(
+        &self,
+        _requester: &R,
+        _idempotency_key: &str,
+        submission: &Submission<'_>,
+    ) -> Result<String>
+    where
+        R: Requester + Sync,
+    {
+        let id = submission.play_id.to_string();
+        self.upsert(id.clone(), submission).await?;
+        Ok(id)
+    }
+
+    async fn finish<R>(
+        &self,
+        _requester: &R,
+        intake_id: &str,
+        submission: &Submission<'_>,
+    ) -> Result<()>
+    where
+        R: Requester + Sync,
+    {
+        self.upsert(intake_id.to_owned(), submission).await
+    }
+
+    async fn correct<R>(
+        &self,
+        _requester: &R,
+        intake_id: &str,
+        submission: &Submission<'_>,
+    ) -> Result<()>
+    where
+        R: Requester + Sync,
+    {
+        self.upsert(intake_id.to_owned(), submission).await
+    }
+
+    async fn delete<R>(&self, _requester: &R, intake_id: &str) -> Result<()>
+    where
+        R: Requester + Sync,
+    {
+        let id = intake_id.to_owned();
+        Ok(self
+            .dbh
+            .call(move |conn| {
+                conn.execute("DELETE FROM intakes WHERE id=?", params![id])?;
+                Ok(())
+            })
+            .await?)
+    }
+}
@@ -0,0 +1,399 @@
+use crate::{
+    intake::backend::{IntakeBackend, ServiceOptions, Submission},
+    internal::{
+        failure::{Permanent, status_error},
+        requester::Requester,
+    },
+    language::Language,
+};
+use anyhow::{Result, anyhow};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::info;
+
+// The intake service's codes for the languages it supports, which
+// [intake_languages] adds to or overrides
+const DEFAULT_LANGUAGES: &[(&str, &str)] =
+    &[("en", "en"), ("ja", "ja"), ("can", "can"), ("yue", "can")];
+
+// The intake service: plays are created with a POST, then PATCHed and
+// DELETEd by the id it responds with
+pub struct Service {
+    url: String,
+    // Keyed by normalized language tag
+    languages: HashMap<String, String>,
+    // Where many plays can be created with one POST, if anywhere
+    batch_url: Option<String>,
+    batch_size: usize,
+    // Set once the service turns out not to look plays up by idempotencyKey,
+    // after which creates rely on the Idempotency-Key header alone
+    lookup_unsupported: AtomicBool,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateRequest<'a> {
+    #[serde(rename = "startTime")]
+    start_time: u64,
+    #[serde(rename = "endTime")]
+    end_time: Option<u64>,
+    #[serde(rename = "activeDuration", skip_serializing_if = "Option::is_none")]
+    active_duration: Option<u64>,
+    #[serde(rename = "game")]
+    game_label: &'a str,
+    language: &'a str,
+    #[serde(rename = "idempotencyKey")]
+    idempotency_key: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct IntakeResponse {
+    message: Option<String>,
+    error: Option<String>,
+    object: Option<IntakeResponseObject>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IntakeResponseObject {
+    id: String,
+}
+
+// One result per play, in the order they were sent
+#[derive(Debug, Deserialize)]
+struct BatchResponse {
+    error: Option<String>,
+    results: Option<Vec<IntakeResponse>>,
+}
+
+impl Service {
+    pub fn new(url: String, options: ServiceOptions) -> Result<Service> {
+        let mut languages: HashMap<String, String> = DEFAULT_LANGUAGES
+            .iter()
+            .map(|(tag, code)| (tag.to_string(), code.to_string()))
+            .collect();
+        for (tag, code) in options.languages {
+            languages.insert(Language::parse(&tag)?.code().to_owned(), code);
+        }
+
+        Ok(Service {
+            url,
+            languages,
+            batch_url: options.batch_url,
+            batch_size: options.batch_size,
+            lookup_unsupported: AtomicBool::new(false),
+        })
+    }
+
+    pub fn batch_size(&self) -> usize {
+        if self.batch_url.is_some() {
+            self.batch_size
+        } else {
+            1
+        }
+    }
+
+    fn create_request<'a>(
+        &'a self,
+        idempotency_key: &'a str,
+        submission: &Submission<'a>,
+    ) -> Result<CreateRequest<'a>> {
+        Ok(CreateRequest {
+            start_time: submission.start_time,
+            end_time: submission.end_time,
+            active_duration: submission.active_duration,
+            game_label: submission.game_label,
+            language: self.intake_code(submission.language)?,
+            idempotency_key,
+        })
+    }
+
+    // Creates each play with one POST to batch_url. The service is expected
+    // to honor each play's idempotencyKey, as there's no lookup beforehand;
+    // plays are only batched if no create was ever sent for them, so retries
+    // go through create and are looked up. Plays that can't be sent, such as
+    // those in an unmapped language, get their own errors
+    pub async fn create_batch<R>(
+        &self,
+        requester: &R,
+        creations: &[(&str, Submission<'_>)],
+    ) -> Result<Vec<Result<String>>>
+    where
+        R: Requester + Sync,
+    {
+        #[derive(Debug, Serialize)]
+        struct Request<'a> {
+            plays: Vec<CreateRequest<'a>>,
+        }
+
+        let url = self
+            .batch_url
+            .as_deref()
+            .ok_or_else(|| anyhow!("no intake_batch_url to create plays in batches"))?;
+
+        let mut results: Vec<Result<String>> = Vec::with_capacity(creations.len());
+        let mut plays = Vec::with_capacity(creations.len());
+        for (idempotency_key, submission) in creations {
+            match self.create_request(idempotency_key, submission) {
+                Ok(request) => {
+                    plays.push(request);
+                    results.push(Ok(String::new()));
+                }
+                Err(e) => results.push(Err(e)),
+            }
+        }
+        if plays.is_empty() {
+            return Ok(results);
+        }
+
+        let method = reqwest::Method::POST;
+        let sent = plays.len();
+        let responses = match requester
+            .request(url, &method, &[], Request { plays })
+            .await?
+        {
+            BatchResponse {
+                error: Some(error), ..
+            } => return Err(anyhow!("Error {method:?}ing {url:?} from server: {error}")),
+            BatchResponse {
+                results: Some(responses),
+                ..
+            } if responses.len() == sent => responses,
+            res => {
+                return Err(anyhow!(
+                    "Error pattern-matching {method:?} {url:?} response for {sent} plays: {res:?}"
+                ));
+            }
+        };
+        info!("Success {method:?}ing {sent} plays to {url:?}");
+
+        let mut responses = responses.into_iter();
+        for result in results.iter_mut().filter(|r| r.is_ok()) {
+            *result = match responses.next() {
+                Some(IntakeResponse {
+                    object: Some(IntakeResponseObject { id }),
+                    error: None,
+                    ..
+                }) => Ok(id),
+                Some(IntakeResponse {
+                    error: Some(error), ..
+                }) => Err(anyhow!("Error creating play in batch: {error}")),
+                res => Err(anyhow!("Error pattern-matching batch result: {res:?}")),
+            };
+        }
+
+        Ok(results)
+    }
+
+    // Falls back to less specific tags, so "ja-JP" uses the code for "ja".
+    // Languages without a code can't be submitted, so are dead-lettered
+    // rather than sent as some other language
+    fn intake_code(&self, language: &Language) -> Result<&str> {
+        language
+            .fallbacks()
+            .find_map(|tag| self.languages.get(tag))
+            .map(String::as_str)
+            .ok_or_else(|| {
+                anyhow!(Permanent(format!(
+                    "intake service has no code for language {language}; add one to [intake_languages]"
+                )))
+            })
+    }
+
+    // The intake already created with this key, e.g. by a POST whose
+    // response was lost. None too if the service can't look plays up
+    async fn find<R>(&self, requester: &R, idempotency_key: &str) -> Result<Option<String>>
+    where
+        R: Requester + Sync,
+    {
+        if self.lookup_unsupported.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+
+        let url = {
+            let mut url = reqwest::Url::parse(&self.url)?;
+            url.query_pairs_mut()
+                .append_pair("idempotencyKey", idempotency_key);
+            String::from(url)
+        };
+        let res = requester
+            .fetch(&url, &reqwest::Method::GET, &[], &())
+            .await?;
+
+        match res.status() {
+            StatusCode::NOT_FOUND => return Ok(None),
+            StatusCode::BAD_REQUEST
+            | StatusCode::METHOD_NOT_ALLOWED
+            | StatusCode::NOT_IMPLEMENTED => {
+                info!(
+                    "{:?} doesn't support looking up plays by idempotencyKey, relying on the Idempotency-Key header",
+                    self.url
+                );
+                self.lookup_unsupported.store(true, Ordering::Relaxed);
+                return Ok(None);
+            }
+            status if !status.is_success() => {
+                return Err(status_error(
+                    status,
+                    format!("Error looking up {url:?}: got status code {status}"),
+                ));
+            }
+            _ => {}
+        }
+
+        match res.json::<IntakeResponse>().await? {
+            IntakeResponse {
+                error: Some(error), ..
+            } => Err(anyhow!("Error looking up {url:?} from server: {error}")),
+            IntakeResponse { object, .. } => Ok(object.map(|o| o.id)),
+        }
+    }
+
+    async fn submit<R, Req>(
+        &self,
+        requester: &R,
+        method: reqwest::Method,
+        headers: &[(&str, &str)],
+        request: Req,
+    ) -> Result<Option<IntakeResponseObject>>
+    where
+        R: Requester + Sync,
+        Req: Serialize + std::fmt::Debug + Send + Sync,
+    {
+        let url = &self.url;
+
+        match requester.request(url, &method, headers, request).await? {
+            IntakeResponse {
+                error: Some(error), ..
+            } => Err(anyhow!("Error {method:?}ing {url:?} from server: {error}")),
+            IntakeResponse {
+                message: Some(message),
+                object,
+                ..
+            } => {
+                info!("Success {method:?}ing {url:?}: {message}");
+                Ok(object)
+            }
+            res => Err(anyhow!(
+                "Error pattern-matching {method:?} {url:?} response: {res:?}"
+            )),
+        }
+    }
+}
+
+impl IntakeBackend for Service {
+    async fn create<R>(
+        &self,
+        requester: &R,
+        idempotency_key: &str,
+        submission: &Submission<'_>,
+    ) -> Result<String>
+    where
+        R: Requester + Sync,
+    {
+        let request = self.create_request(idempotency_key, submission)?;
+
+        if let Some(id) = self.find(requester, idempotency_key).await? {
+            info!("Found intake {id} already created with key {idempotency_key}");
+            return Ok(id);
+        }
+
+        let method = reqwest::Method::POST;
+        let headers = [("Idempotency-Key", idempotency_key)];
+        match self
+            .submit(requester, method.clone(), &headers, request)
+            .await?
+        {
+            Some(IntakeResponseObject { id }) => Ok(id),
+            None => Err(anyhow!(
+                "Error pattern-matching {method:?} {:?} response: no object",
+                self.url
+            )),
+        }
+    }
+
+    async fn finish<R>(
+        &self,
+        requester: &R,
+        intake_id: &str,
+        submission: &Submission<'_>,
+    ) -> Result<()>
+    where
+        R: Requester + Sync,
+    {
+        #[derive(Debug, Serialize)]
+        struct Request<'a> {
+            id: &'a str,
+            #[serde(rename = "endTime")]
+            end_time: Option<u64>,
+            #[serde(rename = "activeDuration")]
+            active_duration: Option<u64>,
+        }
+
+        let request = Request {
+            id: intake_id,
+            end_time: submission.end_time,
+            active_duration: submission.active_duration,
+        };
+
+        self.submit(requester, reqwest::Method::PATCH, &[], request)
+            .await?;
+        Ok(())
+    }
+
+    async fn correct<R>(
+        &self,
+        requester: &R,
+        intake_id: &str,
+        submission: &Submission<'_>,
+    ) -> Result<()>
+    where
+        R: Requester + Sync,
+    {
+        #[derive(Debug, Serialize)]
+        struct Request<'a> {
+            id: &'a str,
+            #[serde(rename = "startTime")]
+            start_time: u64,
+            #[serde(rename = "endTime")]
+            end_time: Option<u64>,
+            #[serde(rename = "activeDuration")]
+            active_duration: Option<u64>,
+            #[serde(rename = "game")]
+            game_label: &'a str,
+            language: &'a str,
+        }
+
+        let request = Request {
+            id: intake_id,
+            start_time: submission.start_time,
+            end_time: submission.end_time,
+            active_duration: submission.active_duration,
+            game_label: submission.game_label,
+            language: self.intake_code(submission.language)?,
+        };
+
+        self.submit(requester, reqwest::Method::PATCH, &[], request)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete<R>(&self, requester: &R, intake_id: &str) -> Result<()>
+    where
+        R: Requester + Sync,
+    {
+        #[derive(Debug, Serialize)]
+        struct Request<'a> {
+            id: &'a str,
+        }
+
+        self.submit(
+            requester,
+            reqwest::Method::DELETE,
+            &[],
+            Request { id: intake_id },
+        )
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+use crate::{
+    intake::backend::{IntakeBackend, Submission},
+    internal::requester::Requester,
+};
+use anyhow::{Result, anyhow};
+use serde_json::{Value, json};
+use tracing::info;
+
+// POSTs a body rendered from a template for each change to a play. Actions
+// without a template are skipped
+pub struct Webhook {
+    url: String,
+    create: String,
+    finish: Option<String>,
+    correct: Option<String>,
+    delete: Option<String>,
+    id_pointer: Option<String>,
+}
+
+impl Webhook {
+    pub fn new(
+        url: String,
+        create: String,
+        finish: Option<String>,
+        correct: Option<String>,
+        delete: Option<String>,
+        id_pointer: Option<String>,
+    ) -> Webhook {
+        Webhook {
+            url,
+            create,
+            finish,
+            correct,
+            delete,
+            id_pointer,
+        }
+    }
+
+    async fn post<R>(
+        &self,
+        requester: &R,
+        headers: &[(&str, &str)],
+        template: &str,
+        values: &[(&str, Value)],
+    ) -> Result<String>
+    where
+        R: Requester + Sync,
+    {
+        let body = render(template, values)?;
+        let res = requester
+            .send(&self.url, &reqwest::Method::POST, headers, body)
+            .await?;
+        info!("Success POSTing {:?}: {}", self.url, res.status());
+        Ok(res.text().await?)
+    }
+}
+
+fn values<'a>(intake_id: Option<&str>, submission: &Submission<'a>) -> Vec<(&'a str, Value)> {
+    vec![
+        ("id", json!(intake_id)),
+        ("play_id", json!(submission.play_id)),
+        ("game", json!(submission.game_label)),
+        ("language", json!(submission.language.code())),
+        ("start_time", json!(submission.start_time)),
+        ("end_time", json!(submission.end_time)),
+        ("active_duration", json!(submission.active_duration)),
+    ]
+}
+
+// Replaces each {{name}} with the JSON value of that field, so string
+// fields arrive quoted and missing ones as null
+fn render(template: &str, values: &[(&str, Value)]) -> Result<Value> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| anyhow!("unclosed {{{{ in template {template:?}"))?;
+        let name = rest[start + 2..start + end].trim();
+        let value = values
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v)
+            .ok_or_else(|| anyhow!("unknown field {name:?} in template {template:?}"))?;
+
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(&value.to_string());
+        rest = &rest[start + end + 2..];
+    }
+    rendered.push_str(rest);
+
+    serde_json::from_str(&rendered)
+        .map_err(|e| anyhow!("template {template:?} rendered invalid JSON {rendered:?}: {e}"))
+}
+
+fn id_at(response: &str, pointer: &str) -> Result<String> {
+    let response: Value = serde_json::from_str(response)?;
+    match response.pointer(pointer) {
+        Some(Value::String(id)) => Ok(id.clone()),
+        Some(Value::Number(id)) => Ok(id.to_string()),
+        _ => Err(anyhow!("no id at {pointer:?} in response {response}")),
+    }
+}
+
+impl IntakeBackend for Webhook {
+    async fn create<R>(
+        &self,
+        requester: &R,
+        idempotency_key: &str,
+        submission: &Submission<'_>,
+    ) -> Result<String>
+    where
+        R: Requester + Sync,
+    {
+        let mut values = values(None, submission);
+        values.push(("idempotency_key", json!(idempotency_key)));
+        let headers = [("Idempotency-Key", idempotency_key)];
+        let response = self
+            .post(requester, &headers, &self.create, &values)
+            .await?;
+
+        match &self.id_pointer {
+            Some(pointer) => id_at(&response, pointer),
+            None => Ok(submission.play_id.to_string()),
+        }
+    }
+
+    async fn finish<R>(
+        &self,
+        requester: &R,
+        intake_id: &str,
+        submission: &Submission<'_>,
+    ) -> Result<()>
+    where
+        R: Requester + Sync,
+    {
+        if let Some(template) = &self.finish {
+            self.post(
+                requester,
+                &[],
+                template,
+                &values(Some(intake_id), submission),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn correct<R>(
+        &self,
+        requester: &R,
+        intake_id: &str,
+        submission: &Submission<'_>,
+    ) -> Result<()>
+    where
+        R: Requester + Sync,
+    {
+        if let Some(template) = &self.correct {
+            self.post(
+                requester,
+                &[],
+                template,
+                &values(Some(intake_id), submission),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn delete<R>(&self, requester: &R, intake_id: &str) -> Result<()>
+    where
+        R: Requester + Sync,
+    {
+        if let Some(template) = &self.delete {
+            self.post(requester, &[], template, &[("id", json!(intake_id))])
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let values = [
+            ("id", json!(null)),
+            ("game", json!("Tetris \"DX\"")),
+            ("start_time", json!(100)),
+        ];
+
+        assert_eq!(
+            render(
+                r#"{"title": {{game}}, "at": {{ start_time }}, "id": {{id}}}"#,
+                &values
+            )
+            .unwrap(),
+            json!({"title": "Tetris \"DX\"", "at": 100, "id": null}),
+        );
+        assert_eq!(render("[]", &values).unwrap(), json!([]));
+        assert!(render(r#"{"x": {{nope}}}"#, &values).is_err());
+        assert!(render(r#"{"x": {{game"#, &values).is_err());
+        assert!(render(r#"{"x": "{{game}}"}"#, &values).is_err());
+    }
+
+    #[test]
+    fn test_id_at() {
+        assert_eq!(
+            id_at(r#"{"data": {"id": "abc"}}"#, "/data/id").unwrap(),
+            "abc"
+        );
+        assert_eq!(id_at(r#"{"id": 42}"#, "/id").unwrap(), "42");
+        assert!(id_at(r#"{"id": null}"#, "/id").is_err());
+        assert!(id_at("not json", "/id").is_err());
+    }
+}
@@ -1,25 +1,167 @@
-use std::{cmp::min, collections::VecDeque, future::Future, time::Duration};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    cmp::min,
+    collections::{HashSet, VecDeque},
+    future::Future,
+    time::Duration,
+};
 use tokio::{
-    sync::mpsc,
+    sync::{mpsc, watch},
     time::{Instant, timeout_at},
 };
-use tracing::info;
+use tracing::{error, info};
 
 pub enum Action {
     Continue,
     ResetTimeout,
     Halt,
     Retry,
+    DeadLetter,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ChannelStatus {
+    pub online: bool,
+    pub buffered: usize,
+    pub priority_attempts: u64,
+    pub normal_attempts: u64,
+    #[serde(rename = "next_retry_secs", serialize_with = "serialize_deadline")]
+    pub retry_deadline: Option<Instant>,
+}
+
+fn serialize_deadline<S>(deadline: &Option<Instant>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    deadline
+        .map(|d| d.saturating_duration_since(Instant::now()).as_secs_f64())
+        .serialize(serializer)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backoff {
+    #[default]
+    Linear,
+    Exponential,
+    Fixed,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryPolicy {
+    pub backoff: Backoff,
+    pub online_secs: u64,
+    pub offline_secs: u64,
+    // Defaults to five times online_secs or offline_secs
+    pub max_delay_secs: Option<u64>,
+    // Events that fail this many times are dead-lettered rather than retried
+    pub max_attempts: Option<u64>,
+    // Randomly shortens each delay by up to half, so that devices coming back
+    // online together don't all retry in lockstep
+    pub jitter: bool,
+    // Schedules retries for each event separately, so that one failing event
+    // doesn't hold up the rest of the queue
+    pub per_item: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            backoff: Backoff::Linear,
+            online_secs: 5,
+            offline_secs: 30,
+            max_delay_secs: None,
+            max_attempts: None,
+            jitter: false,
+            per_item: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn delay(&self, attempts: u64, is_online: bool, elapsed: Duration) -> Duration {
+        let base = if is_online {
+            self.online_secs
+        } else {
+            self.offline_secs
+        };
+        let max = self.max_delay_secs.unwrap_or(base.saturating_mul(5));
+
+        let secs = match self.backoff {
+            Backoff::Fixed => base,
+            Backoff::Linear => base.saturating_mul(attempts),
+            Backoff::Exponential => {
+                base.saturating_mul(1u64 << min(attempts.saturating_sub(1), 32))
+            }
+        };
+
+        let mut delay = Duration::from_secs(min(secs, max));
+        if self.jitter {
+            delay = delay.mul_f64(rand::random_range(0.5..=1.0));
+        }
+
+        // Retry quickly right after startup, when failures are most likely
+        // due to the network still coming up
+        min(delay, elapsed)
+    }
+
+    pub fn is_exhausted(&self, attempts: u64) -> bool {
+        self.max_attempts.is_some_and(|max| attempts >= max)
+    }
 }
 
 pub trait PriorityRetryChannel {
     type Event: std::fmt::Debug + Send + Sync;
 
     fn is_online(&self) -> bool;
+    fn retry_policy(&self) -> &RetryPolicy;
+    fn status_tx(&self) -> &watch::Sender<ChannelStatus>;
     fn is_high_priority(&self, event: &Self::Event) -> bool;
     fn handle(&mut self, event: &Self::Event) -> impl Future<Output = Action> + Send;
 
-    fn run<'a>(
+    fn dead_letter(&mut self, event: Self::Event) -> impl Future<Output = ()> + Send
+    where
+        Self: Send,
+    {
+        async move {
+            error!("Giving up on {event:?}");
+        }
+    }
+
+    // Events with the same key are handled in the order they arrived, even
+    // when retries are scheduled per item. Events without a key may be
+    // handled in any order
+    fn ordering_key(&self, _event: &Self::Event) -> Option<i64> {
+        None
+    }
+
+    // Batchable events that are ready together, up to batch_size of them,
+    // go to handle_batch at once rather than to handle one by one. Retries
+    // always go to handle, so a batch that failed is retried one at a time
+    fn is_batchable(&self, _event: &Self::Event) -> bool {
+        false
+    }
+
+    fn batch_size(&self) -> usize {
+        1
+    }
+
+    // Returns an action for each event, in order
+    fn handle_batch(&mut self, events: &[&Self::Event]) -> impl Future<Output = Vec<Action>> + Send
+    where
+        Self: Send,
+    {
+        async move {
+            let mut actions = Vec::with_capacity(events.len());
+            for event in events {
+                actions.push(self.handle(event).await);
+            }
+            actions
+        }
+    }
+
+    fn run(
         &mut self,
         mut rx: mpsc::UnboundedReceiver<Self::Event>,
     ) -> impl Future<Output = ()> + Send
@@ -27,163 +169,646 @@ pub trait PriorityRetryChannel {
         Self: Send,
     {
         async move {
-            let mut retry_deadline = None;
             let mut buffer = VecDeque::new();
             let mut priority_event = None;
-            let mut priority_retry = None;
-            let mut normal_retry = None;
+            let mut priority_attempts = 0;
             let start = Instant::now();
 
-            let online_secs = 5;
-            let offline_secs = 30;
-
             loop {
-                if let Some(event) = priority_event {
+                let next = schedule(
+                    &buffer,
+                    self.retry_policy().per_item,
+                    self.is_online(),
+                    Instant::now(),
+                    |event| self.ordering_key(event),
+                );
+                self.status_tx().send_replace(ChannelStatus {
+                    online: self.is_online(),
+                    buffered: buffer.len(),
+                    priority_attempts,
+                    normal_attempts: buffer.iter().map(|p| p.attempts).max().unwrap_or(0),
+                    retry_deadline: match next {
+                        Next::Wait(deadline) => Some(deadline),
+                        _ => None,
+                    },
+                });
+
+                if let Some(event) = priority_event.take() {
                     match self.handle(&event).await {
-                        Action::Continue => {
-                            priority_retry = None;
-                            priority_event = None;
-                        }
+                        Action::Continue => priority_attempts = 0,
                         Action::ResetTimeout => {
-                            priority_retry = None;
-                            priority_event = None;
-                            normal_retry = None;
+                            priority_attempts = 0;
+                            for pending in buffer.iter_mut() {
+                                pending.attempts = 0;
+                                pending.due = None;
+                            }
                         }
                         Action::Halt => break,
+                        Action::DeadLetter => {
+                            priority_attempts = 0;
+                            self.dead_letter(event).await;
+                        }
                         Action::Retry => {
-                            let retries = if let Some(r) = priority_retry {
-                                let r = r + 1;
-                                priority_retry = Some(r);
-                                if r > 5 { 5 } else { r }
-                            } else {
-                                priority_retry = Some(1);
-                                1
-                            };
-
-                            let wait = retries
-                                * if self.is_online() {
-                                    online_secs
-                                } else {
-                                    offline_secs
-                                };
-                            let wait = min(start.elapsed().as_secs(), wait);
+                            priority_attempts += 1;
+                            if self.retry_policy().is_exhausted(priority_attempts) {
+                                priority_attempts = 0;
+                                self.dead_letter(event).await;
+                                continue;
+                            }
+
+                            let wait = self.retry_policy().delay(
+                                priority_attempts,
+                                self.is_online(),
+                                start.elapsed(),
+                            );
 
-                            info!("Waiting for {wait}s before retrying");
-                            tokio::time::sleep(Duration::from_secs(wait)).await;
+                            info!("Waiting for {wait:?} before retrying");
+                            self.status_tx().send_modify(|status| {
+                                status.priority_attempts = priority_attempts;
+                                status.retry_deadline = Some(Instant::now() + wait);
+                            });
+                            tokio::time::sleep(wait).await;
                             priority_event = Some(event);
-                            continue;
                         }
                     }
+                    continue;
                 }
 
-                // If the buffer is empty, block until we get an event
-                let event = if buffer.is_empty() {
-                    rx.recv().await
-                // Otherwise we have events to process. First let's see if we have
-                // a deadline to wait for; if so then we'll block on the channel
-                // until the deadline
-                } else if let Some((online_deadline, offline_deadline)) = retry_deadline {
-                    let deadline = if self.is_online() {
-                        online_deadline
-                    } else {
-                        offline_deadline
-                    };
-                    match timeout_at(deadline, rx.recv()).await {
+                let event = match next {
+                    // If the buffer is empty, block until we get an event
+                    Next::Idle => rx.recv().await,
+
+                    // Otherwise we have events to process but they're all
+                    // waiting to be retried, so block on the channel until the
+                    // earliest deadline
+                    Next::Wait(deadline) => match timeout_at(deadline, rx.recv()).await {
                         Ok(event) => event,
-                        Err(_) => {
-                            retry_deadline = None;
-                            None
-                        }
-                    }
-                // Otherwise we have an event to process but no deadline to wait for
-                // so just quickly check the channel (which will almost certainly be
-                // empty) then proceed to processing events
-                } else {
-                    match rx.try_recv() {
+                        Err(_) => continue,
+                    },
+
+                    // Otherwise we have an event ready to process, so just
+                    // quickly check the channel (which will almost certainly be
+                    // empty) then proceed to processing it
+                    Next::Ready(_) => match rx.try_recv() {
                         Ok(e) => Some(e),
                         Err(tokio::sync::mpsc::error::TryRecvError::Disconnected) => break,
                         _ => None,
-                    }
+                    },
                 };
 
                 if let Some(event) = event {
                     if self.is_high_priority(&event) {
-                        match self.handle(&event).await {
-                            Action::Continue => priority_retry = None,
+                        priority_event = Some(event);
+                    } else {
+                        buffer.push_back(Pending {
+                            event,
+                            attempts: 0,
+                            attempted: false,
+                            due: None,
+                        });
+                    }
+                } else if let Next::Ready(index) = next {
+                    let indices = batch(
+                        &buffer,
+                        index,
+                        self.retry_policy().per_item,
+                        self.is_online(),
+                        Instant::now(),
+                        self.batch_size(),
+                        |event| self.is_batchable(event),
+                        |event| self.ordering_key(event),
+                    );
+                    let actions = if indices.len() > 1 {
+                        let events: Vec<_> = indices.iter().map(|i| &buffer[*i].event).collect();
+                        info!("Handling a batch of {} events", events.len());
+                        self.handle_batch(&events).await
+                    } else {
+                        vec![self.handle(&buffer[index].event).await]
+                    };
 
-                            Action::ResetTimeout => {
-                                priority_retry = None;
-                                normal_retry = None;
+                    // Backwards, so removing an event doesn't shift the
+                    // indices still to come
+                    let mut dead = Vec::new();
+                    let mut halt = false;
+                    for (index, action) in indices.into_iter().zip(actions).rev() {
+                        match action {
+                            Action::Continue | Action::ResetTimeout => {
+                                buffer.remove(index);
                             }
 
-                            Action::Halt => break,
+                            Action::Halt => halt = true,
+
+                            Action::DeadLetter => dead.extend(buffer.remove(index)),
 
                             Action::Retry => {
-                                let retries = if let Some(r) = priority_retry {
-                                    let r = r + 1;
-                                    priority_retry = Some(r);
-                                    if r > 5 { 5 } else { r }
+                                let pending = &mut buffer[index];
+                                pending.attempts += 1;
+                                pending.attempted = true;
+                                if self.retry_policy().is_exhausted(pending.attempts) {
+                                    dead.extend(buffer.remove(index));
+                                    continue;
+                                }
+
+                                let now = Instant::now();
+
+                                let policy = self.retry_policy();
+                                let online_wait =
+                                    policy.delay(pending.attempts, true, start.elapsed());
+                                let offline_wait =
+                                    policy.delay(pending.attempts, false, start.elapsed());
+
+                                let (a, b) = if self.is_online() {
+                                    (online_wait, offline_wait)
                                 } else {
-                                    priority_retry = Some(1);
-                                    1
+                                    (offline_wait, online_wait)
                                 };
+                                info!("Waiting for {a:?} (or possibly {b:?}) before retrying");
 
-                                let wait = retries
-                                    * if self.is_online() {
-                                        online_secs
-                                    } else {
-                                        offline_secs
-                                    };
-                                let wait = min(start.elapsed().as_secs(), wait);
-
-                                info!("Waiting for {wait}s before retrying");
-                                tokio::time::sleep(Duration::from_secs(wait)).await;
-                                priority_event = Some(event);
+                                pending.due = Some((now + online_wait, now + offline_wait));
                             }
                         }
-                    } else {
-                        buffer.push_back(event);
                     }
-                } else if let Some(event) = buffer.pop_front() {
-                    match self.handle(&event).await {
-                        Action::Continue => normal_retry = None,
 
-                        Action::ResetTimeout => normal_retry = None,
+                    for pending in dead.into_iter().rev() {
+                        self.dead_letter(pending.event).await;
+                    }
+                    if halt {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
 
-                        Action::Halt => break,
+struct Pending<E> {
+    event: E,
+    attempts: u64,
+    // Unlike attempts, not reset by ResetTimeout, so an event that may have
+    // reached the other end is never batched again
+    attempted: bool,
+    // When to next attempt this event, if online and if offline
+    due: Option<(Instant, Instant)>,
+}
 
-                        Action::Retry => {
-                            buffer.push_front(event);
-                            let now = Instant::now();
-
-                            let retries = if let Some(r) = normal_retry {
-                                let r = r + 1;
-                                normal_retry = Some(r);
-                                if r > 5 { 5 } else { r }
-                            } else {
-                                normal_retry = Some(1);
-                                1
-                            };
-
-                            let max = start.elapsed().as_secs();
-                            let online_secs = min(max, retries * online_secs);
-                            let offline_secs = min(max, retries * offline_secs);
-
-                            let (a, b) = if self.is_online() {
-                                (online_secs, offline_secs)
-                            } else {
-                                (offline_secs, online_secs)
-                            };
-                            info!("Waiting for {a}s (or possibly {b}s) before retrying");
-
-                            retry_deadline = Some((
-                                now + Duration::from_secs(online_secs),
-                                now + Duration::from_secs(offline_secs),
-                            ))
-                        }
+#[derive(Debug, PartialEq)]
+enum Next {
+    Idle,
+    Ready(usize),
+    Wait(Instant),
+}
+
+// Without per_item only the front of the buffer is eligible, so a failing
+// event holds up everything behind it. With per_item any event that's due may
+// go next, unless an earlier event with the same ordering key is still waiting
+fn schedule<E>(
+    buffer: &VecDeque<Pending<E>>,
+    per_item: bool,
+    is_online: bool,
+    now: Instant,
+    ordering_key: impl Fn(&E) -> Option<i64>,
+) -> Next {
+    let mut next = Next::Idle;
+    let mut blocked = HashSet::new();
+
+    for (index, pending) in buffer.iter().enumerate() {
+        if let Some(key) = ordering_key(&pending.event)
+            && !blocked.insert(key)
+        {
+            continue;
+        }
+
+        let due = pending
+            .due
+            .map(|(online, offline)| if is_online { online } else { offline });
+        match due {
+            Some(due) if due > now => {
+                next = match next {
+                    Next::Wait(deadline) => Next::Wait(min(deadline, due)),
+                    _ => Next::Wait(due),
+                };
+            }
+            _ => return Next::Ready(index),
+        }
+
+        if !per_item {
+            break;
+        }
+    }
+
+    next
+}
+
+// The ready event at `first`, then whichever later events can join it in a
+// batch: ready, batchable, never attempted, and the first with their ordering
+// key, so that events sharing a key still go one at a time. Without per_item,
+// the batch stops at the first event that can't join it
+#[allow(clippy::too_many_arguments)]
+fn batch<E>(
+    buffer: &VecDeque<Pending<E>>,
+    first: usize,
+    per_item: bool,
+    is_online: bool,
+    now: Instant,
+    batch_size: usize,
+    is_batchable: impl Fn(&E) -> bool,
+    ordering_key: impl Fn(&E) -> Option<i64>,
+) -> Vec<usize> {
+    let mut indices = vec![first];
+    if buffer[first].attempted || !is_batchable(&buffer[first].event) {
+        return indices;
+    }
+
+    let mut seen: HashSet<i64> = buffer
+        .iter()
+        .take(first + 1)
+        .filter_map(|p| ordering_key(&p.event))
+        .collect();
+
+    for (index, pending) in buffer.iter().enumerate().skip(first + 1) {
+        if indices.len() >= batch_size {
+            break;
+        }
+
+        let is_new_key = match ordering_key(&pending.event) {
+            Some(key) => seen.insert(key),
+            None => true,
+        };
+        let is_due = pending
+            .due
+            .map(|(online, offline)| if is_online { online } else { offline })
+            .is_none_or(|due| due <= now);
+
+        if is_new_key && is_due && !pending.attempted && is_batchable(&pending.event) {
+            indices.push(index);
+        } else if !per_item {
+            break;
+        }
+    }
+
+    indices
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn secs(policy: &RetryPolicy, attempts: u64, is_online: bool) -> u64 {
+        policy
+            .delay(attempts, is_online, Duration::from_secs(3600))
+            .as_secs()
+    }
+
+    #[test]
+    fn test_linear_delay() {
+        let policy = RetryPolicy::default();
+        assert_eq!(secs(&policy, 1, true), 5);
+        assert_eq!(secs(&policy, 2, true), 10);
+        assert_eq!(secs(&policy, 5, true), 25);
+        assert_eq!(secs(&policy, 9, true), 25);
+        assert_eq!(secs(&policy, 1, false), 30);
+        assert_eq!(secs(&policy, 9, false), 150);
+    }
+
+    #[test]
+    fn test_exponential_delay() {
+        let policy = RetryPolicy {
+            backoff: Backoff::Exponential,
+            max_delay_secs: Some(60),
+            ..RetryPolicy::default()
+        };
+        assert_eq!(secs(&policy, 1, true), 5);
+        assert_eq!(secs(&policy, 2, true), 10);
+        assert_eq!(secs(&policy, 3, true), 20);
+        assert_eq!(secs(&policy, 4, true), 40);
+        assert_eq!(secs(&policy, 5, true), 60);
+        assert_eq!(secs(&policy, 500, true), 60);
+    }
+
+    #[test]
+    fn test_fixed_delay() {
+        let policy = RetryPolicy {
+            backoff: Backoff::Fixed,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(secs(&policy, 1, true), 5);
+        assert_eq!(secs(&policy, 7, true), 5);
+        assert_eq!(secs(&policy, 7, false), 30);
+    }
+
+    #[test]
+    fn test_jitter() {
+        let policy = RetryPolicy {
+            jitter: true,
+            ..RetryPolicy::default()
+        };
+        for _ in 0..100 {
+            let delay = policy.delay(2, true, Duration::from_secs(3600));
+            assert!(delay >= Duration::from_secs(5) && delay <= Duration::from_secs(10));
+        }
+    }
+
+    #[test]
+    fn test_delay_capped_by_elapsed() {
+        let policy = RetryPolicy::default();
+        assert_eq!(
+            policy.delay(3, false, Duration::from_secs(2)),
+            Duration::from_secs(2)
+        );
+    }
+
+    #[test]
+    fn test_is_exhausted() {
+        let mut policy = RetryPolicy::default();
+        assert!(!policy.is_exhausted(1000));
+        policy.max_attempts = Some(3);
+        assert!(!policy.is_exhausted(2));
+        assert!(policy.is_exhausted(3));
+    }
+
+    fn pending(key: Option<i64>, due: Option<Instant>) -> Pending<Option<i64>> {
+        Pending {
+            event: key,
+            attempts: 0,
+            attempted: false,
+            due: due.map(|d| (d, d)),
+        }
+    }
+
+    fn next(buffer: Vec<Pending<Option<i64>>>, per_item: bool, now: Instant) -> Next {
+        schedule(&VecDeque::from(buffer), per_item, true, now, |key| *key)
+    }
+
+    #[test]
+    fn test_batch() {
+        let now = Instant::now();
+        let later = now + Duration::from_secs(5);
+        let batched = |buffer: Vec<Pending<Option<i64>>>, per_item, batch_size| {
+            batch(
+                &VecDeque::from(buffer),
+                0,
+                per_item,
+                true,
+                now,
+                batch_size,
+                |key| *key != Some(0),
+                |key| *key,
+            )
+        };
+
+        assert_eq!(batched(vec![pending(Some(1), None)], false, 10), [0]);
+        assert_eq!(
+            batched(
+                vec![
+                    pending(Some(1), None),
+                    pending(Some(2), Some(now)),
+                    pending(None, None)
+                ],
+                false,
+                10
+            ),
+            [0, 1, 2]
+        );
+        assert_eq!(
+            batched(
+                vec![pending(Some(1), None), pending(Some(2), None)],
+                false,
+                1
+            ),
+            [0]
+        );
+
+        // Not batchable, a repeated key, or not yet due
+        for blocker in [
+            pending(Some(0), None),
+            pending(Some(1), None),
+            pending(Some(3), Some(later)),
+        ] {
+            assert_eq!(
+                batched(
+                    vec![pending(Some(1), None), blocker, pending(Some(2), None)],
+                    false,
+                    10
+                ),
+                [0]
+            );
+        }
+        assert_eq!(
+            batched(
+                vec![
+                    pending(Some(1), None),
+                    pending(Some(1), None),
+                    pending(Some(2), None)
+                ],
+                true,
+                10
+            ),
+            [0, 2]
+        );
+
+        assert_eq!(
+            batched(
+                vec![pending(Some(0), None), pending(Some(2), None)],
+                true,
+                10
+            ),
+            [0]
+        );
+
+        // Retries go one at a time, even once a sync resets their attempts
+        let retried = |key| Pending {
+            attempts: 1,
+            attempted: true,
+            ..pending(key, Some(now))
+        };
+        let synced = |key| Pending {
+            attempted: true,
+            ..pending(key, None)
+        };
+        assert_eq!(
+            batched(vec![synced(Some(1)), pending(Some(2), None)], true, 10),
+            [0]
+        );
+        assert_eq!(
+            batched(vec![retried(Some(1)), pending(Some(2), None)], true, 10),
+            [0]
+        );
+        assert_eq!(
+            batched(
+                vec![
+                    pending(Some(1), None),
+                    retried(Some(2)),
+                    pending(Some(3), None)
+                ],
+                true,
+                10
+            ),
+            [0, 2]
+        );
+    }
+
+    #[test]
+    fn test_schedule_global() {
+        let now = Instant::now();
+        let later = now + Duration::from_secs(5);
+
+        assert_eq!(next(vec![], false, now), Next::Idle);
+        assert_eq!(next(vec![pending(None, None)], false, now), Next::Ready(0));
+        assert_eq!(
+            next(
+                vec![pending(None, Some(now)), pending(None, None)],
+                false,
+                now
+            ),
+            Next::Ready(0)
+        );
+        assert_eq!(
+            next(
+                vec![pending(None, Some(later)), pending(None, None)],
+                false,
+                now
+            ),
+            Next::Wait(later)
+        );
+    }
+
+    #[test]
+    fn test_schedule_per_item() {
+        let now = Instant::now();
+        let soon = now + Duration::from_secs(5);
+        let later = now + Duration::from_secs(10);
+
+        assert_eq!(
+            next(
+                vec![pending(None, Some(later)), pending(None, None)],
+                true,
+                now
+            ),
+            Next::Ready(1)
+        );
+        assert_eq!(
+            next(
+                vec![pending(None, Some(later)), pending(None, Some(soon))],
+                true,
+                now
+            ),
+            Next::Wait(soon)
+        );
+
+        // Events sharing a key stay in order behind a waiting event
+        assert_eq!(
+            next(
+                vec![
+                    pending(Some(1), Some(later)),
+                    pending(Some(1), None),
+                    pending(Some(2), None)
+                ],
+                true,
+                now
+            ),
+            Next::Ready(2)
+        );
+        assert_eq!(
+            next(
+                vec![pending(Some(1), Some(later)), pending(Some(1), None)],
+                true,
+                now
+            ),
+            Next::Wait(later)
+        );
+    }
+
+    #[derive(Debug)]
+    enum TestEvent {
+        Create(i64),
+        ForceSync,
+        Stop,
+    }
+
+    // Fails its first batch, forcing a sync as it does, and records which
+    // creates it handled together
+    struct TestChannel {
+        tx: mpsc::UnboundedSender<TestEvent>,
+        status_tx: watch::Sender<ChannelStatus>,
+        retry_policy: RetryPolicy,
+        handled: Vec<Vec<i64>>,
+    }
+
+    impl PriorityRetryChannel for TestChannel {
+        type Event = TestEvent;
+
+        fn is_online(&self) -> bool {
+            true
+        }
+
+        fn retry_policy(&self) -> &RetryPolicy {
+            &self.retry_policy
+        }
+
+        fn status_tx(&self) -> &watch::Sender<ChannelStatus> {
+            &self.status_tx
+        }
+
+        fn is_high_priority(&self, event: &TestEvent) -> bool {
+            !matches!(event, TestEvent::Create(_))
+        }
+
+        async fn handle(&mut self, event: &TestEvent) -> Action {
+            match event {
+                TestEvent::Create(id) => {
+                    self.handled.push(vec![*id]);
+                    if *id == 2 {
+                        self.tx.send(TestEvent::Stop).unwrap();
                     }
+                    Action::Continue
                 }
+                TestEvent::ForceSync => Action::ResetTimeout,
+                TestEvent::Stop => Action::Halt,
             }
         }
+
+        fn ordering_key(&self, event: &TestEvent) -> Option<i64> {
+            match event {
+                TestEvent::Create(id) => Some(*id),
+                _ => None,
+            }
+        }
+
+        fn is_batchable(&self, event: &TestEvent) -> bool {
+            matches!(event, TestEvent::Create(_))
+        }
+
+        fn batch_size(&self) -> usize {
+            10
+        }
+
+        async fn handle_batch(&mut self, events: &[&TestEvent]) -> Vec<Action> {
+            let ids = events.iter().filter_map(|e| self.ordering_key(e)).collect();
+            self.handled.push(ids);
+            if self.handled.len() > 1 {
+                return events.iter().map(|_| Action::Continue).collect();
+            }
+
+            self.tx.send(TestEvent::ForceSync).unwrap();
+            events.iter().map(|_| Action::Retry).collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_force_sync_after_failed_batch() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut channel = TestChannel {
+            tx: tx.clone(),
+            status_tx: watch::channel(ChannelStatus::default()).0,
+            retry_policy: RetryPolicy::default(),
+            handled: vec![],
+        };
+        tx.send(TestEvent::Create(1)).unwrap();
+        tx.send(TestEvent::Create(2)).unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), channel.run(rx))
+            .await
+            .unwrap();
+        assert_eq!(channel.handled, [vec![1, 2], vec![1], vec![2]]);
     }
 }
@@ -0,0 +1,70 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiskSpace {
+    #[default]
+    Ok,
+    // Below the warning threshold
+    Warning,
+    // Below the low threshold, so local copies aren't kept
+    Low,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Disk {
+    pub space: DiskSpace,
+    // On the fullest of the filesystems checked, once checked
+    pub free_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Thresholds {
+    pub warning_bytes: u64,
+    pub low_bytes: u64,
+}
+
+// The least free space on any of the filesystems the paths are on
+pub fn free_bytes<P>(paths: &[P]) -> Result<u64>
+where
+    P: AsRef<Path>,
+{
+    let mut free = u64::MAX;
+    for path in paths {
+        let path = path.as_ref();
+        let available = fs4::available_space(path)
+            .with_context(|| format!("checking free space for {path:?}"))?;
+        free = free.min(available);
+    }
+    Ok(free)
+}
+
+pub fn space_for(free_bytes: u64, thresholds: &Thresholds) -> DiskSpace {
+    if free_bytes < thresholds.low_bytes {
+        DiskSpace::Low
+    } else if free_bytes < thresholds.warning_bytes {
+        DiskSpace::Warning
+    } else {
+        DiskSpace::Ok
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_space_for() {
+        let thresholds = Thresholds {
+            warning_bytes: 100,
+            low_bytes: 10,
+        };
+        assert_eq!(space_for(1000, &thresholds), DiskSpace::Ok);
+        assert_eq!(space_for(100, &thresholds), DiskSpace::Ok);
+        assert_eq!(space_for(99, &thresholds), DiskSpace::Warning);
+        assert_eq!(space_for(10, &thresholds), DiskSpace::Warning);
+        assert_eq!(space_for(9, &thresholds), DiskSpace::Low);
+    }
+}
@@ -0,0 +1,68 @@
+use crate::internal::channel::Action;
+use anyhow::anyhow;
+use reqwest::StatusCode;
+
+// Marks an error that retrying won't fix, such as the server rejecting a
+// request outright
+#[derive(Debug)]
+pub struct Permanent(pub String);
+
+impl std::fmt::Display for Permanent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Permanent {}
+
+pub fn is_permanent_status(status: StatusCode) -> bool {
+    status.is_client_error()
+        && status != StatusCode::REQUEST_TIMEOUT
+        && status != StatusCode::TOO_MANY_REQUESTS
+}
+
+// An error for a response with a non-success status
+pub fn status_error(status: StatusCode, message: String) -> anyhow::Error {
+    if is_permanent_status(status) {
+        anyhow!(Permanent(message))
+    } else {
+        anyhow!(message)
+    }
+}
+
+pub fn is_permanent(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<Permanent>().is_some()
+}
+
+pub fn action_for(error: &anyhow::Error) -> Action {
+    if is_permanent(error) {
+        Action::DeadLetter
+    } else {
+        Action::Retry
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_permanent_status() {
+        assert!(is_permanent_status(StatusCode::BAD_REQUEST));
+        assert!(is_permanent_status(StatusCode::UNPROCESSABLE_ENTITY));
+        assert!(!is_permanent_status(StatusCode::REQUEST_TIMEOUT));
+        assert!(!is_permanent_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_permanent_status(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(!is_permanent_status(StatusCode::OK));
+    }
+
+    #[test]
+    fn test_action_for() {
+        let permanent = anyhow::anyhow!(Permanent("rejected".to_owned())).context("uploading");
+        assert!(matches!(action_for(&permanent), Action::DeadLetter));
+        assert!(matches!(
+            action_for(&anyhow::anyhow!("timed out")),
+            Action::Retry
+        ));
+    }
+}
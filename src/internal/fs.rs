@@ -8,6 +8,7 @@ use notify::{
     Config, Event as NotifyEvent, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
     event::{AccessKind, AccessMode},
 };
+use sha1::{Digest, Sha1};
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc;
@@ -82,18 +83,76 @@ pub fn full_extension(path: &Path) -> Option<&str> {
 pub fn remove_full_extension(path: &mut PathBuf) {
     if let Some(basename) = path.file_name() {
         let basename = basename.to_owned();
-        if let Some(extension) = full_extension(path) {
-            if let Some(basename) = basename.to_str() {
-                let stem_len = basename.len() - extension.len() - 1;
-                if stem_len > 0 {
-                    let stem = &basename[0..stem_len];
-                    path.set_file_name(stem);
-                }
+        if let Some(extension) = full_extension(path)
+            && let Some(basename) = basename.to_str()
+        {
+            let stem_len = basename.len() - extension.len() - 1;
+            if stem_len > 0 {
+                let stem = &basename[0..stem_len];
+                path.set_file_name(stem);
             }
         }
     }
 }
 
+pub fn recursive_files_in<P>(
+    directory: P,
+    min_depth: Option<usize>,
+) -> impl Iterator<Item = PathBuf>
+where
+    P: AsRef<std::path::Path>,
+{
+    let mut walker = walkdir::WalkDir::new(directory).sort_by_file_name();
+
+    if let Some(d) = min_depth {
+        walker = walker.min_depth(d);
+    }
+
+    walker
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+}
+
+pub fn modified_secs(path: &Path) -> Option<u64> {
+    let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+// When the device booted, if the OS exposes it in /proc/stat
+pub async fn boot_time() -> Option<u64> {
+    let stat = tokio::fs::read_to_string("/proc/stat").await.ok()?;
+    parse_boot_time(&stat)
+}
+
+fn parse_boot_time(stat: &str) -> Option<u64> {
+    stat.lines()
+        .find_map(|line| line.strip_prefix("btime "))
+        .and_then(|secs| secs.trim().parse().ok())
+}
+
+// Blocking, so call from spawn_blocking
+pub fn sha1_file(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha1::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+pub fn now_milli() -> String {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+        .to_string()
+}
+
+pub fn now_ymd() -> String {
+    let now: DateTime<Local> = Local::now();
+    now.format("%Y%m%d-%H%M%S").to_string()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -137,37 +196,14 @@ mod test {
         assert_eq!(t("..foo"), "..foo");
         assert_eq!(t("..f.oo"), "..f.oo");
     }
-}
 
-pub fn recursive_files_in<P>(
-    directory: P,
-    min_depth: Option<usize>,
-) -> impl Iterator<Item = PathBuf>
-where
-    P: AsRef<std::path::Path>,
-{
-    let mut walker = walkdir::WalkDir::new(directory).sort_by_file_name();
-
-    if let Some(d) = min_depth {
-        walker = walker.min_depth(d);
+    #[test]
+    fn test_parse_boot_time() {
+        assert_eq!(
+            parse_boot_time("cpu  1 2 3\nintr 5\nbtime 1700000000\nprocesses 9\n"),
+            Some(1700000000),
+        );
+        assert_eq!(parse_boot_time("cpu  1 2 3\n"), None);
+        assert_eq!(parse_boot_time("btime soon\n"), None);
     }
-
-    walker
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| e.file_type().is_file())
-        .map(|e| e.into_path())
-}
-
-pub fn now_milli() -> String {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_millis()
-        .to_string()
-}
-
-pub fn now_ymd() -> String {
-    let now: DateTime<Local> = Local::now();
-    now.format("%Y%m%d-%H%M%S").to_string()
 }
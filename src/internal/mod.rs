@@ -1,6 +1,10 @@
 pub mod channel;
+pub mod disk;
+pub mod failure;
 pub mod fs;
+pub mod idempotency;
 pub mod notifier;
 pub mod online;
 pub mod requester;
+pub mod store;
 pub mod uploader;
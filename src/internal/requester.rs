@@ -1,46 +1,97 @@
-use crate::internal::{notifier::Notifier, online::Online};
+use crate::internal::{
+    failure::{Permanent, is_permanent_status},
+    notifier::Notifier,
+    online::Online,
+};
 use anyhow::{Result, anyhow};
 use serde::{Serialize, de::DeserializeOwned};
 use std::{future::Future, time::Duration};
 
 pub trait Requester: Notifier + Online {
-    fn request<Req, Res>(
+    fn request_timeout(&self) -> Duration;
+
+    // Returns the response whatever its status. GETs have no body
+    fn fetch<Req>(
         &self,
         url: &str,
         method: &reqwest::Method,
-        request: Req,
-    ) -> impl Future<Output = Result<Res>> + Send
+        headers: &[(&str, &str)],
+        request: &Req,
+    ) -> impl Future<Output = Result<reqwest::Response>> + Send
     where
         Self: Sync,
-        Req: Serialize + std::fmt::Debug + Send,
-        Res: DeserializeOwned,
+        Req: Serialize + std::fmt::Debug + Send + Sync,
     {
         async move {
-            let builder = reqwest::ClientBuilder::new().timeout(Duration::from_secs(10));
+            let builder = reqwest::ClientBuilder::new().timeout(self.request_timeout());
             let client = builder.build()?;
 
-            let res = match client
-                .request(method.clone(), url)
-                .json(&request)
-                .send()
-                .await
-            {
-                Ok(res) => res,
+            let mut builder = client.request(method.clone(), url);
+            for (name, value) in headers {
+                builder = builder.header(*name, *value);
+            }
+            if *method != reqwest::Method::GET {
+                builder = builder.json(request);
+            }
+
+            match builder.send().await {
+                Ok(res) => {
+                    self.observed_online();
+                    Ok(res)
+                }
                 Err(e) => {
                     self.observed_error(&e);
-                    return Err(anyhow!(e));
+                    Err(anyhow!(e))
                 }
-            };
+            }
+        }
+    }
 
-            self.observed_online();
+    // Non-success statuses are errors, permanent if retrying won't help
+    fn send<Req>(
+        &self,
+        url: &str,
+        method: &reqwest::Method,
+        headers: &[(&str, &str)],
+        request: Req,
+    ) -> impl Future<Output = Result<reqwest::Response>> + Send
+    where
+        Self: Sync,
+        Req: Serialize + std::fmt::Debug + Send + Sync,
+    {
+        async move {
+            let res = self.fetch(url, method, headers, &request).await?;
 
             if !res.status().is_success() {
-                return Err(anyhow!(
+                let message = format!(
                     "Error {method:?}ing {url:?} with {request:?}: got status code {}",
                     res.status()
-                ));
+                );
+                if is_permanent_status(res.status()) {
+                    return Err(anyhow!(Permanent(message)));
+                }
+                return Err(anyhow!(message));
             }
 
+            Ok(res)
+        }
+    }
+
+    fn request<Req, Res>(
+        &self,
+        url: &str,
+        method: &reqwest::Method,
+        headers: &[(&str, &str)],
+        request: Req,
+    ) -> impl Future<Output = Result<Res>> + Send
+    where
+        Self: Sync,
+        Req: Serialize + std::fmt::Debug + Send + Sync,
+        Res: DeserializeOwned,
+    {
+        async move {
+            let res = self.send(url, method, headers, request).await?;
+
             match res.json().await {
                 Ok(j) => Ok(j),
                 Err(e) => Err(anyhow!(e)),
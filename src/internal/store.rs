@@ -0,0 +1,189 @@
+use crate::internal::fs::{full_extension, now_milli, sha1_file};
+use anyhow::Result;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use tokio::fs::{copy, create_dir_all, metadata, read_dir, remove_file, rename, try_exists};
+use tracing::warn;
+
+// Where keep_saves stores each distinct save once, named by its SHA1. The
+// timestamped saves in each game's directory are hardlinks to these
+pub const OBJECTS_DIRECTORY: &str = ".objects";
+
+// Saves being copied into the store are named with this prefix until they're
+// hashed
+const INCOMING_PREFIX: &str = "incoming-";
+
+pub fn object_path(keep_saves: &Path, digest: &str) -> PathBuf {
+    keep_saves
+        .join(OBJECTS_DIRECTORY)
+        .join(&digest[..2.min(digest.len())])
+        .join(digest)
+}
+
+// Copies the save into the store, unless an identical one is already there,
+// returning its object and digest. The copy is hashed rather than the
+// original, in case RetroArch writes to it again in the meantime
+pub async fn store(keep_saves: &Path, source: &Path) -> Result<(PathBuf, String)> {
+    let objects = keep_saves.join(OBJECTS_DIRECTORY);
+    create_dir_all(&objects).await?;
+
+    let incoming = objects.join(format!("{INCOMING_PREFIX}{}", now_milli()));
+    let res = store_incoming(keep_saves, source, &incoming).await;
+    if res.is_err()
+        && let Err(e) = remove_file(&incoming).await
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        warn!("Could not remove {incoming:?}: {e:?}");
+    }
+    res
+}
+
+async fn store_incoming(
+    keep_saves: &Path,
+    source: &Path,
+    incoming: &Path,
+) -> Result<(PathBuf, String)> {
+    copy(source, incoming).await?;
+
+    let digest = {
+        let incoming = incoming.to_owned();
+        tokio::task::spawn_blocking(move || sha1_file(&incoming)).await??
+    };
+
+    let object = object_path(keep_saves, &digest);
+    if try_exists(&object).await? {
+        remove_file(incoming).await?;
+    } else {
+        if let Some(parent) = object.parent() {
+            create_dir_all(parent).await?;
+        }
+        rename(incoming, &object).await?;
+    }
+
+    Ok((object, digest))
+}
+
+// Copies left behind by a store that never finished, e.g. when study-sync
+// was killed partway through. Returns how many were removed
+pub async fn remove_incoming(keep_saves: &Path) -> Result<usize> {
+    let mut entries = match read_dir(keep_saves.join(OBJECTS_DIRECTORY)).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut removed = 0;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.starts_with(INCOMING_PREFIX))
+        {
+            remove_file(entry.path()).await?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+// Whether the newest save in directory with this extension is a link to
+// object, i.e. the game's last save was identical. Timestamped names sort
+// chronologically
+pub async fn is_latest(directory: &Path, extension: &str, object: &Path) -> Result<bool> {
+    let mut latest: Option<PathBuf> = None;
+    let mut entries = match read_dir(directory).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if full_extension(&path) == Some(extension)
+            && latest
+                .as_ref()
+                .is_none_or(|l| path.file_name() > l.file_name())
+        {
+            latest = Some(path);
+        }
+    }
+
+    let Some(latest) = latest else {
+        return Ok(false);
+    };
+    let (latest, object) = (metadata(&latest).await?, metadata(object).await?);
+    Ok(latest.dev() == object.dev() && latest.ino() == object.ino())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_object_path() {
+        assert_eq!(
+            object_path(
+                Path::new("keep"),
+                "784cf2c9544a49e6a743a33641213a431e85c182"
+            ),
+            Path::new("keep/.objects/78/784cf2c9544a49e6a743a33641213a431e85c182"),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remove_incoming() {
+        let keep_saves = std::env::temp_dir().join(format!(
+            "study-sync-test-{}",
+            crate::internal::idempotency::new_key()
+        ));
+        let objects = keep_saves.join(OBJECTS_DIRECTORY);
+        assert_eq!(remove_incoming(&keep_saves).await.unwrap(), 0);
+
+        let source = keep_saves.join("save.srm");
+        create_dir_all(&objects).await.unwrap();
+        tokio::fs::write(&source, b"save").await.unwrap();
+        let (object, _) = store(&keep_saves, &source).await.unwrap();
+        tokio::fs::write(objects.join("incoming-1"), b"partial")
+            .await
+            .unwrap();
+
+        // A store that fails leaves nothing behind
+        assert!(
+            store(&keep_saves, &keep_saves.join("missing.srm"))
+                .await
+                .is_err()
+        );
+        assert_eq!(remove_incoming(&keep_saves).await.unwrap(), 1);
+        assert_eq!(remove_incoming(&keep_saves).await.unwrap(), 0);
+        assert!(try_exists(&object).await.unwrap());
+
+        tokio::fs::remove_dir_all(&keep_saves).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_store_deduplicates() {
+        let keep_saves = std::env::temp_dir().join(format!(
+            "study-sync-test-{}",
+            crate::internal::idempotency::new_key()
+        ));
+        create_dir_all(&keep_saves).await.unwrap();
+        for (name, contents) in [("a.srm", "save"), ("b.srm", "save"), ("c.srm", "other")] {
+            tokio::fs::write(keep_saves.join(name), contents)
+                .await
+                .unwrap();
+        }
+
+        let (a, a_digest) = store(&keep_saves, &keep_saves.join("a.srm")).await.unwrap();
+        let (b, b_digest) = store(&keep_saves, &keep_saves.join("b.srm")).await.unwrap();
+        let (c, _) = store(&keep_saves, &keep_saves.join("c.srm")).await.unwrap();
+        assert_eq!((&a, &a_digest), (&b, &b_digest));
+        assert_eq!(a, object_path(&keep_saves, &a_digest));
+        assert_ne!(a, c);
+        assert_eq!(
+            crate::internal::fs::recursive_files_in(keep_saves.join(OBJECTS_DIRECTORY), None)
+                .count(),
+            2
+        );
+
+        tokio::fs::remove_dir_all(&keep_saves).await.unwrap();
+    }
+}
@@ -1,42 +1,92 @@
-use crate::internal::{notifier::Notifier, online::Online};
-use anyhow::{Result, anyhow};
-use reqwest::Body;
-use sha1::{Digest, Sha1};
-use std::{
-    future::Future,
-    path::{Path, PathBuf},
-    time::Duration,
+use crate::{
+    internal::{failure::status_error, fs::sha1_file, notifier::Notifier, online::Online},
+    orchestrator::{self, Upload},
 };
-use tokio::fs::File;
+use anyhow::{Result, anyhow};
+use reqwest::{Body, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, future::Future, io::SeekFrom, path::Path, time::Duration};
+use tokio::fs::{File, create_dir_all, metadata, rename};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tokio_util::codec::{BytesCodec, FramedRead};
-use tracing::info;
+use tracing::{info, warn};
+
+// Files larger than a chunk are sent in chunks through an upload session, if
+// the server supports them: a POST to {base_url}/_uploads starts a session,
+// each chunk is a PUT to {base_url}/_uploads/{session} with a Content-Range,
+// and a GET there gives the offset the server has received up to. Each
+// response to these is JSON with the session's "session" id and/or "offset".
+// Servers declare support with an X-Study-Sessions header in response to an
+// OPTIONS of {base_url}/_uploads
+pub struct Resumable {
+    chunk_size: u64,
+    // Unknown until the server is first asked
+    supported: Option<bool>,
+    // Sessions started or resumed since startup, and the offset each last
+    // acknowledged, by upload id. Newer than Upload's session, which is only
+    // loaded from the database at startup
+    sessions: HashMap<i64, (String, u64)>,
+}
+
+impl Resumable {
+    pub fn new(chunk_size: u64) -> Resumable {
+        Resumable {
+            chunk_size,
+            supported: None,
+            sessions: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SessionResponse {
+    session: Option<String>,
+    offset: Option<u64>,
+}
+
+pub enum Chunked {
+    Uploaded,
+    NoSession,
+}
+
+// Before uploading, a HEAD of {base_url}/{directory}?digest={digest} asks
+// whether the server already has the file. It does if the response is a
+// success with an X-Study-Digest header of that same digest
+#[derive(Default)]
+pub struct Digests {
+    // Calculated since startup, by upload id. Each is also recorded in the
+    // database, and so loaded into Upload's digest after a restart
+    by_upload: HashMap<i64, String>,
+    // Unknown until the server is first asked
+    lookup_supported: Option<bool>,
+}
 
 pub trait Uploader: Notifier + Send + Online {
-    fn get_digest_cache(&self) -> &Option<(PathBuf, String)>;
-    fn set_digest_cache(&mut self, cache: Option<(PathBuf, String)>);
+    fn digests(&mut self) -> &mut Digests;
+    fn resumable(&mut self) -> &mut Resumable;
+    fn upload_timeout(&self) -> Duration;
+    fn failed_directory(&self) -> &Path;
 
-    fn digest_for_path(&mut self, path: &Path) -> impl Future<Output = Option<String>> + Send {
+    fn digest_for(&mut self, upload: &Upload) -> impl Future<Output = Option<String>> + Send {
         async move {
-            if let Some((p, d)) = self.get_digest_cache() {
-                if p == path {
-                    return Some(d.clone());
-                }
+            if let Some(digest) = &upload.digest {
+                return Some(digest.clone());
+            }
+            if let Some(digest) = self.digests().by_upload.get(&upload.id) {
+                return Some(digest.clone());
             }
 
+            let path = &upload.path;
             let res = {
                 let path = path.to_owned();
-                tokio::task::spawn_blocking(move || -> Result<String> {
-                    let mut file = std::fs::File::open(path)?;
-                    let mut hasher = Sha1::new();
-                    std::io::copy(&mut file, &mut hasher)?;
-                    Ok(hex::encode(hasher.finalize()))
-                })
+                tokio::task::spawn_blocking(move || sha1_file(&path))
             }
             .await;
 
             match res {
                 Ok(Ok(digest)) => {
-                    self.set_digest_cache(Some((path.to_path_buf(), digest.clone())));
+                    self.digests().by_upload.insert(upload.id, digest.clone());
+                    self.report_upload_digest(upload, &digest);
                     Some(digest)
                 }
                 Ok(Err(e)) => {
@@ -53,14 +103,404 @@ pub trait Uploader: Notifier + Send + Online {
         }
     }
 
+    fn report_upload_digest(&self, upload: &Upload, digest: &str) {
+        let event = orchestrator::Event::UploadDigest {
+            upload_id: upload.id,
+            digest: digest.to_owned(),
+        };
+        if let Err(e) = self.orchestrator_tx().send(event) {
+            self.notify_error(&format!("Could not send to orchestrator: {e:?}"));
+        }
+    }
+
+    fn report_upload_finished(&mut self, upload: &Upload) {
+        self.digests().by_upload.remove(&upload.id);
+        let event = orchestrator::Event::UploadFinished {
+            upload_id: upload.id,
+        };
+        if let Err(e) = self.orchestrator_tx().send(event) {
+            self.notify_error(&format!("Could not send to orchestrator: {e:?}"));
+        }
+    }
+
+    fn report_upload_progress(&self, upload: &Upload, session: &str, offset: u64) {
+        let event = orchestrator::Event::UploadProgress {
+            upload_id: upload.id,
+            session: session.to_owned(),
+            offset,
+        };
+        if let Err(e) = self.orchestrator_tx().send(event) {
+            self.notify_error(&format!("Could not send to orchestrator: {e:?}"));
+        }
+    }
+
+    fn report_upload_failed(&self, upload: &Upload, error: &anyhow::Error) {
+        let event = orchestrator::Event::UploadFailed {
+            upload_id: upload.id,
+            error: format!("{error:#}"),
+        };
+        if let Err(e) = self.orchestrator_tx().send(event) {
+            self.notify_error(&format!("Could not send to orchestrator: {e:?}"));
+        }
+    }
+
+    fn dead_letter_upload(&self, upload: &Upload) -> impl Future<Output = ()> + Send
+    where
+        Self: Sync,
+    {
+        async move {
+            let path = &upload.path;
+            let mut failed_path = self.failed_directory().join(&upload.directory);
+            if let Some(basename) = path.file_name() {
+                failed_path.push(basename);
+            }
+
+            if let Some(parent) = failed_path.parent()
+                && let Err(e) = create_dir_all(parent).await
+            {
+                self.notify_error(&format!("Could not create {parent:?}: {e:?}"));
+                return;
+            }
+
+            if let Err(e) = rename(path, &failed_path).await {
+                self.notify_error(&format!(
+                    "Could not move failed upload {path:?} to {failed_path:?}: {e:?}"
+                ));
+                return;
+            }
+
+            self.notify_error(&format!(
+                "Gave up uploading {path:?}, moved to {failed_path:?}"
+            ));
+
+            let event = orchestrator::Event::UploadDeadLettered {
+                upload_id: upload.id,
+                failed_path,
+            };
+            if let Err(e) = self.orchestrator_tx().send(event) {
+                self.notify_error(&format!("Could not send to orchestrator: {e:?}"));
+            }
+        }
+    }
+
+    fn send_upload_request(
+        &mut self,
+        req: reqwest::RequestBuilder,
+    ) -> impl Future<Output = Result<reqwest::Response>> + Send {
+        async move {
+            match req.send().await {
+                Ok(res) => {
+                    self.observed_online();
+                    Ok(res)
+                }
+                Err(e) => {
+                    self.observed_error(&e);
+                    Err(anyhow!(e))
+                }
+            }
+        }
+    }
+
+    // The offset the server has received up to, or None if it no longer
+    // has the session
+    fn session_offset(
+        &mut self,
+        sessions_url: &str,
+        session: &str,
+    ) -> impl Future<Output = Result<Option<u64>>> + Send {
+        async move {
+            let url = format!("{sessions_url}/{session}");
+            let client = reqwest::ClientBuilder::new()
+                .timeout(self.upload_timeout())
+                .build()?;
+            let res = self.send_upload_request(client.get(&url)).await?;
+
+            let status = res.status();
+            if status == StatusCode::NOT_FOUND || status == StatusCode::GONE {
+                return Ok(None);
+            }
+            if !status.is_success() {
+                return Err(status_error(
+                    status,
+                    format!("Failed to get upload session {url:?}: got status code {status}"),
+                ));
+            }
+
+            match res.json::<SessionResponse>().await? {
+                SessionResponse {
+                    offset: Some(offset),
+                    ..
+                } => Ok(Some(offset)),
+                res => Err(anyhow!("No offset for upload session {url:?}: {res:?}")),
+            }
+        }
+    }
+
+    // Asked once, since nothing else would change the answer. Servers without
+    // sessions may answer an OPTIONS of any path, so only the header counts
+    fn sessions_supported(&mut self, base_url: &str) -> impl Future<Output = Result<bool>> + Send {
+        async move {
+            if let Some(supported) = self.resumable().supported {
+                return Ok(supported);
+            }
+
+            let url = format!("{base_url}/_uploads");
+            let client = reqwest::ClientBuilder::new()
+                .timeout(self.upload_timeout())
+                .build()?;
+            let res = self
+                .send_upload_request(client.request(reqwest::Method::OPTIONS, &url))
+                .await?;
+
+            let supported =
+                res.status().is_success() && res.headers().contains_key("X-Study-Sessions");
+            if !supported {
+                info!("{base_url:?} doesn't support upload sessions, uploading whole files");
+            }
+            self.resumable().supported = Some(supported);
+            Ok(supported)
+        }
+    }
+
+    // None if the server answers with anything but a session
+    fn start_session(
+        &mut self,
+        sessions_url: &str,
+        upload: &Upload,
+        directory: &str,
+        content_type: Option<&str>,
+        length: u64,
+    ) -> impl Future<Output = Result<Option<String>>> + Send {
+        async move {
+            #[derive(Debug, Serialize)]
+            struct Request<'a> {
+                directory: &'a str,
+                basename: &'a str,
+                length: u64,
+                digest: Option<String>,
+                content_type: Option<&'a str>,
+            }
+
+            let path = &upload.path;
+            let request = Request {
+                directory,
+                basename: path
+                    .file_name()
+                    .and_then(std::ffi::OsStr::to_str)
+                    .unwrap_or(""),
+                length,
+                digest: self.digest_for(upload).await,
+                content_type,
+            };
+
+            let client = reqwest::ClientBuilder::new()
+                .timeout(self.upload_timeout())
+                .build()?;
+            let res = self
+                .send_upload_request(client.post(sessions_url).json(&request))
+                .await?;
+
+            let status = res.status();
+            if !status.is_success() {
+                warn!(
+                    "Could not start upload session for {path:?} using {sessions_url:?}: got status code {status}"
+                );
+                return Ok(None);
+            }
+
+            let body = res.text().await?;
+            Ok(serde_json::from_str::<SessionResponse>(&body)
+                .ok()
+                .and_then(|r| r.session))
+        }
+    }
+
+    fn upload_in_chunks(
+        &mut self,
+        base_url: &str,
+        upload: &Upload,
+        directory: &str,
+        content_type: Option<&str>,
+        length: u64,
+    ) -> impl Future<Output = Result<Chunked>> + Send {
+        async move {
+            let path = &upload.path;
+            let sessions_url = format!("{base_url}/_uploads");
+
+            let mut resumed = None;
+            let known = self
+                .resumable()
+                .sessions
+                .get(&upload.id)
+                .cloned()
+                .or_else(|| {
+                    let session = upload.session.clone()?;
+                    Some((session, upload.session_offset.unwrap_or(0)))
+                });
+            if let Some((session, acknowledged)) = known {
+                // The server can't have less than it acknowledged, unless it
+                // lost what it received, so then starts over too
+                match self.session_offset(&sessions_url, &session).await? {
+                    Some(offset) if offset < acknowledged || offset > length => warn!(
+                        "Upload session {session} for {path:?} is at {offset} of {length} bytes after acknowledging {acknowledged}, starting over"
+                    ),
+                    Some(offset) => {
+                        info!("Resuming upload of {path:?} at {offset} of {length} bytes");
+                        resumed = Some((session, offset));
+                    }
+                    None => warn!("Upload session {session} for {path:?} expired, starting over"),
+                }
+            }
+
+            let (session, mut offset) = match resumed {
+                Some(resumed) => resumed,
+                None => {
+                    let started = self
+                        .start_session(&sessions_url, upload, directory, content_type, length)
+                        .await?;
+                    let Some(session) = started else {
+                        return Ok(Chunked::NoSession);
+                    };
+                    (session, 0)
+                }
+            };
+            self.resumable()
+                .sessions
+                .insert(upload.id, (session.clone(), offset));
+            self.report_upload_progress(upload, &session, offset);
+
+            let url = format!("{sessions_url}/{session}");
+            let client = reqwest::ClientBuilder::new()
+                .timeout(self.upload_timeout())
+                .build()?;
+            let mut file = File::open(path).await?;
+            let chunk_size = self.resumable().chunk_size;
+
+            while offset < length {
+                let end = length.min(offset + chunk_size);
+                let mut chunk = vec![0; (end - offset) as usize];
+                file.seek(SeekFrom::Start(offset)).await?;
+                file.read_exact(&mut chunk).await?;
+
+                let req = client
+                    .put(&url)
+                    .header(
+                        reqwest::header::CONTENT_RANGE,
+                        format!("bytes {offset}-{}/{length}", end - 1),
+                    )
+                    .body(chunk);
+                let res = self.send_upload_request(req).await?;
+
+                let status = res.status();
+                if status == StatusCode::NOT_FOUND || status == StatusCode::GONE {
+                    // Retrying starts a new session
+                    self.resumable().sessions.remove(&upload.id);
+                    return Err(anyhow!("Upload session {url:?} for {path:?} expired"));
+                }
+                if !status.is_success() {
+                    return Err(status_error(
+                        status,
+                        format!(
+                            "Failed to upload {path:?} bytes {offset}-{end} using {url:?}: got status code {status}"
+                        ),
+                    ));
+                }
+
+                let acknowledged = match res.json::<SessionResponse>().await? {
+                    SessionResponse {
+                        offset: Some(acknowledged),
+                        ..
+                    } => acknowledged,
+                    res => return Err(anyhow!("No offset uploading {path:?} to {url:?}: {res:?}")),
+                };
+                if acknowledged <= offset || acknowledged > length {
+                    return Err(anyhow!(
+                        "Upload session {url:?} acknowledged {acknowledged} of {length} bytes after {offset}"
+                    ));
+                }
+
+                offset = acknowledged;
+                self.resumable()
+                    .sessions
+                    .insert(upload.id, (session.clone(), offset));
+                self.report_upload_progress(upload, &session, offset);
+            }
+
+            self.resumable().sessions.remove(&upload.id);
+            info!("Successfully uploaded {path:?} in chunks to {url:?}");
+            Ok(Chunked::Uploaded)
+        }
+    }
+
+    fn server_has(
+        &mut self,
+        base_url: &str,
+        directory: &str,
+        digest: &str,
+    ) -> impl Future<Output = Result<bool>> + Send {
+        async move {
+            let url = format!("{base_url}/{directory}?digest={digest}");
+            let client = reqwest::ClientBuilder::new()
+                .timeout(self.upload_timeout())
+                .build()?;
+            let res = self.send_upload_request(client.head(&url)).await?;
+
+            let status = res.status();
+            if matches!(
+                status,
+                StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+            ) {
+                info!("{base_url:?} doesn't support looking up digests, uploading every file");
+                self.digests().lookup_supported = Some(false);
+                return Ok(false);
+            }
+            if status.is_success() {
+                self.digests().lookup_supported = Some(true);
+            }
+
+            // Anything else, including a server error, means uploading as usual
+            Ok(status.is_success()
+                && res
+                    .headers()
+                    .get("X-Study-Digest")
+                    .is_some_and(|d| d.as_bytes().eq_ignore_ascii_case(digest.as_bytes())))
+        }
+    }
+
     fn upload_path_to_directory(
         &mut self,
         base_url: &str,
-        path: &Path,
+        upload: &Upload,
         directory: &str,
         content_type: Option<&str>,
     ) -> impl Future<Output = Result<()>> + Send {
         async move {
+            let path = &upload.path;
+
+            let digest = self.digest_for(upload).await;
+            if let Some(digest) = &digest
+                && self.digests().lookup_supported != Some(false)
+                && self.server_has(base_url, directory, digest).await?
+            {
+                info!("{base_url:?} already has {path:?} in {directory:?}, skipping upload");
+                return Ok(());
+            }
+
+            let length = metadata(path).await?.len();
+            if length > self.resumable().chunk_size && self.sessions_supported(base_url).await? {
+                match self
+                    .upload_in_chunks(base_url, upload, directory, content_type, length)
+                    .await?
+                {
+                    Chunked::Uploaded => return Ok(()),
+                    Chunked::NoSession => {
+                        info!(
+                            "{base_url:?} didn't start an upload session, uploading {path:?} whole"
+                        );
+                    }
+                }
+            }
+
             let mut url = format!("{base_url}/{directory}");
 
             let basename = path
@@ -68,7 +508,7 @@ pub trait Uploader: Notifier + Send + Online {
                 .and_then(std::ffi::OsStr::to_str)
                 .unwrap_or("");
 
-            if let Some(digest) = self.digest_for_path(path).await {
+            if let Some(digest) = &digest {
                 let param = format!("?digest={digest}");
                 url.push_str(&param);
             }
@@ -77,7 +517,7 @@ pub trait Uploader: Notifier + Send + Online {
             let stream = FramedRead::new(file, BytesCodec::new());
             let body = Body::wrap_stream(stream);
 
-            let builder = reqwest::ClientBuilder::new().timeout(Duration::from_secs(30));
+            let builder = reqwest::ClientBuilder::new().timeout(self.upload_timeout());
             let client = builder.build()?;
 
             let mut req = client
@@ -89,22 +529,15 @@ pub trait Uploader: Notifier + Send + Online {
                 req = req.header(reqwest::header::CONTENT_TYPE, content_type);
             }
 
-            let res = match req.send().await {
-                Ok(res) => res,
-                Err(e) => {
-                    self.observed_error(&e);
-                    return Err(anyhow!(e));
-                }
-            };
-
-            self.observed_online();
+            let res = self.send_upload_request(req).await?;
 
-            if !res.status().is_success() {
-                return Err(anyhow!(
-                    "Failed to upload {path:?} using {url:?}: got status code {}, body {:?}",
-                    res.status(),
+            let status = res.status();
+            if !status.is_success() {
+                let message = format!(
+                    "Failed to upload {path:?} using {url:?}: got status code {status}, body {:?}",
                     res.text().await
-                ));
+                );
+                return Err(status_error(status, message));
             }
 
             let message = res.text().await?;
@@ -114,3 +547,480 @@ pub trait Uploader: Notifier + Send + Online {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{internal::idempotency::new_key, notify, orchestrator::UploadKind};
+    use axum::{
+        Json, Router,
+        body::Bytes,
+        extract::State,
+        http::{HeaderMap, Method, Uri},
+        response::{IntoResponse, Response},
+    };
+    use serde_json::json;
+    use std::{
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    };
+    use tokio::sync::mpsc;
+
+    // A server with at most one upload session, s1, or without sessions at
+    // all, accepting whatever it's sent like an ordinary upload
+    #[derive(Default)]
+    struct Server {
+        sessions: bool,
+        // Claims to support sessions, but won't start any
+        refuses_sessions: bool,
+        // The response to a HEAD, with any X-Study-Digest, rather than 404
+        head: Option<(StatusCode, Option<String>)>,
+        requests: Vec<String>,
+        // The query of each HEAD
+        looked_up: Vec<String>,
+        session: Vec<u8>,
+        whole: Vec<u8>,
+    }
+
+    async fn handle(
+        State(server): State<Arc<Mutex<Server>>>,
+        method: Method,
+        uri: Uri,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> Response {
+        let mut server = server.lock().unwrap();
+        server.requests.push(format!("{method} {}", uri.path()));
+
+        match (&method, uri.path()) {
+            (&Method::HEAD, _) => {
+                server.looked_up.push(uri.query().unwrap_or("").to_owned());
+                match &server.head {
+                    Some((status, Some(digest))) => {
+                        (*status, [("X-Study-Digest", digest.clone())]).into_response()
+                    }
+                    Some((status, None)) => status.into_response(),
+                    None => StatusCode::NOT_FOUND.into_response(),
+                }
+            }
+            (&Method::OPTIONS, "/_uploads") if server.sessions || server.refuses_sessions => {
+                [("X-Study-Sessions", "1")].into_response()
+            }
+            (&Method::POST, "/_uploads") if server.refuses_sessions => {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response()
+            }
+            (&Method::POST, "/_uploads") if server.sessions => {
+                server.session.clear();
+                Json(json!({ "session": "s1" })).into_response()
+            }
+            (&Method::GET, "/_uploads/s1") if server.sessions => {
+                Json(json!({ "offset": server.session.len() })).into_response()
+            }
+            (&Method::PUT, "/_uploads/s1") if server.sessions => {
+                let start: usize = headers["Content-Range"]
+                    .to_str()
+                    .unwrap()
+                    .trim_start_matches("bytes ")
+                    .split('-')
+                    .next()
+                    .unwrap()
+                    .parse()
+                    .unwrap();
+                if start != server.session.len() {
+                    return StatusCode::CONFLICT.into_response();
+                }
+                server.session.extend_from_slice(&body);
+                Json(json!({ "offset": server.session.len() })).into_response()
+            }
+            _ => {
+                if method == Method::POST {
+                    server.whole = body.to_vec();
+                }
+                "ok".into_response()
+            }
+        }
+    }
+
+    async fn serve(server: Server) -> (String, Arc<Mutex<Server>>) {
+        let server = Arc::new(Mutex::new(server));
+        let app = Router::new().fallback(handle).with_state(server.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, server)
+    }
+
+    struct TestUploader {
+        digests: Digests,
+        resumable: Resumable,
+        notify_tx: mpsc::UnboundedSender<notify::Event>,
+        orchestrator_tx: mpsc::UnboundedSender<orchestrator::Event>,
+        failed_directory: PathBuf,
+    }
+
+    impl Notifier for TestUploader {
+        fn notify_tx(&self) -> &mpsc::UnboundedSender<notify::Event> {
+            &self.notify_tx
+        }
+    }
+
+    impl Online for TestUploader {
+        fn orchestrator_tx(&self) -> &mpsc::UnboundedSender<orchestrator::Event> {
+            &self.orchestrator_tx
+        }
+
+        fn is_online(&self) -> bool {
+            true
+        }
+    }
+
+    impl Uploader for TestUploader {
+        fn digests(&mut self) -> &mut Digests {
+            &mut self.digests
+        }
+
+        fn resumable(&mut self) -> &mut Resumable {
+            &mut self.resumable
+        }
+
+        fn upload_timeout(&self) -> Duration {
+            Duration::from_secs(10)
+        }
+
+        fn failed_directory(&self) -> &Path {
+            &self.failed_directory
+        }
+    }
+
+    // An uploader with 1 KiB chunks, the receiving end of its events to the
+    // orchestrator, and an upload of a 2500 byte file
+    fn uploader() -> (
+        TestUploader,
+        mpsc::UnboundedReceiver<orchestrator::Event>,
+        Upload,
+        Vec<u8>,
+    ) {
+        let (notify_tx, _) = mpsc::unbounded_channel();
+        let (orchestrator_tx, orchestrator_rx) = mpsc::unbounded_channel();
+        let directory = std::env::temp_dir().join(format!("study-sync-test-{}", new_key()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let contents: Vec<u8> = (0..2500u32).map(|i| i as u8).collect();
+        let path = directory.join("save.srm");
+        std::fs::write(&path, &contents).unwrap();
+
+        let uploader = TestUploader {
+            digests: Digests::default(),
+            resumable: Resumable::new(1024),
+            notify_tx,
+            orchestrator_tx,
+            failed_directory: directory.join("failed"),
+        };
+        let upload = Upload {
+            id: 1,
+            kind: UploadKind::Save,
+            path,
+            directory: "saves".to_owned(),
+            game: None,
+            attempts: 0,
+            session: None,
+            session_offset: None,
+            digest: None,
+        };
+        (uploader, orchestrator_rx, upload, contents)
+    }
+
+    fn progress(orchestrator_rx: &mut mpsc::UnboundedReceiver<orchestrator::Event>) -> Vec<u64> {
+        let mut offsets = Vec::new();
+        while let Ok(event) = orchestrator_rx.try_recv() {
+            if let orchestrator::Event::UploadProgress { offset, .. } = event {
+                offsets.push(offset);
+            }
+        }
+        offsets
+    }
+
+    #[tokio::test]
+    async fn test_upload_in_chunks() {
+        let (url, server) = serve(Server {
+            sessions: true,
+            ..Server::default()
+        })
+        .await;
+        let (mut uploader, mut orchestrator_rx, upload, contents) = uploader();
+
+        uploader
+            .upload_path_to_directory(&url, &upload, "saves", None)
+            .await
+            .unwrap();
+
+        let server = server.lock().unwrap();
+        assert_eq!(server.session, contents);
+        assert!(server.whole.is_empty());
+        assert_eq!(
+            server.requests[1..],
+            [
+                "OPTIONS /_uploads",
+                "POST /_uploads",
+                "PUT /_uploads/s1",
+                "PUT /_uploads/s1",
+                "PUT /_uploads/s1"
+            ]
+        );
+        assert_eq!(progress(&mut orchestrator_rx), [0, 1024, 2048, 2500]);
+        std::fs::remove_dir_all(upload.path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_upload_without_sessions() {
+        let (url, server) = serve(Server::default()).await;
+        let (mut uploader, _orchestrator_rx, upload, contents) = uploader();
+
+        for _ in 0..2 {
+            uploader
+                .upload_path_to_directory(&url, &upload, "saves", None)
+                .await
+                .unwrap();
+        }
+
+        // Only asked once, and nothing's sent to _uploads but the question
+        let server = server.lock().unwrap();
+        assert_eq!(server.whole, contents);
+        assert_eq!(
+            server
+                .requests
+                .iter()
+                .filter(|r| !r.starts_with("HEAD"))
+                .collect::<Vec<_>>(),
+            ["OPTIONS /_uploads", "POST /saves", "POST /saves"]
+        );
+        std::fs::remove_dir_all(upload.path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_upload_when_session_refused() {
+        let (url, server) = serve(Server {
+            refuses_sessions: true,
+            ..Server::default()
+        })
+        .await;
+        let (mut uploader, _orchestrator_rx, upload, contents) = uploader();
+
+        uploader
+            .upload_path_to_directory(&url, &upload, "saves", None)
+            .await
+            .unwrap();
+
+        let server = server.lock().unwrap();
+        assert_eq!(server.whole, contents);
+        assert_eq!(
+            server.requests[1..],
+            ["OPTIONS /_uploads", "POST /_uploads", "POST /saves"]
+        );
+        std::fs::remove_dir_all(upload.path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume_upload() {
+        let (mut first, mut orchestrator_rx, upload, contents) = uploader();
+        let upload = Upload {
+            session: Some("s1".to_owned()),
+            session_offset: Some(1024),
+            ..upload
+        };
+
+        // From before a restart
+        let (url, server) = serve(Server {
+            sessions: true,
+            session: contents[..1024].to_vec(),
+            ..Server::default()
+        })
+        .await;
+        first
+            .upload_path_to_directory(&url, &upload, "saves", None)
+            .await
+            .unwrap();
+        {
+            let server = server.lock().unwrap();
+            assert_eq!(server.session, contents);
+            assert_eq!(
+                server.requests[1..],
+                [
+                    "OPTIONS /_uploads",
+                    "GET /_uploads/s1",
+                    "PUT /_uploads/s1",
+                    "PUT /_uploads/s1"
+                ]
+            );
+        }
+        assert_eq!(progress(&mut orchestrator_rx), [1024, 2048, 2500]);
+
+        // A server that lost what it acknowledged starts over
+        let (url, server) = serve(Server {
+            sessions: true,
+            session: contents[..512].to_vec(),
+            ..Server::default()
+        })
+        .await;
+        let (mut second, mut orchestrator_rx, fresh, _) = uploader();
+        std::fs::remove_dir_all(fresh.path.parent().unwrap()).unwrap();
+        second
+            .upload_path_to_directory(&url, &upload, "saves", None)
+            .await
+            .unwrap();
+        {
+            let server = server.lock().unwrap();
+            assert_eq!(server.session, contents);
+            assert_eq!(server.requests[3], "POST /_uploads");
+        }
+        assert_eq!(progress(&mut orchestrator_rx), [0, 1024, 2048, 2500]);
+
+        std::fs::remove_dir_all(upload.path.parent().unwrap()).unwrap();
+    }
+
+    fn digests_reported(
+        orchestrator_rx: &mut mpsc::UnboundedReceiver<orchestrator::Event>,
+    ) -> Vec<String> {
+        let mut digests = Vec::new();
+        while let Ok(event) = orchestrator_rx.try_recv() {
+            if let orchestrator::Event::UploadDigest { digest, .. } = event {
+                digests.push(digest);
+            }
+        }
+        digests
+    }
+
+    #[tokio::test]
+    async fn test_skip_upload_server_has() {
+        let (mut uploader, mut orchestrator_rx, upload, _) = uploader();
+        let digest = sha1_file(&upload.path).unwrap();
+        let (url, server) = serve(Server {
+            head: Some((StatusCode::OK, Some(digest.to_uppercase()))),
+            ..Server::default()
+        })
+        .await;
+
+        uploader
+            .upload_path_to_directory(&url, &upload, "saves", None)
+            .await
+            .unwrap();
+
+        let server = server.lock().unwrap();
+        assert_eq!(server.requests, ["HEAD /saves"]);
+        assert_eq!(server.looked_up, [format!("digest={digest}")]);
+        assert_eq!(digests_reported(&mut orchestrator_rx), [digest]);
+        std::fs::remove_dir_all(upload.path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_upload_server_lacks() {
+        // Another digest, no digest, or not found
+        for head in [
+            Some((StatusCode::OK, Some("0".repeat(40)))),
+            Some((StatusCode::OK, None)),
+            None,
+        ] {
+            let (url, server) = serve(Server {
+                head,
+                ..Server::default()
+            })
+            .await;
+            let (mut uploader, _orchestrator_rx, upload, contents) = uploader();
+
+            for _ in 0..2 {
+                uploader
+                    .upload_path_to_directory(&url, &upload, "saves", None)
+                    .await
+                    .unwrap();
+            }
+
+            // Still asked each time
+            let server = server.lock().unwrap();
+            assert_eq!(server.whole, contents);
+            assert_eq!(
+                server
+                    .requests
+                    .iter()
+                    .filter(|r| !r.starts_with("OPTIONS"))
+                    .collect::<Vec<_>>(),
+                ["HEAD /saves", "POST /saves", "HEAD /saves", "POST /saves"]
+            );
+            std::fs::remove_dir_all(upload.path.parent().unwrap()).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_digest_lookup_unsupported() {
+        for status in [StatusCode::METHOD_NOT_ALLOWED, StatusCode::NOT_IMPLEMENTED] {
+            let (url, server) = serve(Server {
+                head: Some((status, None)),
+                ..Server::default()
+            })
+            .await;
+            let (mut uploader, _orchestrator_rx, upload, contents) = uploader();
+
+            for _ in 0..2 {
+                uploader
+                    .upload_path_to_directory(&url, &upload, "saves", None)
+                    .await
+                    .unwrap();
+            }
+
+            // Only asked once
+            let server = server.lock().unwrap();
+            assert_eq!(server.whole, contents);
+            assert_eq!(
+                server
+                    .requests
+                    .iter()
+                    .filter(|r| !r.starts_with("OPTIONS"))
+                    .collect::<Vec<_>>(),
+                ["HEAD /saves", "POST /saves", "POST /saves"]
+            );
+            std::fs::remove_dir_all(upload.path.parent().unwrap()).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_digest_reused() {
+        let (url, server) = serve(Server::default()).await;
+        let (mut first, mut orchestrator_rx, upload, _) = uploader();
+
+        // Calculated and recorded once, then reused
+        for _ in 0..2 {
+            first
+                .upload_path_to_directory(&url, &upload, "saves", None)
+                .await
+                .unwrap();
+        }
+        let digest = sha1_file(&upload.path).unwrap();
+        assert_eq!(
+            digests_reported(&mut orchestrator_rx),
+            std::slice::from_ref(&digest)
+        );
+
+        // Loaded from the database after a restart, so not calculated again
+        let (mut restarted, mut orchestrator_rx, _, _) = uploader();
+        let stored = "f".repeat(40);
+        let loaded = Upload {
+            digest: Some(stored.clone()),
+            ..upload.clone()
+        };
+        restarted
+            .upload_path_to_directory(&url, &loaded, "saves", None)
+            .await
+            .unwrap();
+        assert!(digests_reported(&mut orchestrator_rx).is_empty());
+
+        let server = server.lock().unwrap();
+        assert_eq!(
+            server.looked_up,
+            [
+                format!("digest={digest}"),
+                format!("digest={digest}"),
+                format!("digest={stored}")
+            ]
+        );
+        std::fs::remove_dir_all(upload.path.parent().unwrap()).unwrap();
+    }
+}
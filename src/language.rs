@@ -0,0 +1,138 @@
+use anyhow::{Result, anyhow};
+use serde::{Serialize, Serializer};
+
+// A BCP-47 language tag, like "ja" or "zh-Hant-TW", with its subtags in
+// their conventional case
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Language(String);
+
+impl Language {
+    pub fn parse(tag: &str) -> Result<Language> {
+        let malformed = || anyhow!("{tag:?} is not a BCP-47 language tag");
+
+        let mut normalized = Vec::new();
+        let mut in_extension = false;
+        for (i, subtag) in tag.split(['-', '_']).enumerate() {
+            if subtag.is_empty()
+                || subtag.len() > 8
+                || !subtag.chars().all(|c| c.is_ascii_alphanumeric())
+            {
+                return Err(malformed());
+            }
+
+            if i == 0 {
+                let private = subtag.eq_ignore_ascii_case("x") || subtag.eq_ignore_ascii_case("i");
+                if !private
+                    && (subtag.len() < 2 || !subtag.chars().all(|c| c.is_ascii_alphabetic()))
+                {
+                    return Err(malformed());
+                }
+                in_extension = private;
+                normalized.push(subtag.to_ascii_lowercase());
+                continue;
+            }
+
+            // After a singleton like the "x" of private use, subtags are
+            // no longer scripts or regions
+            if subtag.len() == 1 {
+                in_extension = true;
+            }
+
+            normalized.push(match subtag.len() {
+                _ if in_extension => subtag.to_ascii_lowercase(),
+                2 => subtag.to_ascii_uppercase(),
+                4 if subtag.chars().all(|c| c.is_ascii_alphabetic()) => {
+                    subtag[..1].to_ascii_uppercase() + &subtag[1..].to_ascii_lowercase()
+                }
+                _ => subtag.to_ascii_lowercase(),
+            });
+        }
+
+        Ok(Language(normalized.join("-")))
+    }
+
+    // For games not in the games database
+    pub fn undetermined() -> Language {
+        Language("und".to_owned())
+    }
+
+    pub fn code(&self) -> &str {
+        &self.0
+    }
+
+    // The tag, then each shorter tag it falls back to, as in RFC 4647
+    // lookup: "zh-Hant-TW", "zh-Hant", "zh"
+    pub fn fallbacks(&self) -> impl Iterator<Item = &str> {
+        let tag = self.0.as_str();
+        let mut end = Some(tag.len());
+        std::iter::from_fn(move || {
+            let current = &tag[..end?];
+            end = current.rfind('-').map(|i| {
+                // A singleton can't end a tag, so it's dropped along with
+                // the subtag after it
+                if current[..i].rfind('-').is_some_and(|j| i - j == 2) {
+                    i - 2
+                } else {
+                    i
+                }
+            });
+            Some(current)
+        })
+    }
+}
+
+impl std::fmt::Display for Language {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Serialize for Language {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.code())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let code = |tag| Language::parse(tag).map(|l| l.code().to_owned()).ok();
+
+        assert_eq!(code("ja").as_deref(), Some("ja"));
+        assert_eq!(code("EN-us").as_deref(), Some("en-US"));
+        assert_eq!(code("zh_hant_tw").as_deref(), Some("zh-Hant-TW"));
+        assert_eq!(code("yue-HK").as_deref(), Some("yue-HK"));
+        assert_eq!(code("sr-Latn-RS").as_deref(), Some("sr-Latn-RS"));
+        assert_eq!(code("es-419").as_deref(), Some("es-419"));
+        assert_eq!(code("en-x-US").as_deref(), Some("en-x-us"));
+        assert_eq!(code("x-Klingon").as_deref(), Some("x-klingon"));
+        assert_eq!(code(""), None);
+        assert_eq!(code("e"), None);
+        assert_eq!(code("en-"), None);
+        assert_eq!(code("en--US"), None);
+        assert_eq!(code("12"), None);
+        assert_eq!(code("en US"), None);
+        assert_eq!(code("en-toolongsubtag"), None);
+    }
+
+    #[test]
+    fn test_fallbacks() {
+        let fallbacks = |tag| {
+            Language::parse(tag)
+                .unwrap()
+                .fallbacks()
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(fallbacks("ja"), ["ja"]);
+        assert_eq!(fallbacks("zh-Hant-TW"), ["zh-Hant-TW", "zh-Hant", "zh"]);
+        assert_eq!(fallbacks("en-US-x-a"), ["en-US-x-a", "en-US", "en"]);
+    }
+}
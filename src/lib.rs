@@ -1,9 +1,17 @@
+pub mod config;
 pub mod database;
+pub mod export;
+pub mod games;
 pub mod intake;
 pub mod internal;
+pub mod language;
+pub mod migrations;
 pub mod notify;
 pub mod orchestrator;
+pub mod plays;
+pub mod retention;
 pub mod saves;
 pub mod screenshots;
 pub mod server;
+pub mod stats;
 pub mod watcher;
@@ -0,0 +1,253 @@
+use anyhow::anyhow;
+use rusqlite::{Connection, Transaction, params};
+use tracing::info;
+
+// Each migration brings the plays database from the version before it (its
+// index in this list) to the next, tracked with PRAGMA user_version. Only
+// ever append to this list, with the next number in migrations/
+const MIGRATIONS: &[(&str, &str)] = &[
+    ("baseline", include_str!("migrations/001-baseline.sql")),
+    (
+        "upload queue and dead letters",
+        include_str!("migrations/002-uploads.sql"),
+    ),
+    (
+        "provisional plays",
+        include_str!("migrations/003-provisional-plays.sql"),
+    ),
+    ("pauses", include_str!("migrations/004-pauses.sql")),
+    (
+        "estimated end times",
+        include_str!("migrations/005-estimated-end-times.sql"),
+    ),
+    ("heartbeats", include_str!("migrations/006-heartbeats.sql")),
+    (
+        "play revisions",
+        include_str!("migrations/007-play-revisions.sql"),
+    ),
+    (
+        "idempotency keys",
+        include_str!("migrations/008-idempotency-keys.sql"),
+    ),
+    (
+        "upload sessions",
+        include_str!("migrations/009-upload-sessions.sql"),
+    ),
+    (
+        "upload digests",
+        include_str!("migrations/010-upload-digests.sql"),
+    ),
+    (
+        "create attempts",
+        include_str!("migrations/011-create-attempts.sql"),
+    ),
+];
+
+// Databases from before versioning were made from plays.schema as it was at
+// the time, so are at version 0 whatever they contain. Works out which of
+// the first three migrations, which cover every plays.schema, they already
+// have
+fn adopt_unversioned(tx: &Transaction) -> rusqlite::Result<usize> {
+    if !table_exists(tx, "plays")? {
+        return Ok(0);
+    }
+    if has_column(tx, "plays", "provisional")? {
+        return Ok(3);
+    }
+    if has_column(tx, "plays", "failed")? {
+        return Ok(2);
+    }
+
+    // The upload queue from before dead letters, which the upload queue
+    // migration finishes
+    if table_exists(tx, "uploads")? {
+        add_column(tx, "uploads", "failed_time", "INTEGER")?;
+        add_column(tx, "uploads", "failed_path", "TEXT")?;
+        tx.execute_batch("DROP INDEX IF EXISTS uploads_pending")?;
+    }
+    Ok(1)
+}
+
+fn table_exists(tx: &Transaction, table: &str) -> rusqlite::Result<bool> {
+    tx.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?",
+        params![table],
+        |row| row.get(0),
+    )
+}
+
+fn has_column(tx: &Transaction, table: &str, column: &str) -> rusqlite::Result<bool> {
+    tx.query_row(
+        "SELECT COUNT(*) > 0 FROM pragma_table_info(?) WHERE name = ?",
+        params![table, column],
+        |row| row.get(0),
+    )
+}
+
+fn add_column(
+    tx: &Transaction,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    if !has_column(tx, table, column)? {
+        tx.execute_batch(&format!(
+            "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+        ))?;
+    }
+
+    Ok(())
+}
+
+fn version(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.pragma_query_value(None, "user_version", |row| row.get(0))
+}
+
+pub fn migrate(conn: &mut Connection) -> Result<(), tokio_rusqlite::Error> {
+    let mut current = version(conn)?;
+    if current == 0 {
+        let tx = conn.transaction()?;
+        current = adopt_unversioned(&tx)?;
+        tx.pragma_update(None, "user_version", current)?;
+        tx.commit()?;
+        if current > 0 {
+            info!("Adopted unversioned plays database at version {current}");
+        }
+    }
+
+    if current > MIGRATIONS.len() {
+        return Err(tokio_rusqlite::Error::Other(
+            anyhow!(
+                "plays database is at version {current}, but this study-sync only knows up to version {}",
+                MIGRATIONS.len()
+            )
+            .into(),
+        ));
+    }
+
+    for (index, (description, sql)) in MIGRATIONS.iter().enumerate().skip(current) {
+        let version = index + 1;
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", version)?;
+        tx.commit()?;
+        info!("Migrated plays database to version {version} ({description})");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn columns(conn: &Connection, table: &str) -> Vec<String> {
+        let mut stmt = conn
+            .prepare("SELECT name FROM pragma_table_info(?)")
+            .unwrap();
+        stmt.query_map([table], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_migrate_new_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        assert_eq!(version(&conn).unwrap(), MIGRATIONS.len());
+        assert!(columns(&conn, "plays").contains(&"provisional".to_owned()));
+        assert!(columns(&conn, "uploads").contains(&"failed_path".to_owned()));
+
+        // Running again is a no-op
+        migrate(&mut conn).unwrap();
+        assert_eq!(version(&conn).unwrap(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn test_migrate_unversioned_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(include_str!("migrations/001-baseline.sql"))
+            .unwrap();
+        // plays.schema as it was once uploads could be dead lettered
+        conn.execute_batch(include_str!("migrations/002-uploads.sql"))
+            .unwrap();
+        conn.execute_batch(
+            "INSERT INTO plays (game, start_time) VALUES ('gb/Tetris.gb', 5);
+             INSERT INTO plays (game, start_time) VALUES ('gb/Tetris.gb', 6);",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+        assert_eq!(version(&conn).unwrap(), MIGRATIONS.len());
+
+        let provisional: bool = conn
+            .query_row("SELECT provisional FROM plays LIMIT 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert!(!provisional);
+
+        let keys: Vec<String> = conn
+            .prepare("SELECT idempotency_key FROM plays")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_ne!(keys[0], keys[1]);
+        for key in keys {
+            assert_eq!(key.len(), 36);
+            assert_eq!(&key[14..15], "4");
+        }
+    }
+
+    #[test]
+    fn test_migrate_unversioned_upload_queue() {
+        // As plays.schema was when the upload queue was added, before dead
+        // letters
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(include_str!("migrations/001-baseline.sql"))
+            .unwrap();
+        conn.execute_batch(
+            "CREATE TABLE uploads (
+               kind TEXT NOT NULL,
+               path TEXT NOT NULL,
+               directory TEXT NOT NULL,
+               game TEXT,
+               created_time INTEGER NOT NULL,
+               attempts INTEGER NOT NULL DEFAULT 0,
+               last_error TEXT,
+               first_failure INTEGER,
+               done_time INTEGER
+             );
+             CREATE INDEX uploads_pending ON uploads(created_time) WHERE done_time IS NULL;
+             INSERT INTO uploads (kind, path, directory, created_time)
+               VALUES ('save', 'a.srm', 'saves', 5);",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+        assert_eq!(version(&conn).unwrap(), MIGRATIONS.len());
+        assert!(columns(&conn, "plays").contains(&"failed".to_owned()));
+        assert!(columns(&conn, "uploads").contains(&"failed_path".to_owned()));
+
+        let pending: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM uploads WHERE done_time IS NULL AND failed_time IS NULL",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(pending, 1);
+    }
+
+    #[test]
+    fn test_migrate_newer_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+        assert!(migrate(&mut conn).is_err());
+    }
+}
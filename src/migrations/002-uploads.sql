@@ -0,0 +1,22 @@
+ALTER TABLE plays ADD COLUMN failed TEXT;
+
+CREATE TABLE
+  IF NOT EXISTS uploads (
+    kind TEXT NOT NULL,
+    path TEXT NOT NULL,
+    directory TEXT NOT NULL,
+    game TEXT,
+    created_time INTEGER NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    first_failure INTEGER,
+    done_time INTEGER,
+    failed_time INTEGER,
+    failed_path TEXT
+  );
+
+CREATE INDEX
+  IF NOT EXISTS uploads_pending
+  ON uploads(created_time)
+  WHERE done_time IS NULL
+  AND failed_time IS NULL;
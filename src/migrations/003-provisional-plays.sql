@@ -0,0 +1 @@
+ALTER TABLE plays ADD COLUMN provisional BOOLEAN DEFAULT 0;
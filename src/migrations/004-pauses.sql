@@ -0,0 +1,10 @@
+CREATE TABLE
+  IF NOT EXISTS pauses (
+    play INTEGER NOT NULL,
+    start_time INTEGER NOT NULL,
+    end_time INTEGER
+  );
+
+CREATE INDEX
+  IF NOT EXISTS pauses_play
+  ON pauses(play);
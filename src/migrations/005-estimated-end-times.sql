@@ -0,0 +1 @@
+ALTER TABLE plays ADD COLUMN end_estimated BOOLEAN DEFAULT 0;
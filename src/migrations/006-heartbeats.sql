@@ -0,0 +1 @@
+ALTER TABLE plays ADD COLUMN last_seen INTEGER;
@@ -0,0 +1,3 @@
+ALTER TABLE plays ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE plays ADD COLUMN submitted_revision INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,12 @@
+ALTER TABLE plays ADD COLUMN idempotency_key TEXT;
+
+-- A random (version 4) UUID for each existing play, like new_key()
+UPDATE plays
+SET
+  idempotency_key = lower(hex(randomblob(4))) || '-' ||
+    lower(hex(randomblob(2))) || '-4' ||
+    substr(lower(hex(randomblob(2))), 2) || '-' ||
+    substr('89ab', 1 + abs(random()) % 4, 1) ||
+    substr(lower(hex(randomblob(2))), 2) || '-' ||
+    lower(hex(randomblob(6)))
+WHERE idempotency_key IS NULL;
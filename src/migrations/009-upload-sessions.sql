@@ -0,0 +1,3 @@
+ALTER TABLE uploads ADD COLUMN session TEXT;
+
+ALTER TABLE uploads ADD COLUMN session_offset INTEGER;
@@ -0,0 +1,4 @@
+ALTER TABLE plays ADD COLUMN create_attempted BOOLEAN NOT NULL DEFAULT 0;
+
+-- Creates still pending from before this was recorded may have been sent
+UPDATE plays SET create_attempted = 1 WHERE intake_id IS NULL;
@@ -1,9 +1,9 @@
-use crate::internal::channel::{Action, PriorityRetryChannel};
+use crate::internal::channel::{Action, ChannelStatus, PriorityRetryChannel, RetryPolicy};
 use anyhow::Result;
 use std::path::{Path, PathBuf};
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tracing::{error, info};
 
 #[derive(Debug)]
@@ -16,20 +16,32 @@ pub enum Event {
 
 pub struct NotifyPre {
     rx: mpsc::UnboundedReceiver<Event>,
+    status_tx: watch::Sender<ChannelStatus>,
 }
 
 pub struct Notify {
+    status_tx: watch::Sender<ChannelStatus>,
+    retry_policy: RetryPolicy,
     led_path: PathBuf,
 }
 
 pub fn prepare() -> (NotifyPre, mpsc::UnboundedSender<Event>) {
     let (tx, rx) = mpsc::unbounded_channel();
-    (NotifyPre { rx }, tx)
+    let (status_tx, _) = watch::channel(ChannelStatus::default());
+    (NotifyPre { rx, status_tx }, tx)
 }
 
 impl NotifyPre {
+    pub fn status(&self) -> watch::Receiver<ChannelStatus> {
+        self.status_tx.subscribe()
+    }
+
     pub async fn start(self, led_path: PathBuf) -> Result<()> {
-        let notify = Notify { led_path };
+        let notify = Notify {
+            status_tx: self.status_tx,
+            retry_policy: RetryPolicy::default(),
+            led_path,
+        };
         notify.start(self.rx).await
     }
 }
@@ -103,6 +115,14 @@ impl PriorityRetryChannel for Notify {
         true
     }
 
+    fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    fn status_tx(&self) -> &watch::Sender<ChannelStatus> {
+        &self.status_tx
+    }
+
     fn is_high_priority(&self, event: &Event) -> bool {
         match event {
             Event::StartShutdown => true,
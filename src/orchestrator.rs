@@ -1,46 +1,122 @@
 use crate::{
-    database::Database,
+    database::{Database, active_duration},
+    games::derive_label,
     intake,
     internal::{
-        fs::{full_extension, now_milli, now_ymd, recursive_files_in, remove_full_extension},
+        disk::{Disk, DiskSpace, Thresholds, free_bytes, space_for},
+        fs::{
+            boot_time, full_extension, modified_secs, now_milli, now_ymd, recursive_files_in,
+            remove_full_extension,
+        },
         notifier::Notifier,
+        store::{is_latest, remove_incoming, store},
     },
-    notify, saves, screenshots, server, watcher,
+    language::Language,
+    notify,
+    plays::{Edit, Invalid, check_split},
+    retention::{Report, RetentionPolicy, prune_saves, remove_orphaned_objects},
+    saves, screenshots, server, watcher,
 };
-use anyhow::Result;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::iter;
 use std::path::{Path, PathBuf};
-use tokio::fs::{copy, create_dir_all, hard_link, remove_file, rename};
-use tokio::join;
-use tokio::sync::mpsc;
-use tracing::{error, info};
-
-#[derive(Debug, Clone)]
-pub enum Language {
-    English,
-    Japanese,
-    Cantonese,
-    Other(String),
-}
-
-#[derive(Debug)]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::fs::{copy, create_dir_all, hard_link, remove_dir, remove_file, rename, try_exists};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::time::{MissedTickBehavior, interval};
+use tokio::{join, select};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Game {
     pub id: i64,
     pub path: PathBuf,
     pub directory: Option<String>,
     pub language: Language,
     pub label: String,
+    // Not in the games database yet, so intake submissions and screenshot
+    // uploads are held until it's added
+    pub provisional: bool,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Play {
     pub id: i64,
     pub game: Game,
     pub start_time: u64,
     pub end_time: Option<u64>,
+    // Guessed during recovery, since the play was never ended
+    pub end_estimated: bool,
+    // Updated every HEARTBEAT while the play is current
+    pub last_seen: Option<u64>,
     pub intake_id: Option<String>,
     pub submitted_start: Option<u64>,
     pub submitted_end: Option<u64>,
     pub skipped: bool,
+    // Bumped by each edit, so intake can be brought up to date
+    pub revision: u64,
+    // Sent with the intake create, so a retried create is recognized
+    pub idempotency_key: String,
+    // Total of the pauses that have ended
+    pub paused_secs: u64,
+    pub paused_since: Option<u64>,
+}
+
+// What pausing does to the current play
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PausePolicy {
+    // Keep the play going, but don't count paused time as active
+    #[default]
+    Active,
+    // End the play, and start a new one on resume
+    Split,
+}
+
+// What to do with a save identical to the game's last save of that kind,
+// e.g. an .srm RetroArch rewrites on every exit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateSaves {
+    // Neither keep nor upload it
+    #[default]
+    Skip,
+    // Keep it, which takes no extra space, but don't upload it
+    Keep,
+    // Keep and upload it like any other save
+    Upload,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UploadKind {
+    Screenshot,
+    Extra,
+    Save,
+    SaveScreenshot,
+}
+
+#[derive(Debug, Clone)]
+pub struct Upload {
+    pub id: i64,
+    pub kind: UploadKind,
+    pub path: PathBuf,
+    pub directory: String,
+    pub game: Option<PathBuf>,
+    pub attempts: u64,
+    // The server's session for resuming a chunked upload, and how much of
+    // the file it last acknowledged
+    pub session: Option<String>,
+    pub session_offset: Option<u64>,
+    // SHA1 of the file, once calculated
+    pub digest: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Status {
+    pub current_play: Option<Play>,
+    pub previous_play: Option<Play>,
+    pub disk: Disk,
 }
 
 #[derive(Debug)]
@@ -49,6 +125,10 @@ pub enum Event {
     GameEnded(PathBuf),
     ScreenshotCreated(PathBuf),
     SaveFileCreated(PathBuf),
+    // Intake is about to send a create for the play for the first time
+    IntakeAttempted {
+        play_id: i64,
+    },
     IntakeStarted {
         play_id: i64,
         intake_id: String,
@@ -57,24 +137,78 @@ pub enum Event {
     IntakeEnded {
         play_id: i64,
         submitted_end: u64,
+        revision: u64,
     },
     IntakeFull {
         play_id: i64,
         intake_id: String,
         submitted_start: u64,
         submitted_end: u64,
+        revision: u64,
+    },
+    IntakeCorrected {
+        play_id: i64,
+        revision: u64,
+    },
+    UploadFinished {
+        upload_id: i64,
+    },
+    UploadFailed {
+        upload_id: i64,
+        error: String,
+    },
+    UploadProgress {
+        upload_id: i64,
+        session: String,
+        offset: u64,
+    },
+    UploadDigest {
+        upload_id: i64,
+        digest: String,
+    },
+    UploadDeadLettered {
+        upload_id: i64,
+        failed_path: PathBuf,
+    },
+    IntakeDeadLettered {
+        play_id: i64,
+        error: String,
+    },
+    RequeueUpload(i64),
+    RequeuePlay(i64),
+    EditPlay {
+        play_id: i64,
+        edit: Edit,
+        reply: oneshot::Sender<Result<Vec<Play>>>,
     },
+    Paused,
+    Resumed,
+    // From a prune running in the background
+    Pruned(Result<Report>),
     IsOnline(bool),
     ForceSync,
     StartShutdown,
 }
 
+const HEARTBEAT: Duration = Duration::from_secs(60);
+
+// A current play whose heartbeat is older than this on startup was cut short
+// by the device turning off, rather than by a quick restart of study-sync
+const HEARTBEAT_STALE: Duration = Duration::from_secs(5 * 60);
+
+const DISK_CHECK: Duration = Duration::from_secs(30);
+
 pub struct OrchestratorPre {
     rx: mpsc::UnboundedReceiver<Event>,
+    // Weak, so the orchestrator still stops once every other sender is gone
+    tx: mpsc::WeakUnboundedSender<Event>,
+    status_tx: watch::Sender<Status>,
 }
 
 pub struct Orchestrator {
     rx: mpsc::UnboundedReceiver<Event>,
+    tx: mpsc::WeakUnboundedSender<Event>,
+    status_tx: watch::Sender<Status>,
     intake_tx: mpsc::UnboundedSender<intake::Event>,
     screenshots_tx: mpsc::UnboundedSender<screenshots::Event>,
     saves_tx: mpsc::UnboundedSender<saves::Event>,
@@ -86,20 +220,50 @@ pub struct Orchestrator {
     pending_saves: PathBuf,
     keep_saves: PathBuf,
     extra_directory: PathBuf,
+    provisional_directory: PathBuf,
     latest_screenshot: PathBuf,
     trim_game_prefix: Option<String>,
+    pause_policy: PausePolicy,
+    duplicate_saves: DuplicateSaves,
+    retention: Option<RetentionPolicy>,
+    disk_thresholds: Thresholds,
+    disk: Disk,
     database: Database,
     current_play: Option<Play>,
     previous_play: Option<Play>,
+    // Game whose play was ended by a pause under the split policy
+    split_game: Option<Game>,
+    // Whether a prune is running, so another isn't started until it finishes
+    pruning: bool,
+}
+
+// A save linked or copied into pending_saves, with whether its screenshot
+// was too
+struct PendingSave {
+    screenshot: bool,
+    digest: Option<String>,
 }
 
 pub fn prepare() -> (OrchestratorPre, mpsc::UnboundedSender<Event>) {
     let (tx, rx) = mpsc::unbounded_channel();
-    (OrchestratorPre { rx }, tx)
+    let (status_tx, _) = watch::channel(Status::default());
+    let weak_tx = tx.downgrade();
+    (
+        OrchestratorPre {
+            rx,
+            tx: weak_tx,
+            status_tx,
+        },
+        tx,
+    )
 }
 
 impl OrchestratorPre {
     #![allow(clippy::too_many_arguments)]
+    pub fn status(&self) -> watch::Receiver<Status> {
+        self.status_tx.subscribe()
+    }
+
     pub async fn start(
         self,
         database: Database,
@@ -107,8 +271,14 @@ impl OrchestratorPre {
         pending_saves: PathBuf,
         keep_saves: PathBuf,
         extra_directory: PathBuf,
+        provisional_directory: PathBuf,
         latest_screenshot: PathBuf,
         trim_game_prefix: Option<String>,
+        pause_policy: PausePolicy,
+        duplicate_saves: DuplicateSaves,
+        retention: Option<RetentionPolicy>,
+        disk_thresholds: Thresholds,
+        failed_directory_name: String,
         intake_tx: mpsc::UnboundedSender<intake::Event>,
         screenshots_tx: mpsc::UnboundedSender<screenshots::Event>,
         saves_tx: mpsc::UnboundedSender<saves::Event>,
@@ -117,14 +287,39 @@ impl OrchestratorPre {
         server_tx: mpsc::UnboundedSender<server::Event>,
         notify_tx: mpsc::UnboundedSender<notify::Event>,
     ) -> Result<()> {
-        self.upload_existing_screenshots(&pending_screenshots, &screenshots_tx)?;
-        self.upload_extra_screenshots(&extra_directory, &screenshots_tx);
-        self.upload_existing_saves(&pending_saves, &saves_tx)?;
-
+        self.reconcile_uploads(
+            &database,
+            &pending_screenshots,
+            &extra_directory,
+            &provisional_directory,
+            &pending_saves,
+            &failed_directory_name,
+        )
+        .await?;
+        self.load_upload_backlog(&database, &screenshots_tx, &saves_tx)
+            .await?;
+
+        self.recover_orphaned_plays(
+            &database,
+            &keep_saves,
+            &pending_screenshots,
+            &provisional_directory,
+        )
+        .await?;
         let previous = self.load_backlog(&database, &intake_tx).await?;
 
+        // Nothing else stores saves, so any copies left are from a store that
+        // never finished
+        match remove_incoming(&keep_saves).await {
+            Ok(0) => {}
+            Ok(n) => info!("Removed {n} unfinished copies from the save store"),
+            Err(e) => warn!("Could not remove unfinished copies from the save store: {e:?}"),
+        }
+
         let orchestrator = Orchestrator {
             rx: self.rx,
+            tx: self.tx,
+            status_tx: self.status_tx,
             intake_tx,
             screenshots_tx,
             saves_tx,
@@ -136,78 +331,171 @@ impl OrchestratorPre {
             pending_saves,
             keep_saves,
             extra_directory,
+            provisional_directory,
             latest_screenshot,
             trim_game_prefix,
+            pause_policy,
+            duplicate_saves,
+            retention,
+            disk_thresholds,
+            disk: Disk::default(),
             database,
             current_play: previous,
             previous_play: None,
+            split_game: None,
+            pruning: false,
         };
         orchestrator.start().await
     }
 
-    fn upload_existing_screenshots(
+    async fn reconcile_uploads(
         &self,
+        database: &Database,
         pending_screenshots: &Path,
-        screenshots_tx: &mpsc::UnboundedSender<screenshots::Event>,
+        extra_directory: &Path,
+        provisional_directory: &Path,
+        pending_saves: &Path,
+        failed_directory_name: &str,
     ) -> Result<()> {
+        let known = database.pending_upload_paths().await?;
+        let failed_screenshots = pending_screenshots.join(failed_directory_name);
+        let failed_saves = pending_saves.join(failed_directory_name);
+
         for path in recursive_files_in(pending_screenshots, Some(3)) {
-            let mut directory = path.clone();
-            directory.pop();
-            let directory = directory.strip_prefix(pending_screenshots)?;
-            info!("Found batched screenshot {path:?} for {directory:?}");
-            if let Some(directory) = directory.to_str() {
-                let event = screenshots::Event::UploadScreenshot(path, directory.to_owned());
-                screenshots_tx.send(event)?;
+            if known.contains(&path)
+                || path.starts_with(&failed_screenshots)
+                || path.starts_with(provisional_directory)
+            {
+                continue;
+            }
+            if let Some(directory) = relative_directory(&path, pending_screenshots) {
+                info!("Found orphaned screenshot {path:?} for {directory:?}");
+                database
+                    .enqueue_upload(UploadKind::Screenshot, &path, &directory, None)
+                    .await?;
             }
         }
 
-        Ok(())
-    }
+        for path in recursive_files_in(extra_directory, None) {
+            if known.contains(&path) {
+                continue;
+            }
+            if let Some(directory) = relative_directory(&path, pending_screenshots) {
+                info!("Found orphaned extra screenshot {path:?}");
+                database
+                    .enqueue_upload(UploadKind::Extra, &path, &directory, None)
+                    .await?;
+            }
+        }
 
-    fn upload_existing_saves(
-        &self,
-        pending_saves: &Path,
-        saves_tx: &mpsc::UnboundedSender<saves::Event>,
-    ) -> Result<()> {
         for path in recursive_files_in(pending_saves, None) {
-            let mut directory = path.clone();
-            directory.pop();
-            let directory = directory.strip_prefix(pending_saves)?.to_owned();
+            if known.contains(&path) || path.starts_with(&failed_saves) {
+                continue;
+            }
+            let Some(directory) = relative_directory(&path, pending_saves) else {
+                continue;
+            };
 
-            let event = match path.extension().map(|s| s.to_str()) {
+            let kind = match path.extension().map(|s| s.to_str()) {
                 Some(Some("png" | "jpg")) => {
-                    info!("Found batched screenshot {path:?} for {directory:?}");
-                    saves::Event::UploadScreenshot(path, directory)
+                    info!("Found orphaned save screenshot {path:?} for {directory:?}");
+                    UploadKind::SaveScreenshot
                 }
                 Some(Some(_)) => {
-                    info!("Found batched save {path:?} for {directory:?}");
-                    saves::Event::UploadSave(path, directory)
+                    info!("Found orphaned save {path:?} for {directory:?}");
+                    UploadKind::Save
                 }
                 _ => continue,
             };
-            saves_tx.send(event)?;
+            database
+                .enqueue_upload(kind, &path, &directory, None)
+                .await?;
         }
 
         Ok(())
     }
 
-    fn upload_extra_screenshots(
+    async fn load_upload_backlog(
         &self,
-        extra_directory: &Path,
+        database: &Database,
         screenshots_tx: &mpsc::UnboundedSender<screenshots::Event>,
-    ) {
-        let extra_directory = extra_directory.to_owned();
-        let screenshots_tx = screenshots_tx.clone();
+        saves_tx: &mpsc::UnboundedSender<saves::Event>,
+    ) -> Result<()> {
+        let backlog = database.load_upload_backlog().await?;
+        if backlog.is_empty() {
+            info!("No backlog of uploads found");
+            return Ok(());
+        }
 
-        tokio::spawn(async move {
-            for path in recursive_files_in(extra_directory, None) {
-                info!("Uploading extra screenshot {path:?}");
-                let event = screenshots::Event::UploadExtra(path);
-                if let Err(e) = screenshots_tx.send(event) {
-                    error!("Could not send to screenshots: {e:?}");
-                }
+        info!("Found backlog of {} uploads", backlog.len());
+        for upload in backlog {
+            if !try_exists(&upload.path).await? {
+                warn!("Dropping queued upload {upload:?} because its file no longer exists");
+                database.upload_finished(upload.id).await?;
+                continue;
             }
-        });
+
+            send_upload(upload, screenshots_tx, saves_tx)?;
+        }
+
+        Ok(())
+    }
+
+    // Ends plays left open by a crash or power loss at the last sign of
+    // activity during them: a kept save, a pending or uploaded screenshot, the
+    // start of a pause, or a heartbeat
+    async fn recover_orphaned_plays(
+        &self,
+        database: &Database,
+        keep_saves: &Path,
+        pending_screenshots: &Path,
+        provisional_directory: &Path,
+    ) -> Result<()> {
+        let booted = boot_time().await;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let stale_before = now.saturating_sub(HEARTBEAT_STALE.as_secs());
+
+        for play in database.orphaned_plays(booted, stale_before).await? {
+            let game = &play.game;
+
+            let until = match database.next_play_start(play.start_time).await? {
+                Some(next) => next.min(booted.unwrap_or(now)),
+                None => booted.unwrap_or(now),
+            };
+            let during = play.start_time..=until;
+
+            let mut save_dir = keep_saves.join(&game.path);
+            save_dir.set_extension("");
+            let screenshot_dir = if game.provisional {
+                Some(held_screenshot_dir(provisional_directory, &game.path))
+            } else {
+                game.directory.as_ref().map(|d| pending_screenshots.join(d))
+            };
+
+            let uploaded = database
+                .last_upload_time(&game.path, play.start_time, until)
+                .await?;
+
+            let end_time = iter::once(save_dir)
+                .chain(screenshot_dir)
+                .flat_map(|dir| recursive_files_in(dir, None))
+                .filter_map(|path| modified_secs(&path))
+                .filter(|t| during.contains(t))
+                .chain(uploaded)
+                .chain(play.paused_since)
+                .chain(play.last_seen)
+                .max()
+                .unwrap_or(play.start_time);
+
+            let play = database.estimated_end(play, end_time).await?;
+            warn!("Ended orphaned play {play:?} at estimated time {end_time}");
+        }
+
+        Ok(())
     }
 
     async fn load_backlog(
@@ -217,20 +505,20 @@ impl OrchestratorPre {
     ) -> Result<Option<Play>> {
         let (previous, backlog) = join!(
             database.load_previously_playing(),
-            database.load_intake_backlog(),
+            database.load_intake_backlog(None),
         );
 
         let previous = previous?;
         match &previous {
             Some(p) => {
                 info!("Found previously-playing game {p:?}");
-                if p.end_time.is_none() {
-                    if let Some(intake_id) = &p.intake_id {
-                        intake_tx.send(intake::Event::PreviousGame {
-                            play_id: p.id,
-                            intake_id: intake_id.clone(),
-                        })?;
-                    }
+                if p.end_time.is_none()
+                    && let Some(intake_id) = &p.intake_id
+                {
+                    intake_tx.send(intake::Event::PreviousGame {
+                        play_id: p.id,
+                        intake_id: intake_id.clone(),
+                    })?;
                 }
             }
             None => info!("No previously-playing game found"),
@@ -255,7 +543,44 @@ impl OrchestratorPre {
 
 impl Orchestrator {
     pub async fn start(mut self) -> Result<()> {
-        while let Some(event) = self.rx.recv().await {
+        self.resolve_provisional_games().await;
+
+        let mut heartbeat = interval(HEARTBEAT);
+        heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        // Without a policy, never ticks
+        let mut prune = interval(
+            self.retention
+                .as_ref()
+                .map_or(HEARTBEAT, RetentionPolicy::interval),
+        );
+        prune.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let mut disk_check = interval(DISK_CHECK);
+        disk_check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            self.publish_status();
+
+            let event = select! {
+                event = self.rx.recv() => event,
+                _ = heartbeat.tick() => {
+                    self.heartbeat().await;
+                    continue;
+                }
+                _ = prune.tick(), if self.retention.is_some() => {
+                    self.prune();
+                    continue;
+                }
+                _ = disk_check.tick() => {
+                    self.check_disk().await;
+                    continue;
+                }
+            };
+            let Some(event) = event else {
+                break;
+            };
+
             info!("Handling {event:?}");
             match event {
                 Event::GameStarted(path) => {
@@ -265,6 +590,14 @@ impl Orchestrator {
                         ));
                     }
 
+                    // Starting a game abandons a split one, so resuming
+                    // doesn't start it again
+                    self.split_game = None;
+
+                    // Games added with `study-sync games set` since the
+                    // last start are picked up here without a ForceSync
+                    self.resolve_provisional_games().await;
+
                     let path = match self.trim_game_path(&path) {
                         Some(p) => p,
                         None => continue,
@@ -272,24 +605,30 @@ impl Orchestrator {
 
                     let (remove_res, game_res) = join!(
                         remove_file(&self.latest_screenshot),
-                        self.database.game_for_path(path),
+                        self.database.find_game(path),
                     );
 
-                    if let Err(e) = remove_res {
-                        if e.kind() != std::io::ErrorKind::NotFound {
-                            self.notify_error(&format!(
-                                "Could not remove latest screenshot {:?}: {e:?}",
-                                self.latest_screenshot
-                            ));
-                            continue;
-                        }
+                    if let Err(e) = remove_res
+                        && e.kind() != std::io::ErrorKind::NotFound
+                    {
+                        self.notify_error(&format!(
+                            "Could not remove latest screenshot {:?}: {e:?}",
+                            self.latest_screenshot
+                        ));
+                        continue;
                     }
 
                     let game = match game_res {
-                        Ok(game) => game,
+                        Ok(Some(game)) => game,
+                        Ok(None) => {
+                            self.notify_error(&format!(
+                                "Could not find game for path {path:?}, so recording the play provisionally until it's added with `study-sync games set`"
+                            ));
+                            Game::provisional(path.to_path_buf())
+                        }
                         Err(e) => {
                             self.notify_error(&format!(
-                                "Could not find game for path {path:?}: {e:?}"
+                                "Could not look up game for path {path:?}: {e:?}"
                             ));
                             continue;
                         }
@@ -303,27 +642,14 @@ impl Orchestrator {
                         }
                     };
 
+                    self.submit_started(&play);
                     self.set_current_play(Some(play));
-                    let play = self.current_play.as_ref().unwrap();
-                    let game = &play.game;
-
-                    let event = intake::Event::SubmitStarted {
-                        play_id: play.id,
-                        game_label: game.label.clone(),
-                        language: game.language.clone(),
-                        start_time: play.start_time,
-                    };
-                    if let Err(e) = self.intake_tx.send(event) {
-                        self.notify_error(&format!("Could not send to intake: {e:?}"));
-                    }
 
-                    if let Some(screenshot_dir) = self.screenshot_dir() {
-                        if let Err(e) = create_dir_all(&screenshot_dir).await {
-                            self.notify_error(&format!(
-                                "Could not create {screenshot_dir:?}: {e:?}"
-                            ));
-                            continue;
-                        }
+                    if let Some(screenshot_dir) = self.screenshot_dir()
+                        && let Err(e) = create_dir_all(&screenshot_dir).await
+                    {
+                        self.notify_error(&format!("Could not create {screenshot_dir:?}: {e:?}"));
+                        continue;
                     }
 
                     let mut pending_save_dir = self.pending_saves.join(path);
@@ -349,14 +675,14 @@ impl Orchestrator {
                 }
 
                 Event::GameEnded(path) => {
-                    if let Err(e) = remove_file(&self.latest_screenshot).await {
-                        if e.kind() != std::io::ErrorKind::NotFound {
-                            self.notify_error(&format!(
-                                "Could not remove latest screenshot {:?}: {e:?}",
-                                self.latest_screenshot
-                            ));
-                            continue;
-                        }
+                    if let Err(e) = remove_file(&self.latest_screenshot).await
+                        && e.kind() != std::io::ErrorKind::NotFound
+                    {
+                        self.notify_error(&format!(
+                            "Could not remove latest screenshot {:?}: {e:?}",
+                            self.latest_screenshot
+                        ));
+                        continue;
                     }
 
                     let path = match self.trim_game_path(&path) {
@@ -370,22 +696,14 @@ impl Orchestrator {
                                 "Previous game does not match! {path:?}, expected {play:?}"
                             ));
                         } else {
-                            self.current_play = Some(self.database.finished_playing(play).await?);
-                            if let Some(play) = &self.current_play {
-                                let game = &play.game;
-                                let event = intake::Event::SubmitFull {
-                                    play_id: play.id,
-                                    game_label: game.label.clone(),
-                                    language: game.language.clone(),
-                                    start_time: play.start_time,
-                                    end_time: play.end_time.unwrap(),
-                                };
-                                if let Err(e) = self.intake_tx.send(event) {
-                                    self.notify_error(&format!("Could not send to intake: {e:?}"));
-                                }
-                            }
+                            self.finish_play(play).await?;
                             self.notify_success(false, "Play ended!");
                         }
+                    } else if let Some(game) = self.split_game.take()
+                        && game.path == path
+                    {
+                        // Split while paused, so the play already ended
+                        self.notify_success(false, "Play ended!");
                     } else {
                         self.notify_error("No previous game!");
                     }
@@ -393,9 +711,75 @@ impl Orchestrator {
                     self.set_current_play(None);
                 }
 
+                Event::Paused => {
+                    let Some(play) = &mut self.current_play else {
+                        self.notify_error("No current play to pause!");
+                        continue;
+                    };
+                    if play.paused_since.is_some() {
+                        continue;
+                    }
+
+                    match self.pause_policy {
+                        PausePolicy::Active => match self.database.paused(play.id).await {
+                            Ok(since) => play.paused_since = Some(since),
+                            Err(e) => {
+                                self.notify_error(&format!("Could not pause play: {e:?}"));
+                                continue;
+                            }
+                        },
+                        PausePolicy::Split => {
+                            let play = self.current_play.take().unwrap();
+                            self.split_game = Some(play.game.clone());
+                            self.finish_play(play).await?;
+                            self.set_current_play(None);
+                        }
+                    }
+
+                    self.notify_success(true, "Play paused");
+                }
+
+                Event::Resumed => {
+                    if let Some(game) = self.split_game.take() {
+                        if let Some(play) = &self.current_play {
+                            self.notify_error(&format!(
+                                "Already have a current play, so not resuming {game:?}! {play:?}"
+                            ));
+                            continue;
+                        }
+
+                        let play = match self.database.started_playing(game).await {
+                            Ok(play) => play,
+                            Err(e) => {
+                                self.notify_error(&format!("Could not start play: {e:?}"));
+                                continue;
+                            }
+                        };
+                        self.submit_started(&play);
+                        self.set_current_play(Some(play));
+                    } else if let Some(play) = &mut self.current_play
+                        && let Some(since) = play.paused_since
+                    {
+                        match self.database.resumed(play.id).await {
+                            Ok(until) => play.paused_secs += until.saturating_sub(since),
+                            Err(e) => {
+                                self.notify_error(&format!("Could not resume play: {e:?}"));
+                                continue;
+                            }
+                        }
+                        play.paused_since = None;
+                    } else {
+                        self.notify_error("No paused play to resume!");
+                        continue;
+                    }
+
+                    self.notify_success(true, "Play resumed");
+                }
+
                 Event::ScreenshotCreated(path) => {
-                    if let Some((play, directory)) = self.playing_with_directory() {
-                        let mut destination = self.screenshot_dir().unwrap();
+                    if let Some(play) = self.playing()
+                        && let Some(mut destination) = self.screenshot_dir()
+                    {
                         destination.push(now_milli());
                         destination.set_extension(
                             path.extension()
@@ -416,14 +800,14 @@ impl Orchestrator {
                             continue;
                         }
 
-                        if let Err(e) = remove_res {
-                            if e.kind() != std::io::ErrorKind::NotFound {
-                                self.notify_error(&format!(
-                                    "Could not remove latest screenshot {:?}: {e:?}",
-                                    self.latest_screenshot
-                                ));
-                                continue;
-                            }
+                        if let Err(e) = remove_res
+                            && e.kind() != std::io::ErrorKind::NotFound
+                        {
+                            self.notify_error(&format!(
+                                "Could not remove latest screenshot {:?}: {e:?}",
+                                self.latest_screenshot
+                            ));
+                            continue;
                         }
 
                         if let Err(e) = hard_link(&destination, &self.latest_screenshot).await {
@@ -434,13 +818,31 @@ impl Orchestrator {
                             continue;
                         }
 
-                        let event = screenshots::Event::UploadScreenshot(
-                            destination,
-                            directory.to_string(),
-                        );
-                        if let Err(e) = self.screenshots_tx.send(event) {
-                            self.notify_error(&format!("Could not send to screenshots: {e:?}"));
-                        }
+                        let Some(directory) = &play.game.directory else {
+                            info!("Holding screenshot {destination:?} until the game is known");
+                            continue;
+                        };
+
+                        let upload = match self
+                            .database
+                            .enqueue_upload(
+                                UploadKind::Screenshot,
+                                &destination,
+                                directory,
+                                Some(&play.game.path),
+                            )
+                            .await
+                        {
+                            Ok(upload) => upload,
+                            Err(e) => {
+                                self.notify_error(&format!(
+                                    "Could not enqueue upload of {destination:?}: {e:?}"
+                                ));
+                                continue;
+                            }
+                        };
+
+                        self.send_upload(upload);
                     } else {
                         let mut destination = self.extra_directory.clone();
                         destination.push(path.file_name().unwrap());
@@ -452,10 +854,36 @@ impl Orchestrator {
                             continue;
                         }
 
-                        let event = screenshots::Event::UploadExtra(destination);
-                        if let Err(e) = self.screenshots_tx.send(event) {
-                            self.notify_error(&format!("Could not send to screenshots: {e:?}"));
-                        }
+                        let Some(directory) =
+                            relative_directory(&destination, &self.pending_screenshots)
+                        else {
+                            self.notify_error(&format!(
+                                "Could not determine directory of extra screenshot {destination:?}"
+                            ));
+                            continue;
+                        };
+
+                        let game = self.playing().map(|p| p.game.path.clone());
+                        let upload = match self
+                            .database
+                            .enqueue_upload(
+                                UploadKind::Extra,
+                                &destination,
+                                &directory,
+                                game.as_deref(),
+                            )
+                            .await
+                        {
+                            Ok(upload) => upload,
+                            Err(e) => {
+                                self.notify_error(&format!(
+                                    "Could not enqueue upload of {destination:?}: {e:?}"
+                                ));
+                                continue;
+                            }
+                        };
+
+                        self.send_upload(upload);
                     }
                 }
 
@@ -484,85 +912,92 @@ impl Orchestrator {
                     target.set_extension(extension);
 
                     let pending_save_destination = self.pending_saves.join(&target);
-                    let keep_save_destination = self.keep_saves.join(&target);
-
                     let mut pending_screenshot_destination = pending_save_destination.clone();
-                    let mut keep_screenshot_destination = keep_save_destination.clone();
                     pending_screenshot_destination.set_extension("png");
-                    keep_screenshot_destination.set_extension("png");
-
-                    let latest_screenshot = &self.latest_screenshot;
-
-                    let (keep_save_res, keep_screenshot_res) = join!(
-                        copy(&path, &keep_save_destination),
-                        copy(&latest_screenshot, &keep_screenshot_destination),
-                    );
-
-                    if let Err(e) = keep_save_res {
-                        self.notify_error(&format!(
-                            "Could not copy save {path:?} to {keep_save_destination:?}: {e:?}"
-                        ));
-                        continue;
-                    }
-
-                    if let Err(e) = keep_screenshot_res {
-                        self.notify_error(&format!(
-                            "Could not copy screenshot {latest_screenshot:?} to {keep_screenshot_destination:?}: {e:?}"
-                        ));
-                    }
-
-                    let (pending_save_res, pending_screenshot_res) = join!(
-                        hard_link(&keep_save_destination, &pending_save_destination),
-                        hard_link(
-                            &keep_screenshot_destination,
-                            &pending_screenshot_destination
-                        ),
-                    );
 
-                    if let Err(e) = pending_save_res {
-                        self.notify_error(&format!(
-                            "Could not hardlink save {keep_save_destination:?} to {pending_save_destination:?}: {e:?}"
-                        ));
+                    let pending = if self.disk.space == DiskSpace::Low {
+                        self.pend_save(&path, &target).await
+                    } else {
+                        self.keep_save(&path, &target, extension).await
+                    };
+                    let Some(pending) = pending else {
                         continue;
-                    }
-
-                    if let Err(e) = &pending_screenshot_res {
-                        self.notify_error(&format!(
-                            "Could not hardlink screenshot {keep_screenshot_destination:?} to {pending_screenshot_destination:?}: {e:?}"
-                        ));
-                    }
+                    };
 
-                    let event =
-                        saves::Event::UploadSave(pending_save_destination, directory.clone());
-                    if let Err(e) = self.saves_tx.send(event) {
-                        self.notify_error(&format!("Could not send to saves: {e:?}"));
+                    let Some(directory) = directory.to_str() else {
+                        self.notify_error(&format!("Could not stringify directory {directory:?}"));
                         continue;
-                    }
+                    };
+                    let game = self.playing().map(|p| p.game.path.clone());
 
-                    if pending_screenshot_res.is_ok() {
-                        let event = saves::Event::UploadScreenshot(
-                            pending_screenshot_destination,
+                    let mut upload = match self
+                        .database
+                        .enqueue_upload(
+                            UploadKind::Save,
+                            &pending_save_destination,
                             directory,
-                        );
-                        if let Err(e) = self.saves_tx.send(event) {
-                            self.notify_error(&format!("Could not send to saves: {e:?}"));
+                            game.as_deref(),
+                        )
+                        .await
+                    {
+                        Ok(upload) => upload,
+                        Err(e) => {
+                            self.notify_error(&format!(
+                                "Could not enqueue upload of {pending_save_destination:?}: {e:?}"
+                            ));
                             continue;
                         }
+                    };
+                    // Saves the uploader reading it again
+                    if let Some(digest) = pending.digest {
+                        match self.database.upload_digest(upload.id, &digest).await {
+                            Ok(()) => upload.digest = Some(digest),
+                            Err(e) => warn!("Could not record digest of {path:?}: {e:?}"),
+                        }
+                    }
+                    self.send_upload(upload);
+
+                    if pending.screenshot {
+                        let upload = match self
+                            .database
+                            .enqueue_upload(
+                                UploadKind::SaveScreenshot,
+                                &pending_screenshot_destination,
+                                directory,
+                                game.as_deref(),
+                            )
+                            .await
+                        {
+                            Ok(upload) => upload,
+                            Err(e) => {
+                                self.notify_error(&format!(
+                                    "Could not enqueue upload of {pending_screenshot_destination:?}: {e:?}"
+                                ));
+                                continue;
+                            }
+                        };
+                        self.send_upload(upload);
                     }
 
                     self.notify_success(true, &format!("Handled save {path:?}"));
                 }
 
+                Event::IntakeAttempted { play_id } => {
+                    if let Err(e) = self.database.create_attempted(play_id).await {
+                        self.notify_error(&format!("Could not record intake attempt: {e:?}"));
+                    }
+                }
+
                 Event::IntakeStarted {
                     play_id,
                     intake_id,
                     submitted_start,
                 } => {
-                    if let Some(play) = &mut self.current_play {
-                        if play.id == play_id {
-                            play.intake_id = Some(intake_id.clone());
-                            play.submitted_start = Some(submitted_start);
-                        }
+                    if let Some(play) = &mut self.current_play
+                        && play.id == play_id
+                    {
+                        play.intake_id = Some(intake_id.clone());
+                        play.submitted_start = Some(submitted_start);
                     }
 
                     if let Err(e) = self
@@ -580,19 +1015,25 @@ impl Orchestrator {
                 Event::IntakeEnded {
                     play_id,
                     submitted_end,
+                    revision,
                 } => {
-                    if let Some(play) = &mut self.current_play {
-                        if play.id == play_id {
-                            play.submitted_end = Some(submitted_end);
-                        }
+                    if let Some(play) = &mut self.current_play
+                        && play.id == play_id
+                    {
+                        play.submitted_end = Some(submitted_end);
                     }
 
-                    if let Err(e) = self.database.final_intake(play_id, submitted_end).await {
+                    if let Err(e) = self
+                        .database
+                        .final_intake(play_id, submitted_end, revision)
+                        .await
+                    {
                         self.notify_error(&format!("Could not update intake: {e:?}"));
                         continue;
                     }
 
                     self.notify_success(true, "Finished intake");
+                    self.send_intake_backlog(play_id).await;
                 }
 
                 Event::IntakeFull {
@@ -600,18 +1041,25 @@ impl Orchestrator {
                     intake_id,
                     submitted_start,
                     submitted_end,
+                    revision,
                 } => {
-                    if let Some(play) = &mut self.current_play {
-                        if play.id == play_id {
-                            play.intake_id = Some(intake_id.clone());
-                            play.submitted_start = Some(submitted_start);
-                            play.submitted_end = Some(submitted_end);
-                        }
+                    if let Some(play) = &mut self.current_play
+                        && play.id == play_id
+                    {
+                        play.intake_id = Some(intake_id.clone());
+                        play.submitted_start = Some(submitted_start);
+                        play.submitted_end = Some(submitted_end);
                     }
 
                     if let Err(e) = self
                         .database
-                        .full_intake(play_id, &intake_id, submitted_start, submitted_end)
+                        .full_intake(
+                            play_id,
+                            &intake_id,
+                            submitted_start,
+                            submitted_end,
+                            revision,
+                        )
                         .await
                     {
                         self.notify_error(&format!("Could not update intake: {e:?}"));
@@ -619,16 +1067,151 @@ impl Orchestrator {
                     }
 
                     self.notify_success(true, &format!("Created full intake {intake_id:?}"));
+                    self.send_intake_backlog(play_id).await;
                 }
 
-                Event::IsOnline(online) => {
-                    if let Err(e) = self.intake_tx.send(intake::Event::IsOnline(online)) {
-                        self.notify_error(&format!("Could not send to intake: {e:?}"));
+                Event::IntakeCorrected { play_id, revision } => {
+                    if let Err(e) = self.database.intake_corrected(play_id, revision).await {
+                        self.notify_error(&format!("Could not update intake: {e:?}"));
+                        continue;
                     }
-                    if let Err(e) = self
-                        .screenshots_tx
-                        .send(screenshots::Event::IsOnline(online))
-                    {
+
+                    self.send_intake_backlog(play_id).await;
+                }
+
+                Event::UploadFinished { upload_id } => {
+                    if let Err(e) = self.database.upload_finished(upload_id).await {
+                        self.notify_error(&format!("Could not finish upload: {e:?}"));
+                    }
+                }
+
+                Event::UploadFailed { upload_id, error } => {
+                    if let Err(e) = self.database.upload_failed(upload_id, &error).await {
+                        self.notify_error(&format!("Could not record upload failure: {e:?}"));
+                    }
+                }
+
+                Event::UploadProgress {
+                    upload_id,
+                    session,
+                    offset,
+                } => {
+                    if let Err(e) = self
+                        .database
+                        .upload_progress(upload_id, &session, offset)
+                        .await
+                    {
+                        self.notify_error(&format!("Could not record upload progress: {e:?}"));
+                    }
+                }
+
+                Event::UploadDigest { upload_id, digest } => {
+                    if let Err(e) = self.database.upload_digest(upload_id, &digest).await {
+                        self.notify_error(&format!("Could not record upload digest: {e:?}"));
+                    }
+                }
+
+                Event::UploadDeadLettered {
+                    upload_id,
+                    failed_path,
+                } => {
+                    if let Err(e) = self
+                        .database
+                        .upload_dead_lettered(upload_id, &failed_path)
+                        .await
+                    {
+                        self.notify_error(&format!("Could not record dead-lettered upload: {e:?}"));
+                    }
+                }
+
+                Event::IntakeDeadLettered { play_id, error } => {
+                    if let Err(e) = self.database.intake_failed(play_id, &error).await {
+                        self.notify_error(&format!("Could not record dead-lettered intake: {e:?}"));
+                    }
+                }
+
+                Event::RequeueUpload(upload_id) => {
+                    let (upload, failed_path) =
+                        match self.database.load_failed_upload(upload_id).await {
+                            Ok(Some(u)) => u,
+                            Ok(None) => {
+                                self.notify_error(&format!(
+                                    "No dead-lettered upload {upload_id} to requeue"
+                                ));
+                                continue;
+                            }
+                            Err(e) => {
+                                self.notify_error(&format!(
+                                    "Could not load dead-lettered upload {upload_id}: {e:?}"
+                                ));
+                                continue;
+                            }
+                        };
+
+                    if let Some(parent) = upload.path.parent()
+                        && let Err(e) = create_dir_all(parent).await
+                    {
+                        self.notify_error(&format!("Could not create {parent:?}: {e:?}"));
+                        continue;
+                    }
+
+                    if let Err(e) = rename(&failed_path, &upload.path).await {
+                        self.notify_error(&format!(
+                            "Could not move {failed_path:?} back to {:?}: {e:?}",
+                            upload.path
+                        ));
+                        continue;
+                    }
+
+                    if let Err(e) = self.database.upload_requeued(upload_id).await {
+                        self.notify_error(&format!("Could not requeue upload: {e:?}"));
+                        continue;
+                    }
+
+                    self.send_upload(upload);
+                }
+
+                Event::RequeuePlay(play_id) => {
+                    if let Err(e) = self.database.play_requeued(play_id).await {
+                        self.notify_error(&format!("Could not requeue play: {e:?}"));
+                        continue;
+                    }
+
+                    self.send_intake_backlog(play_id).await;
+                }
+
+                Event::EditPlay {
+                    play_id,
+                    edit,
+                    reply,
+                } => {
+                    let result = self.edit_play(play_id, edit).await;
+                    if let Err(e) = &result {
+                        warn!("Could not edit play {play_id}: {e:#}");
+                    }
+                    if reply.send(result).is_err() {
+                        warn!("Edit of play {play_id} was abandoned");
+                    }
+                }
+
+                Event::Pruned(res) => {
+                    self.pruning = false;
+                    let res = match res {
+                        Ok(_) => remove_orphaned_objects(&self.keep_saves).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = res {
+                        self.notify_error(&format!("Could not prune {:?}: {e:?}", self.keep_saves));
+                    }
+                }
+                Event::IsOnline(online) => {
+                    if let Err(e) = self.intake_tx.send(intake::Event::IsOnline(online)) {
+                        self.notify_error(&format!("Could not send to intake: {e:?}"));
+                    }
+                    if let Err(e) = self
+                        .screenshots_tx
+                        .send(screenshots::Event::IsOnline(online))
+                    {
                         self.notify_error(&format!("Could not send to screenshots: {e:?}"));
                     }
                     if let Err(e) = self.saves_tx.send(saves::Event::IsOnline(online)) {
@@ -637,6 +1220,8 @@ impl Orchestrator {
                 }
 
                 Event::ForceSync => {
+                    self.resolve_provisional_games().await;
+
                     if let Err(e) = self.intake_tx.send(intake::Event::ForceSync) {
                         self.notify_error(&format!("Could not send to intake: {e:?}"));
                     }
@@ -682,28 +1267,503 @@ impl Orchestrator {
         Ok(())
     }
 
+    fn publish_status(&self) {
+        self.status_tx.send_replace(Status {
+            current_play: self.current_play.clone(),
+            previous_play: self.previous_play.clone(),
+            disk: self.disk.clone(),
+        });
+    }
+
     fn playing(&self) -> Option<&Play> {
         self.current_play.as_ref().or(self.previous_play.as_ref())
     }
 
-    fn playing_with_directory(&self) -> Option<(&Play, &str)> {
-        if let Some(playing) = self.playing() {
-            if let Some(ref directory) = playing.game.directory {
-                return Some((playing, directory));
+    fn screenshot_dir(&self) -> Option<PathBuf> {
+        let playing = self.playing()?;
+        if playing.game.provisional {
+            return Some(self.held_screenshot_dir(&playing.game.path));
+        }
+
+        playing
+            .game
+            .directory
+            .as_ref()
+            .map(|directory| self.pending_screenshots.join(directory))
+    }
+
+    fn held_screenshot_dir(&self, game_path: &Path) -> PathBuf {
+        held_screenshot_dir(&self.provisional_directory, game_path)
+    }
+
+    // Once a provisional game has been added to the games database, uploads
+    // its held screenshots and submits its plays to intake
+    async fn resolve_provisional_games(&mut self) {
+        let paths = match self.database.provisional_games().await {
+            Ok(paths) => paths,
+            Err(e) => {
+                self.notify_error(&format!("Could not load provisional games: {e:?}"));
+                return;
+            }
+        };
+
+        for path in paths {
+            let game = match self.database.find_game(&path).await {
+                Ok(Some(game)) => game,
+                Ok(None) => continue,
+                Err(e) => {
+                    self.notify_error(&format!("Could not look up game for path {path:?}: {e:?}"));
+                    continue;
+                }
+            };
+
+            info!("Resolved provisional game {path:?} to {game:?}");
+
+            for play in [&mut self.current_play, &mut self.previous_play]
+                .into_iter()
+                .flatten()
+            {
+                if play.game.path == path {
+                    play.game = game.clone();
+                }
+            }
+
+            let held = self.held_screenshot_dir(&path);
+            if let Err(e) = self.release_held_screenshots(&held, &game).await {
+                self.notify_error(&format!(
+                    "Could not release held screenshots in {held:?}: {e:?}"
+                ));
+                continue;
+            }
+
+            let play_ids = match self.database.game_resolved(&path).await {
+                Ok(play_ids) => play_ids,
+                Err(e) => {
+                    self.notify_error(&format!("Could not resolve plays of {path:?}: {e:?}"));
+                    continue;
+                }
+            };
+
+            for play_id in play_ids {
+                self.send_intake_backlog(play_id).await;
+            }
+
+            self.notify_success(false, &format!("Resolved game {:?}", game.label));
+        }
+    }
+
+    async fn release_held_screenshots(&self, held: &Path, game: &Game) -> Result<()> {
+        if !try_exists(held).await? {
+            return Ok(());
+        }
+
+        let (destination_dir, directory, kind) = match &game.directory {
+            Some(directory) => (
+                self.pending_screenshots.join(directory),
+                directory.clone(),
+                UploadKind::Screenshot,
+            ),
+            None => {
+                let directory = self
+                    .extra_directory
+                    .strip_prefix(&self.pending_screenshots)?
+                    .to_str()
+                    .ok_or_else(|| anyhow!("Could not stringify {:?}", self.extra_directory))?;
+                (
+                    self.extra_directory.clone(),
+                    directory.to_owned(),
+                    UploadKind::Extra,
+                )
             }
+        };
+        create_dir_all(&destination_dir).await?;
+
+        for path in recursive_files_in(held, None) {
+            let destination = destination_dir.join(path.file_name().unwrap());
+            info!("Releasing held screenshot {path:?} to {destination:?}");
+            rename(&path, &destination).await?;
+
+            let upload = self
+                .database
+                .enqueue_upload(kind, &destination, &directory, Some(&game.path))
+                .await?;
+            self.send_upload(upload);
         }
 
-        None
+        remove_dir(held).await?;
+        Ok(())
     }
 
-    fn screenshot_dir(&self) -> Option<PathBuf> {
-        if let Some(playing) = self.playing() {
-            if let Some(ref directory) = playing.game.directory {
-                return Some(self.pending_screenshots.join(directory));
+    fn send_upload(&self, upload: Upload) {
+        if let Err(e) = send_upload(upload, &self.screenshots_tx, &self.saves_tx) {
+            self.notify_error(&format!("Could not send upload: {e:?}"));
+        }
+    }
+
+    // Only ended plays can be edited. Returns every play the edit changed
+    async fn edit_play(&mut self, play_id: i64, edit: Edit) -> Result<Vec<Play>> {
+        let play = self
+            .database
+            .load_play(play_id)
+            .await?
+            .ok_or_else(|| Invalid(format!("no play {play_id}")))?;
+        let Some(end_time) = play.end_time else {
+            return Err(Invalid(format!("play {play_id} hasn't ended")).into());
+        };
+
+        let changed = match edit {
+            Edit::Adjust(adjust) => {
+                let (start_time, end_time) = adjust.times(play.start_time, end_time)?;
+                let game_path = match &adjust.game {
+                    Some(path) => {
+                        self.database
+                            .find_game(path)
+                            .await?
+                            .ok_or_else(|| Invalid(format!("no game with path {path:?}")))?
+                            .path
+                    }
+                    None => play.game.path.clone(),
+                };
+                self.database
+                    .adjust_play(play_id, start_time, end_time, &game_path)
+                    .await?;
+                vec![play_id]
+            }
+
+            Edit::Skip => {
+                self.database.skip_play(play_id).await?;
+                vec![play_id]
+            }
+
+            Edit::Split { at } => {
+                check_split(play.start_time, end_time, at)?;
+                let new_id = self.database.split_play(play_id, at).await?;
+                self.send_intake_backlog(new_id).await;
+                vec![play_id, new_id]
+            }
+
+            Edit::Merge { with } => {
+                let other = self
+                    .database
+                    .load_play(with)
+                    .await?
+                    .ok_or_else(|| Invalid(format!("no play {with}")))?;
+                if other.end_time.is_none() {
+                    return Err(Invalid(format!("play {with} hasn't ended")).into());
+                }
+                if other.game.path != play.game.path {
+                    return Err(Invalid(format!(
+                        "plays {play_id} and {with} are of different games"
+                    ))
+                    .into());
+                }
+                if other.skipped || with == play_id {
+                    return Err(Invalid(format!("play {with} can't be merged")).into());
+                }
+
+                let (earlier, later) = if play.start_time <= other.start_time {
+                    (&play, &other)
+                } else {
+                    (&other, &play)
+                };
+                self.database.merge_plays(earlier, later).await?;
+                vec![earlier.id, later.id]
+            }
+        };
+
+        let mut plays = Vec::with_capacity(changed.len());
+        for id in changed {
+            let Some(play) = self.database.load_play(id).await? else {
+                continue;
+            };
+            // Otherwise the submission still queued in intake will catch up
+            // once it's done
+            if play.submitted_end.is_some() {
+                self.send_intake_backlog(id).await;
+            }
+            if self.previous_play.as_ref().is_some_and(|p| p.id == id) {
+                self.previous_play = Some(play.clone());
+            }
+            plays.push(play);
+        }
+        Ok(plays)
+    }
+
+    // Whatever the play still needs from intake: its submission, or edits
+    // made since it was last submitted
+    async fn send_intake_backlog(&self, play_id: i64) {
+        let backlog = match self.database.load_intake_backlog(Some(play_id)).await {
+            Ok(backlog) => backlog,
+            Err(e) => {
+                self.notify_error(&format!(
+                    "Could not load intake backlog for play {play_id}: {e:?}"
+                ));
+                return;
+            }
+        };
+
+        for event in backlog {
+            if let Err(e) = self.intake_tx.send(event) {
+                self.notify_error(&format!("Could not send to intake: {e:?}"));
+            }
+        }
+    }
+
+    async fn heartbeat(&mut self) {
+        let Some(play) = &mut self.current_play else {
+            return;
+        };
+
+        match self.database.heartbeat(play.id).await {
+            Ok(last_seen) => play.last_seen = Some(last_seen),
+            Err(e) => self.notify_error(&format!("Could not record heartbeat: {e:?}")),
+        }
+    }
+
+    // Keeps the save and the latest screenshot in keep_saves, and links them
+    // into pending_saves to be uploaded. None if there's nothing to upload
+    async fn keep_save(&self, path: &Path, target: &Path, extension: &str) -> Option<PendingSave> {
+        let pending_save_destination = self.pending_saves.join(target);
+        let keep_save_destination = self.keep_saves.join(target);
+
+        let mut pending_screenshot_destination = pending_save_destination.clone();
+        let mut keep_screenshot_destination = keep_save_destination.clone();
+        pending_screenshot_destination.set_extension("png");
+        keep_screenshot_destination.set_extension("png");
+
+        let (object, digest) = match store(&self.keep_saves, path).await {
+            Ok(stored) => stored,
+            Err(e) => {
+                self.notify_error(&format!(
+                    "Could not store save {path:?} in {:?}: {e:?}",
+                    self.keep_saves
+                ));
+                return None;
             }
+        };
+
+        let keep_save_dir = keep_save_destination.parent()?;
+        let duplicate = match is_latest(keep_save_dir, extension, &object).await {
+            Ok(duplicate) => duplicate,
+            Err(e) => {
+                warn!("Could not compare {path:?} to the last save: {e:?}");
+                false
+            }
+        };
+        if duplicate && self.duplicate_saves == DuplicateSaves::Skip {
+            self.notify_success(true, &format!("Skipped duplicate save {path:?}"));
+            return None;
+        }
+
+        let latest_screenshot = &self.latest_screenshot;
+
+        let (keep_save_res, keep_screenshot_res) = join!(
+            hard_link(&object, &keep_save_destination),
+            copy(&latest_screenshot, &keep_screenshot_destination),
+        );
+
+        if let Err(e) = keep_save_res {
+            self.notify_error(&format!(
+                "Could not hardlink save {object:?} to {keep_save_destination:?}: {e:?}"
+            ));
+            return None;
+        }
+
+        if let Err(e) = keep_screenshot_res {
+            self.notify_error(&format!(
+                "Could not copy screenshot {latest_screenshot:?} to {keep_screenshot_destination:?}: {e:?}"
+            ));
+        }
+
+        if duplicate && self.duplicate_saves == DuplicateSaves::Keep {
+            self.notify_success(true, &format!("Kept duplicate save {path:?}"));
+            return None;
         }
 
-        None
+        let (pending_save_res, pending_screenshot_res) = join!(
+            hard_link(&keep_save_destination, &pending_save_destination),
+            hard_link(
+                &keep_screenshot_destination,
+                &pending_screenshot_destination
+            ),
+        );
+
+        if let Err(e) = pending_save_res {
+            self.notify_error(&format!(
+                "Could not hardlink save {keep_save_destination:?} to {pending_save_destination:?}: {e:?}"
+            ));
+            return None;
+        }
+
+        if let Err(e) = &pending_screenshot_res {
+            self.notify_error(&format!(
+                "Could not hardlink screenshot {keep_screenshot_destination:?} to {pending_screenshot_destination:?}: {e:?}"
+            ));
+        }
+
+        Some(PendingSave {
+            screenshot: pending_screenshot_res.is_ok(),
+            digest: Some(digest),
+        })
+    }
+
+    // When space is low, copies the save and the latest screenshot straight
+    // into pending_saves, so they take no space once uploaded
+    async fn pend_save(&self, path: &Path, target: &Path) -> Option<PendingSave> {
+        let pending_save_destination = self.pending_saves.join(target);
+        let mut pending_screenshot_destination = pending_save_destination.clone();
+        pending_screenshot_destination.set_extension("png");
+
+        let latest_screenshot = &self.latest_screenshot;
+
+        let (pending_save_res, pending_screenshot_res) = join!(
+            copy(path, &pending_save_destination),
+            copy(&latest_screenshot, &pending_screenshot_destination),
+        );
+
+        if let Err(e) = pending_save_res {
+            self.notify_error(&format!(
+                "Could not copy save {path:?} to {pending_save_destination:?}: {e:?}"
+            ));
+            return None;
+        }
+
+        if let Err(e) = &pending_screenshot_res {
+            self.notify_error(&format!(
+                "Could not copy screenshot {latest_screenshot:?} to {pending_screenshot_destination:?}: {e:?}"
+            ));
+        }
+
+        warn!("Not keeping save {path:?} since disk space is low");
+        Some(PendingSave {
+            screenshot: pending_screenshot_res.is_ok(),
+            digest: None,
+        })
+    }
+
+    async fn check_disk(&mut self) {
+        let paths = [
+            &self.pending_screenshots,
+            &self.pending_saves,
+            &self.keep_saves,
+        ];
+        let free_bytes = match free_bytes(&paths) {
+            Ok(free_bytes) => free_bytes,
+            Err(e) => {
+                self.notify_error(&format!("Could not check disk space: {e:?}"));
+                return;
+            }
+        };
+
+        let space = space_for(free_bytes, &self.disk_thresholds);
+        let previous = self.disk.space;
+        self.disk = Disk {
+            space,
+            free_bytes: Some(free_bytes),
+        };
+
+        if space <= previous {
+            if space < previous {
+                info!("Disk space recovered to {space:?} with {free_bytes} bytes free");
+            }
+            return;
+        }
+
+        match space {
+            DiskSpace::Ok => {}
+            DiskSpace::Warning => self.notify_error(&format!(
+                "Disk space is running out: {free_bytes} bytes free"
+            )),
+            DiskSpace::Low => {
+                self.notify_emergency(&format!(
+                    "Disk space is low, not keeping saves: {free_bytes} bytes free"
+                ));
+
+                // Uploading frees pending files, so retry now instead of
+                // waiting out any backoff
+                if let Err(e) = self.screenshots_tx.send(screenshots::Event::ForceSync) {
+                    self.notify_error(&format!("Could not send to screenshots: {e:?}"));
+                }
+                if let Err(e) = self.saves_tx.send(saves::Event::ForceSync) {
+                    self.notify_error(&format!("Could not send to saves: {e:?}"));
+                }
+                self.prune();
+            }
+        }
+    }
+
+    // Prunes kept saves in the background, since hashing them to check
+    // they were uploaded can take a while. The result comes back as
+    // Event::Pruned, and the store is cleaned up then, between saves
+    fn prune(&mut self) {
+        let Some(policy) = self.retention.clone() else {
+            return;
+        };
+        if self.pruning {
+            info!("Not pruning, since the last prune hasn't finished");
+            return;
+        }
+        self.pruning = true;
+
+        let database = self.database.clone();
+        let keep_saves = self.keep_saves.clone();
+        let pending_saves = self.pending_saves.clone();
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let res = prune_saves(&database, &keep_saves, &pending_saves, &policy, false).await;
+            if let Some(tx) = tx.upgrade()
+                && let Err(e) = tx.send(Event::Pruned(res))
+            {
+                warn!("Could not send prune result: {e:?}");
+            }
+        });
+    }
+
+    fn submit_started(&self, play: &Play) {
+        let game = &play.game;
+        if game.provisional {
+            return;
+        }
+
+        let event = intake::Event::SubmitStarted {
+            play_id: play.id,
+            idempotency_key: play.idempotency_key.clone(),
+            create_attempted: false,
+            game_label: game.label.clone(),
+            language: game.language.clone(),
+            start_time: play.start_time,
+        };
+        if let Err(e) = self.intake_tx.send(event) {
+            self.notify_error(&format!("Could not send to intake: {e:?}"));
+        }
+    }
+
+    // Leaves the finished play as current, for set_current_play to retire
+    async fn finish_play(&mut self, play: Play) -> Result<()> {
+        let play = self.database.finished_playing(play).await?;
+        let game = &play.game;
+
+        if !game.provisional
+            && let Some(end_time) = play.end_time
+        {
+            let event = intake::Event::SubmitFull {
+                play_id: play.id,
+                idempotency_key: play.idempotency_key.clone(),
+                create_attempted: false,
+                game_label: game.label.clone(),
+                language: game.language.clone(),
+                start_time: play.start_time,
+                end_time,
+                active_duration: play.active_duration().unwrap_or(0),
+                revision: play.revision,
+            };
+            if let Err(e) = self.intake_tx.send(event) {
+                self.notify_error(&format!("Could not send to intake: {e:?}"));
+            }
+        }
+
+        self.current_play = Some(play);
+        Ok(())
     }
 
     fn set_current_play(&mut self, play: Option<Play>) {
@@ -733,8 +1793,539 @@ impl Orchestrator {
     }
 }
 
+fn send_upload(
+    upload: Upload,
+    screenshots_tx: &mpsc::UnboundedSender<screenshots::Event>,
+    saves_tx: &mpsc::UnboundedSender<saves::Event>,
+) -> Result<()> {
+    match upload.kind {
+        UploadKind::Screenshot => {
+            screenshots_tx.send(screenshots::Event::UploadScreenshot(upload))?
+        }
+        UploadKind::Extra => screenshots_tx.send(screenshots::Event::UploadExtra(upload))?,
+        UploadKind::Save => saves_tx.send(saves::Event::UploadSave(upload))?,
+        UploadKind::SaveScreenshot => saves_tx.send(saves::Event::UploadScreenshot(upload))?,
+    }
+
+    Ok(())
+}
+
+fn held_screenshot_dir(provisional_directory: &Path, game_path: &Path) -> PathBuf {
+    let mut directory = provisional_directory.join(game_path);
+    directory.set_extension("");
+    directory
+}
+
+fn relative_directory(path: &Path, root: &Path) -> Option<String> {
+    path.parent()?
+        .strip_prefix(root)
+        .ok()?
+        .to_str()
+        .map(str::to_owned)
+}
+
+impl Play {
+    pub fn active_duration(&self) -> Option<u64> {
+        self.end_time
+            .map(|end_time| active_duration(self.start_time, end_time, self.paused_secs))
+    }
+}
+
+impl Game {
+    // Stands in for a game that isn't in the games database, so that its
+    // plays can still be recorded
+    pub fn provisional(path: PathBuf) -> Game {
+        Game {
+            id: 0,
+            label: derive_label(&path),
+            path,
+            directory: None,
+            language: Language::undetermined(),
+            provisional: true,
+        }
+    }
+}
+
 impl Notifier for Orchestrator {
     fn notify_tx(&self) -> &mpsc::UnboundedSender<notify::Event> {
         &self.notify_tx
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        database::test::{execute, execute_games, open_in_memory, query},
+        internal::{idempotency::new_key, store::OBJECTS_DIRECTORY},
+    };
+    use std::os::unix::fs::MetadataExt;
+
+    // An orchestrator on an in-memory database with games A and B, and the
+    // other ends of its channels
+    struct Harness {
+        tx: mpsc::UnboundedSender<Event>,
+        intake_rx: mpsc::UnboundedReceiver<intake::Event>,
+        status: watch::Receiver<Status>,
+        // Shared with the orchestrator, for changing the games database
+        // under it
+        database: Database,
+        orchestrator: tokio::task::JoinHandle<Result<()>>,
+        directory: PathBuf,
+        screenshots_rx: mpsc::UnboundedReceiver<screenshots::Event>,
+        saves_rx: mpsc::UnboundedReceiver<saves::Event>,
+        // Kept so the orchestrator's sends to them succeed
+        _watcher_rxs: [mpsc::UnboundedReceiver<watcher::Event>; 2],
+        _server_rx: mpsc::UnboundedReceiver<server::Event>,
+        _notify_rx: mpsc::UnboundedReceiver<notify::Event>,
+    }
+
+    impl Harness {
+        // plays is run against the plays database first
+        async fn start(pause_policy: PausePolicy, plays: &str) -> Harness {
+            Harness::start_with(pause_policy, DuplicateSaves::default(), plays).await
+        }
+
+        async fn start_with(
+            pause_policy: PausePolicy,
+            duplicate_saves: DuplicateSaves,
+            plays: &str,
+        ) -> Harness {
+            let directory = std::env::temp_dir().join(format!("study-sync-test-{}", new_key()));
+            let path = |name| directory.join(name);
+            for name in [
+                "roms",
+                "screenshots",
+                "saves",
+                "keep",
+                "extra",
+                "provisional",
+            ] {
+                create_dir_all(path(name)).await.unwrap();
+            }
+
+            let (notify_tx, notify_rx) = mpsc::unbounded_channel();
+            let database = open_in_memory(
+                &[("gb/A.gb", "a", "A"), ("gb/B.gb", "b", "B")],
+                notify_tx.clone(),
+            )
+            .await;
+            execute(&database, plays.to_owned()).await;
+
+            let (intake_tx, intake_rx) = mpsc::unbounded_channel();
+            let (screenshots_tx, screenshots_rx) = mpsc::unbounded_channel();
+            let (saves_tx, saves_rx) = mpsc::unbounded_channel();
+            let (screenshot_watcher_tx, screenshot_watcher_rx) = mpsc::unbounded_channel();
+            let (save_watcher_tx, save_watcher_rx) = mpsc::unbounded_channel();
+            let (server_tx, server_rx) = mpsc::unbounded_channel();
+
+            let (pre, tx) = prepare();
+            let status = pre.status();
+            let orchestrator = tokio::spawn(pre.start(
+                database.clone(),
+                path("screenshots"),
+                path("saves"),
+                path("keep"),
+                path("extra"),
+                path("provisional"),
+                path("latest.png"),
+                path("roms").to_str().map(str::to_owned),
+                pause_policy,
+                duplicate_saves,
+                None,
+                Thresholds {
+                    warning_bytes: 0,
+                    low_bytes: 0,
+                },
+                "failed".to_owned(),
+                intake_tx,
+                screenshots_tx,
+                saves_tx,
+                screenshot_watcher_tx,
+                save_watcher_tx,
+                server_tx,
+                notify_tx,
+            ));
+
+            Harness {
+                tx,
+                intake_rx,
+                status,
+                database,
+                orchestrator,
+                directory,
+                screenshots_rx,
+                saves_rx,
+                _watcher_rxs: [screenshot_watcher_rx, save_watcher_rx],
+                _server_rx: server_rx,
+                _notify_rx: notify_rx,
+            }
+        }
+
+        fn send(&self, event: Event) {
+            self.tx.send(event).unwrap();
+        }
+
+        // Where a game, or a save beside it, is under the ROM directory
+        fn rom(&self, path: &str) -> PathBuf {
+            self.directory.join("roms").join(path)
+        }
+
+        // The play ID of the next intake submission, which must be of the
+        // given kind and game
+        async fn started(&mut self, label: &str) -> i64 {
+            match self.intake_rx.recv().await {
+                Some(intake::Event::SubmitStarted {
+                    play_id,
+                    game_label,
+                    ..
+                }) if game_label == label => play_id,
+                event => panic!("expected {label} to start, got {event:?}"),
+            }
+        }
+
+        async fn ended(&mut self, label: &str) -> i64 {
+            match self.intake_rx.recv().await {
+                Some(intake::Event::SubmitFull {
+                    play_id,
+                    game_label,
+                    ..
+                }) if game_label == label => play_id,
+                event => panic!("expected {label} to end, got {event:?}"),
+            }
+        }
+
+        async fn stop(self) {
+            drop(self.tx);
+            self.orchestrator.await.unwrap().unwrap();
+            std::fs::remove_dir_all(&self.directory).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_split_pauses() {
+        let mut h = Harness::start(PausePolicy::Split, "").await;
+
+        h.send(Event::GameStarted(h.rom("gb/A.gb")));
+        let first = h.started("A").await;
+        h.send(Event::Paused);
+        assert_eq!(h.ended("A").await, first);
+        h.send(Event::Resumed);
+        let second = h.started("A").await;
+        assert_ne!(second, first);
+        h.send(Event::Paused);
+        assert_eq!(h.ended("A").await, second);
+
+        // Starting another game abandons the split one, so resuming doesn't
+        // start it again
+        h.send(Event::GameStarted(h.rom("gb/B.gb")));
+        let third = h.started("B").await;
+        h.send(Event::Resumed);
+        h.send(Event::GameEnded(h.rom("gb/B.gb")));
+        assert_eq!(h.ended("B").await, third);
+
+        h.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_recover_stale_play() {
+        // The current play's heartbeat stopped half an hour ago, so it ended
+        // then
+        let mut h = Harness::start(
+            PausePolicy::Active,
+            "INSERT INTO plays (game, start_time, last_seen, idempotency_key)
+               VALUES ('gb/A.gb', unixepoch() - 3600, unixepoch() - 1800, 'k1');
+             INSERT INTO current (play) VALUES (last_insert_rowid());",
+        )
+        .await;
+        match h.intake_rx.recv().await {
+            Some(intake::Event::SubmitFull {
+                start_time,
+                end_time,
+                active_duration,
+                ..
+            }) => {
+                assert_eq!(end_time - start_time, 1800);
+                assert_eq!(active_duration, 1800);
+            }
+            event => panic!("expected A to end, got {event:?}"),
+        }
+        h.stop().await;
+
+        // A current play with a recent heartbeat carries on
+        let mut h = Harness::start(
+            PausePolicy::Active,
+            "INSERT INTO plays (game, start_time, last_seen, idempotency_key)
+               VALUES ('gb/A.gb', unixepoch() - 3600, unixepoch() - 30, 'k1');
+             INSERT INTO current (play) VALUES (last_insert_rowid());",
+        )
+        .await;
+        let play_id = h.started("A").await;
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            h.status
+                .wait_for(|s| s.current_play.as_ref().is_some_and(|p| p.id == play_id)),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        h.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_malformed_language() {
+        let mut h = Harness::start(PausePolicy::Active, "").await;
+        execute_games(
+            &h.database,
+            "UPDATE games SET language = 'not a tag' WHERE label = 'A'".to_owned(),
+        )
+        .await;
+
+        // Still recorded, as if A weren't in the games database
+        h.send(Event::GameStarted(h.rom("gb/A.gb")));
+        let status = h
+            .status
+            .wait_for(|s| s.current_play.is_some())
+            .await
+            .unwrap()
+            .clone();
+        let play = status.current_play.unwrap();
+        assert!(play.game.provisional);
+        assert_eq!(play.game.path, Path::new("gb/A.gb"));
+
+        h.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_resolve_provisional_game() {
+        let mut h = Harness::start(
+            PausePolicy::Active,
+            "INSERT INTO plays (game, start_time, end_time, provisional, idempotency_key)
+               VALUES ('gb/C.gb', 1000, 2000, 1, 'k1');",
+        )
+        .await;
+        let held = h.directory.join("provisional/gb/C");
+        create_dir_all(&held).await.unwrap();
+        tokio::fs::write(held.join("shot.png"), b"png")
+            .await
+            .unwrap();
+
+        // Not yet in the games database, so nothing is sent
+        h.send(Event::ForceSync);
+        assert!(matches!(
+            h.intake_rx.recv().await,
+            Some(intake::Event::ForceSync)
+        ));
+        assert!(matches!(
+            h.screenshots_rx.recv().await,
+            Some(screenshots::Event::ForceSync)
+        ));
+
+        execute_games(
+            &h.database,
+            "INSERT INTO games (path, directory, language, label)
+               VALUES ('gb/C.gb', 'c', 'en', 'C')"
+                .to_owned(),
+        )
+        .await;
+        h.send(Event::ForceSync);
+
+        match h.intake_rx.recv().await {
+            Some(intake::Event::SubmitFull {
+                game_label,
+                start_time: 1000,
+                end_time: 2000,
+                ..
+            }) if game_label == "C" => {}
+            event => panic!("expected C to be submitted, got {event:?}"),
+        }
+        let released = h.directory.join("screenshots/c/shot.png");
+        match h.screenshots_rx.recv().await {
+            Some(screenshots::Event::UploadScreenshot(upload)) => {
+                assert_eq!(upload.path, released);
+                assert_eq!(upload.directory, "c");
+            }
+            event => panic!("expected the held screenshot, got {event:?}"),
+        }
+        assert!(try_exists(&released).await.unwrap());
+        assert!(!try_exists(&held).await.unwrap());
+        assert_eq!(
+            query(
+                &h.database,
+                "SELECT COUNT(*) FROM plays WHERE provisional = 1".to_owned()
+            )
+            .await,
+            0
+        );
+
+        h.stop().await;
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_saves() {
+        for mode in [
+            DuplicateSaves::Skip,
+            DuplicateSaves::Keep,
+            DuplicateSaves::Upload,
+        ] {
+            let mut h = Harness::start_with(PausePolicy::Active, mode, "").await;
+            let keep = h.directory.join("keep");
+            let kept_dir = keep.join("gb/A");
+            create_dir_all(&kept_dir).await.unwrap();
+            create_dir_all(h.directory.join("saves/gb/A"))
+                .await
+                .unwrap();
+            tokio::fs::write(h.directory.join("latest.png"), b"png")
+                .await
+                .unwrap();
+
+            // The game's last save, identical to the one being written
+            let save = h.rom("gb/A.srm");
+            create_dir_all(h.rom("gb")).await.unwrap();
+            tokio::fs::write(&save, b"save").await.unwrap();
+            let (object, _) = store(&keep, &save).await.unwrap();
+            hard_link(&object, kept_dir.join("20240101-120000.srm"))
+                .await
+                .unwrap();
+
+            h.send(Event::SaveFileCreated(save));
+            h.send(Event::ForceSync);
+            let mut uploaded = vec![];
+            loop {
+                match h.saves_rx.recv().await {
+                    Some(saves::Event::ForceSync) => break,
+                    Some(saves::Event::UploadSave(upload)) => uploaded.push(upload.kind),
+                    Some(saves::Event::UploadScreenshot(upload)) => uploaded.push(upload.kind),
+                    event => panic!("expected the save's uploads, got {event:?}"),
+                }
+            }
+
+            // Whether kept or not, there's still only the one object
+            let kept: Vec<_> = recursive_files_in(&kept_dir, None)
+                .filter(|p| p.extension() == Some("srm".as_ref()))
+                .collect();
+            let objects = recursive_files_in(keep.join(OBJECTS_DIRECTORY), None).count();
+            assert_eq!(objects, 1, "{mode:?}");
+            match mode {
+                DuplicateSaves::Skip => {
+                    assert_eq!(kept.len(), 1);
+                    assert!(uploaded.is_empty());
+                }
+                DuplicateSaves::Keep => {
+                    assert_eq!(kept.len(), 2);
+                    assert!(uploaded.is_empty());
+                }
+                DuplicateSaves::Upload => {
+                    assert_eq!(kept.len(), 2);
+                    assert_eq!(uploaded, [UploadKind::Save, UploadKind::SaveScreenshot]);
+                }
+            }
+            for path in kept {
+                assert_eq!(
+                    std::fs::metadata(path).unwrap().ino(),
+                    std::fs::metadata(&object).unwrap().ino()
+                );
+            }
+
+            h.stop().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_uploads() {
+        let directory = std::env::temp_dir().join(format!("study-sync-test-{}", new_key()));
+        let screenshots = directory.join("screenshots");
+        let saves = directory.join("saves");
+        let files = [
+            "screenshots/ja/a/queued.png",
+            "screenshots/ja/a/orphaned.png",
+            "screenshots/extra/orphaned.png",
+            "screenshots/failed/ja/a/dead.png",
+            "screenshots/unknown/gb/C/held.png",
+            "saves/gb/A/20240101-120000.png",
+            "saves/gb/A/20240101-120000.srm",
+            "saves/gb/A/no-extension",
+            "saves/failed/gb/A/20230101-120000.srm",
+        ];
+        for file in files {
+            let path = directory.join(file);
+            create_dir_all(path.parent().unwrap()).await.unwrap();
+            tokio::fs::write(&path, b"data").await.unwrap();
+        }
+
+        let (notify_tx, _notify_rx) = mpsc::unbounded_channel();
+        let database = open_in_memory(&[], notify_tx).await;
+        let queued = database
+            .enqueue_upload(
+                UploadKind::Screenshot,
+                &directory.join(files[0]),
+                "ja/a",
+                None,
+            )
+            .await
+            .unwrap();
+
+        let (pre, _tx) = prepare();
+        pre.reconcile_uploads(
+            &database,
+            &screenshots,
+            &screenshots.join("extra"),
+            &screenshots.join("unknown"),
+            &saves,
+            "failed",
+        )
+        .await
+        .unwrap();
+
+        // Only the files nothing was queued for, outside failed and held
+        // screenshots
+        let backlog: Vec<_> = database
+            .load_upload_backlog()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|u| (u.kind, u.path, u.directory))
+            .collect();
+        assert_eq!(
+            backlog,
+            [
+                (queued.kind, queued.path, queued.directory),
+                (
+                    UploadKind::Screenshot,
+                    directory.join(files[1]),
+                    "ja/a".to_owned()
+                ),
+                (
+                    UploadKind::Extra,
+                    directory.join(files[2]),
+                    "extra".to_owned()
+                ),
+                (
+                    UploadKind::SaveScreenshot,
+                    directory.join(files[5]),
+                    "gb/A".to_owned()
+                ),
+                (
+                    UploadKind::Save,
+                    directory.join(files[6]),
+                    "gb/A".to_owned()
+                ),
+            ]
+        );
+
+        // Everything is now known, so a second start queues nothing more
+        pre.reconcile_uploads(
+            &database,
+            &screenshots,
+            &screenshots.join("extra"),
+            &screenshots.join("unknown"),
+            &saves,
+            "failed",
+        )
+        .await
+        .unwrap();
+        assert_eq!(database.load_upload_backlog().await.unwrap().len(), 5);
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,161 @@
+use anyhow::{Result, anyhow};
+use clap::{Args, Subcommand};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+// Edits go through the running study-sync's server, since it owns the plays
+// database and forwards corrections to intake
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// List the most recent plays
+    List {
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Change the start time, end time, or game of a play
+    Edit {
+        id: i64,
+
+        #[command(flatten)]
+        adjust: Adjust,
+    },
+
+    /// Stop syncing a play, e.g. one that was recorded by mistake
+    Skip { id: i64 },
+
+    /// Split a play in two at a Unix time
+    Split {
+        id: i64,
+
+        #[arg(long)]
+        at: u64,
+    },
+
+    /// Merge a later play of the same game into this one
+    Merge {
+        id: i64,
+
+        #[arg(long)]
+        with: i64,
+    },
+}
+
+#[derive(Debug)]
+pub enum Edit {
+    Adjust(Adjust),
+    Skip,
+    Split { at: u64 },
+    Merge { with: i64 },
+}
+
+#[derive(Args, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Adjust {
+    /// New start, as Unix time
+    #[arg(long)]
+    pub start_time: Option<u64>,
+
+    /// New end, as Unix time
+    #[arg(long)]
+    pub end_time: Option<u64>,
+
+    /// Path of the game the play was actually of
+    #[arg(long)]
+    pub game: Option<PathBuf>,
+}
+
+// Marks an edit that was refused, such as one ending a play before it starts
+#[derive(Debug)]
+pub struct Invalid(pub String);
+
+impl std::fmt::Display for Invalid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Invalid {}
+
+pub fn is_invalid(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<Invalid>().is_some()
+}
+
+impl Adjust {
+    pub fn times(&self, start_time: u64, end_time: u64) -> Result<(u64, u64), Invalid> {
+        let start_time = self.start_time.unwrap_or(start_time);
+        let end_time = self.end_time.unwrap_or(end_time);
+        if end_time < start_time {
+            return Err(Invalid(format!(
+                "end time {end_time} is before start time {start_time}"
+            )));
+        }
+        Ok((start_time, end_time))
+    }
+}
+
+pub fn check_split(start_time: u64, end_time: u64, at: u64) -> Result<(), Invalid> {
+    if at <= start_time || at >= end_time {
+        return Err(Invalid(format!(
+            "split time {at} is not between {start_time} and {end_time}"
+        )));
+    }
+    Ok(())
+}
+
+pub async fn run(command: Command, listen: &str) -> Result<()> {
+    let base = format!("http://{listen}");
+    let client = reqwest::Client::new();
+
+    let request = match command {
+        Command::List { limit } => client
+            .get(format!("{base}/plays"))
+            .query(&[("limit", limit)]),
+        Command::Edit { id, adjust } => client.patch(format!("{base}/plays/{id}")).json(&adjust),
+        Command::Skip { id } => client.post(format!("{base}/plays/{id}/skip")),
+        Command::Split { id, at } => client
+            .post(format!("{base}/plays/{id}/split"))
+            .query(&[("at", at)]),
+        Command::Merge { id, with } => client
+            .post(format!("{base}/plays/{id}/merge"))
+            .query(&[("with", with)]),
+    };
+
+    let response = request.send().await?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(anyhow!("study-sync responded {status}: {body}"));
+    }
+
+    println!("{}", body.trim_end());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_adjust_times() {
+        let adjust = |start_time, end_time| Adjust {
+            start_time,
+            end_time,
+            game: None,
+        };
+
+        assert_eq!(adjust(None, None).times(100, 200).unwrap(), (100, 200));
+        assert_eq!(adjust(Some(50), None).times(100, 200).unwrap(), (50, 200));
+        assert_eq!(adjust(None, Some(100)).times(100, 200).unwrap(), (100, 100));
+        assert!(adjust(Some(250), None).times(100, 200).is_err());
+        assert!(adjust(Some(150), Some(120)).times(100, 200).is_err());
+    }
+
+    #[test]
+    fn test_check_split() {
+        assert!(check_split(100, 200, 150).is_ok());
+        assert!(check_split(100, 200, 100).is_err());
+        assert!(check_split(100, 200, 200).is_err());
+        assert!(check_split(100, 200, 50).is_err());
+    }
+}
@@ -0,0 +1,373 @@
+use crate::{
+    database::Database,
+    internal::{
+        fs::{full_extension, recursive_files_in, sha1_file},
+        store::OBJECTS_DIRECTORY,
+    },
+    orchestrator::UploadKind,
+};
+use anyhow::Result;
+use chrono::{IsoWeek, NaiveDate, NaiveDateTime, TimeDelta, prelude::*};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::fs::{metadata, remove_file, try_exists};
+use tracing::info;
+
+// Only settable in the config file, under [retention]. Without it, nothing
+// in keep_saves is pruned
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionPolicy {
+    // Keep every save from this many days
+    pub all_days: u64,
+    // Then the newest save each day, back to this many days
+    pub daily_days: u64,
+    // Then the newest save each week, or none at all
+    pub weekly: bool,
+    // Regardless of age, keep this many of the newest saves of each kind per
+    // game
+    pub newest: usize,
+    // How often study-sync prunes while running
+    pub interval_hours: u64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy {
+            all_days: 7,
+            daily_days: 90,
+            weekly: true,
+            newest: 3,
+            interval_hours: 24,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_hours.max(1) * 60 * 60)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub pruned: Vec<PathBuf>,
+    // Past retention, but not uploaded yet
+    pub unconfirmed: Vec<PathBuf>,
+}
+
+struct Save {
+    path: PathBuf,
+    time: NaiveDateTime,
+}
+
+// Removes kept saves, and their screenshots, outside the policy. Only files
+// the saves component has uploaded are removed. With dry_run, the report
+// lists what would be pruned without removing anything. Leaves the store
+// alone, so can run alongside saves being kept
+pub async fn prune_saves(
+    database: &Database,
+    keep_saves: &Path,
+    pending_saves: &Path,
+    policy: &RetentionPolicy,
+    dry_run: bool,
+) -> Result<Report> {
+    let mut report = Report::default();
+    let now = Local::now().naive_local();
+
+    for mut saves in kept_saves(keep_saves).into_values() {
+        saves.sort_by_key(|s| std::cmp::Reverse(s.time));
+        let times: Vec<_> = saves.iter().map(|s| s.time).collect();
+
+        for (save, keep) in saves.iter().zip(retained(&times, policy, now)) {
+            if keep {
+                continue;
+            }
+
+            let mut screenshot = save.path.clone();
+            screenshot.set_extension("png");
+
+            // The screenshot identifies the save, so goes only with it
+            for (path, kind) in [
+                (&save.path, UploadKind::Save),
+                (&screenshot, UploadKind::SaveScreenshot),
+            ] {
+                if !try_exists(path).await? {
+                    continue;
+                }
+                if !uploaded(database, keep_saves, pending_saves, path, kind).await? {
+                    report.unconfirmed.push(path.clone());
+                    break;
+                }
+                if !dry_run {
+                    remove_file(path).await?;
+                }
+                report.pruned.push(path.clone());
+            }
+        }
+    }
+
+    info!(
+        "Pruned {} kept files, leaving {} not yet uploaded",
+        report.pruned.len(),
+        report.unconfirmed.len()
+    );
+
+    Ok(report)
+}
+
+// Timestamped saves, grouped by game and kind of save
+fn kept_saves(keep_saves: &Path) -> BTreeMap<(PathBuf, String), Vec<Save>> {
+    let mut saves: BTreeMap<_, Vec<_>> = BTreeMap::new();
+
+    for path in recursive_files_in(keep_saves, Some(1)) {
+        if path
+            .strip_prefix(keep_saves)
+            .is_ok_and(|p| p.starts_with(OBJECTS_DIRECTORY))
+        {
+            continue;
+        }
+
+        let Some(extension) = full_extension(&path).filter(|e| *e != "png") else {
+            continue;
+        };
+        let Some(time) = path
+            .file_name()
+            .and_then(std::ffi::OsStr::to_str)
+            .and_then(|b| b.split_once('.'))
+            .and_then(|(stem, _)| NaiveDateTime::parse_from_str(stem, "%Y%m%d-%H%M%S").ok())
+        else {
+            continue;
+        };
+        let Some(parent) = path.parent() else {
+            continue;
+        };
+
+        saves
+            .entry((parent.to_path_buf(), extension.to_owned()))
+            .or_default()
+            .push(Save {
+                path: path.clone(),
+                time,
+            });
+    }
+
+    saves
+}
+
+// Whether the upload of the file's pending copy finished. A duplicate save
+// that was kept but never uploaded counts if an identical save was
+async fn uploaded(
+    database: &Database,
+    keep_saves: &Path,
+    pending_saves: &Path,
+    path: &Path,
+    kind: UploadKind,
+) -> Result<bool> {
+    let pending = pending_saves.join(path.strip_prefix(keep_saves)?);
+    if database.upload_done(&pending).await? {
+        return Ok(true);
+    }
+
+    let digest = {
+        let path = path.to_owned();
+        tokio::task::spawn_blocking(move || sha1_file(&path)).await??
+    };
+    database.digest_uploaded(kind, &digest).await
+}
+
+// Stored saves no longer kept under any name. Only the orchestrator calls
+// this, between keeping saves, since a save is only linked after it's stored
+pub async fn remove_orphaned_objects(keep_saves: &Path) -> Result<usize> {
+    let mut removed = 0;
+    for object in recursive_files_in(keep_saves.join(OBJECTS_DIRECTORY), Some(2)) {
+        if metadata(&object).await?.nlink() == 1 {
+            remove_file(&object).await?;
+            removed += 1;
+        }
+    }
+    info!("Removed {removed} stored saves no longer kept");
+    Ok(removed)
+}
+
+// Whether to keep each save, given their times newest first. Each day or
+// week is represented by its newest save
+pub fn retained(
+    times: &[NaiveDateTime],
+    policy: &RetentionPolicy,
+    now: NaiveDateTime,
+) -> Vec<bool> {
+    let mut days: HashSet<NaiveDate> = HashSet::new();
+    let mut weeks: HashSet<IsoWeek> = HashSet::new();
+
+    times
+        .iter()
+        .enumerate()
+        .map(|(i, time)| {
+            let age = now - *time;
+            let (day, week) = (time.date(), time.iso_week());
+
+            let keep = if i < policy.newest || age < TimeDelta::days(policy.all_days as i64) {
+                true
+            } else if age < TimeDelta::days(policy.daily_days as i64) {
+                !days.contains(&day)
+            } else {
+                policy.weekly && !weeks.contains(&week)
+            };
+
+            if keep {
+                days.insert(day);
+                weeks.insert(week);
+            }
+            keep
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        database::test::open_in_memory,
+        internal::{idempotency::new_key, store::store},
+    };
+    use tokio::fs::{create_dir_all, hard_link, write};
+    use tokio::sync::mpsc;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y%m%d-%H%M%S").unwrap()
+    }
+
+    #[test]
+    fn test_retained() {
+        let policy = RetentionPolicy {
+            newest: 1,
+            ..RetentionPolicy::default()
+        };
+        let now = at("20240601-120000");
+        let times = [
+            "20240531-090000", // within all_days
+            "20240528-090000", // within all_days
+            "20240520-220000", // newest that day
+            "20240520-080000", // same day
+            "20240519-080000", // another day
+            "20240210-080000", // newest that week
+            "20240209-080000", // same week
+            "20240201-080000", // another week
+        ]
+        .map(at);
+
+        assert_eq!(
+            retained(&times, &policy, now),
+            [true, true, true, false, true, true, false, true],
+        );
+
+        let policy = RetentionPolicy {
+            weekly: false,
+            newest: 6,
+            ..RetentionPolicy::default()
+        };
+        assert_eq!(
+            retained(&times, &policy, now),
+            [true, true, true, true, true, true, false, false],
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prune() {
+        let (notify_tx, _notify_rx) = mpsc::unbounded_channel();
+        let database = open_in_memory(&[], notify_tx).await;
+        let directory = std::env::temp_dir().join(format!("study-sync-test-{}", new_key()));
+        let (keep_saves, pending_saves) = (directory.join("keep"), directory.join("pending"));
+        let game = keep_saves.join("gb/A");
+        create_dir_all(&game).await.unwrap();
+
+        // Newest first. The newest is always kept, and shares its contents
+        // with the next
+        let mut objects = vec![];
+        for (name, contents, uploaded) in [
+            ("20200103-000000.srm", "b", false),
+            ("20200102-000000.srm", "b", true),
+            ("20200102-000000.png", "", true),
+            ("20200101-000000.srm", "a", false),
+            ("20191231-000000.srm", "c", true),
+        ] {
+            let path = game.join(name);
+            if name.ends_with(".png") {
+                write(&path, contents).await.unwrap();
+            } else {
+                let source = directory.join("source");
+                write(&source, contents).await.unwrap();
+                let (object, _) = store(&keep_saves, &source).await.unwrap();
+                hard_link(&object, &path).await.unwrap();
+                objects.push(object);
+            }
+
+            if uploaded {
+                let pending = pending_saves.join(path.strip_prefix(&keep_saves).unwrap());
+                let kind = if name.ends_with(".png") {
+                    UploadKind::SaveScreenshot
+                } else {
+                    UploadKind::Save
+                };
+                let upload = database
+                    .enqueue_upload(kind, &pending, "gb/A", None)
+                    .await
+                    .unwrap();
+                database.upload_finished(upload.id).await.unwrap();
+            }
+        }
+
+        let policy = RetentionPolicy {
+            all_days: 0,
+            daily_days: 0,
+            weekly: false,
+            newest: 1,
+            ..RetentionPolicy::default()
+        };
+
+        let report = prune_saves(&database, &keep_saves, &pending_saves, &policy, true)
+            .await
+            .unwrap();
+        assert_eq!(report.pruned.len(), 3);
+        assert!(try_exists(game.join("20191231-000000.srm")).await.unwrap());
+
+        let report = prune_saves(&database, &keep_saves, &pending_saves, &policy, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            report.pruned,
+            [
+                game.join("20200102-000000.srm"),
+                game.join("20200102-000000.png"),
+                game.join("20191231-000000.srm"),
+            ],
+        );
+        assert_eq!(report.unconfirmed, [game.join("20200101-000000.srm")]);
+        for (name, exists) in [
+            ("20200103-000000.srm", true),
+            ("20200102-000000.srm", false),
+            ("20200102-000000.png", false),
+            ("20200101-000000.srm", true),
+            ("20191231-000000.srm", false),
+        ] {
+            assert_eq!(try_exists(game.join(name)).await.unwrap(), exists, "{name}");
+        }
+
+        // Only the object no kept save links to is removed
+        assert_eq!(remove_orphaned_objects(&keep_saves).await.unwrap(), 1);
+        let [b, _, a, c] = &objects[..] else {
+            panic!("{objects:?}");
+        };
+        assert!(try_exists(b).await.unwrap());
+        assert!(try_exists(a).await.unwrap());
+        assert!(!try_exists(c).await.unwrap());
+
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+    }
+}
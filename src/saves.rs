@@ -1,22 +1,25 @@
 use crate::{
     internal::{
-        channel::{Action, PriorityRetryChannel},
+        channel::{Action, ChannelStatus, PriorityRetryChannel, RetryPolicy},
+        failure::action_for,
         notifier::Notifier,
         online::Online,
-        uploader::Uploader,
+        uploader::{Digests, Resumable, Uploader},
     },
-    notify, orchestrator,
+    notify,
+    orchestrator::{self, Upload},
 };
 use anyhow::Result;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::fs::remove_file;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tracing::{error, info};
 
 #[derive(Debug)]
 pub enum Event {
-    UploadSave(PathBuf, PathBuf),
-    UploadScreenshot(PathBuf, PathBuf),
+    UploadSave(Upload),
+    UploadScreenshot(Upload),
     IsOnline(bool),
     ForceSync,
     StartShutdown,
@@ -24,35 +27,56 @@ pub enum Event {
 
 pub struct SavesPre {
     rx: mpsc::UnboundedReceiver<Event>,
+    status_tx: watch::Sender<ChannelStatus>,
 }
 
 pub struct Saves {
+    status_tx: watch::Sender<ChannelStatus>,
     orchestrator_tx: mpsc::UnboundedSender<orchestrator::Event>,
     notify_tx: mpsc::UnboundedSender<notify::Event>,
     save_url: String,
-    digest_cache: Option<(PathBuf, String)>,
+    digests: Digests,
     is_online: bool,
+    retry_policy: RetryPolicy,
+    upload_timeout: Duration,
+    failed_directory: PathBuf,
+    resumable: Resumable,
 }
 
 pub fn prepare() -> (SavesPre, mpsc::UnboundedSender<Event>) {
     let (tx, rx) = mpsc::unbounded_channel();
-    (SavesPre { rx }, tx)
+    let (status_tx, _) = watch::channel(ChannelStatus::default());
+    (SavesPre { rx, status_tx }, tx)
 }
 
 impl SavesPre {
+    #![allow(clippy::too_many_arguments)]
+    pub fn status(&self) -> watch::Receiver<ChannelStatus> {
+        self.status_tx.subscribe()
+    }
+
     pub async fn start(
         self,
         orchestrator_tx: mpsc::UnboundedSender<orchestrator::Event>,
         notify_tx: mpsc::UnboundedSender<notify::Event>,
         save_url: String,
         is_online: bool,
+        retry_policy: RetryPolicy,
+        upload_timeout: Duration,
+        failed_directory: PathBuf,
+        chunk_size: u64,
     ) -> Result<()> {
         let saves = Saves {
+            status_tx: self.status_tx,
             orchestrator_tx,
             notify_tx,
             save_url,
-            digest_cache: None,
+            digests: Digests::default(),
             is_online,
+            retry_policy,
+            upload_timeout,
+            failed_directory,
+            resumable: Resumable::new(chunk_size),
         };
         saves.start(self.rx).await
     }
@@ -65,13 +89,9 @@ impl Saves {
         Ok(())
     }
 
-    async fn upload_file(
-        &mut self,
-        path: &Path,
-        directory: &Path,
-        is_screenshot: bool,
-    ) -> Result<()> {
-        let extension = path
+    async fn upload_file(&mut self, upload: &Upload, is_screenshot: bool) -> Result<()> {
+        let extension = upload
+            .path
             .extension()
             .and_then(std::ffi::OsStr::to_str)
             .unwrap_or(if is_screenshot { "png" } else { "unk" });
@@ -87,7 +107,7 @@ impl Saves {
         };
 
         let url = self.save_url.clone();
-        self.upload_path_to_directory(&url, path, directory.to_str().unwrap(), content_type)
+        self.upload_path_to_directory(&url, upload, &upload.directory, content_type)
             .await
     }
 }
@@ -99,12 +119,20 @@ impl Notifier for Saves {
 }
 
 impl Uploader for Saves {
-    fn get_digest_cache(&self) -> &Option<(PathBuf, String)> {
-        &self.digest_cache
+    fn digests(&mut self) -> &mut Digests {
+        &mut self.digests
+    }
+
+    fn resumable(&mut self) -> &mut Resumable {
+        &mut self.resumable
     }
 
-    fn set_digest_cache(&mut self, cache: Option<(PathBuf, String)>) {
-        self.digest_cache = cache;
+    fn upload_timeout(&self) -> Duration {
+        self.upload_timeout
+    }
+
+    fn failed_directory(&self) -> &Path {
+        &self.failed_directory
     }
 }
 
@@ -125,14 +153,31 @@ impl PriorityRetryChannel for Saves {
         self.is_online
     }
 
+    fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    fn status_tx(&self) -> &watch::Sender<ChannelStatus> {
+        &self.status_tx
+    }
+
     fn is_high_priority(&self, event: &Event) -> bool {
         match event {
             Event::StartShutdown => true,
             Event::IsOnline(_) => true,
             Event::ForceSync => true,
 
-            Event::UploadSave(_, _) => false,
-            Event::UploadScreenshot(_, _) => false,
+            Event::UploadSave(_) => false,
+            Event::UploadScreenshot(_) => false,
+        }
+    }
+
+    async fn dead_letter(&mut self, event: Event) {
+        match event {
+            Event::UploadSave(upload) | Event::UploadScreenshot(upload) => {
+                self.dead_letter_upload(&upload).await
+            }
+            _ => error!("Giving up on {event:?}"),
         }
     }
 
@@ -152,12 +197,16 @@ impl PriorityRetryChannel for Saves {
                 Action::ResetTimeout
             }
 
-            Event::UploadSave(path, directory) => {
-                if let Err(e) = self.upload_file(path, directory, false).await {
+            Event::UploadSave(upload) => {
+                let path = &upload.path;
+                if let Err(e) = self.upload_file(upload, false).await {
                     error!("Could not upload {path:?}: {e:?}");
-                    return Action::Retry;
+                    self.report_upload_failed(upload, &e);
+                    return action_for(&e);
                 }
 
+                self.report_upload_finished(upload);
+
                 if let Err(e) = remove_file(&path).await {
                     self.notify_error(&format!(
                         "Could not remove uploaded save file {path:?}: {e:?}"
@@ -170,12 +219,16 @@ impl PriorityRetryChannel for Saves {
                 Action::Continue
             }
 
-            Event::UploadScreenshot(path, directory) => {
-                if let Err(e) = self.upload_file(path, directory, true).await {
+            Event::UploadScreenshot(upload) => {
+                let path = &upload.path;
+                if let Err(e) = self.upload_file(upload, true).await {
                     error!("Could not upload {path:?}: {e:?}");
-                    return Action::Retry;
+                    self.report_upload_failed(upload, &e);
+                    return action_for(&e);
                 }
 
+                self.report_upload_finished(upload);
+
                 if let Err(e) = remove_file(&path).await {
                     self.notify_error(&format!(
                         "Could not remove uploaded save screenshot file {path:?}: {e:?}"
@@ -1,22 +1,25 @@
 use crate::{
     internal::{
-        channel::{Action, PriorityRetryChannel},
+        channel::{Action, ChannelStatus, PriorityRetryChannel, RetryPolicy},
+        failure::action_for,
         notifier::Notifier,
         online::Online,
-        uploader::Uploader,
+        uploader::{Digests, Resumable, Uploader},
     },
-    notify, orchestrator,
+    notify,
+    orchestrator::{self, Upload},
 };
 use anyhow::Result;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::fs::remove_file;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tracing::{error, info};
 
 #[derive(Debug)]
 pub enum Event {
-    UploadScreenshot(PathBuf, String),
-    UploadExtra(PathBuf),
+    UploadScreenshot(Upload),
+    UploadExtra(Upload),
     IsOnline(bool),
     ForceSync,
     StartShutdown,
@@ -24,23 +27,35 @@ pub enum Event {
 
 pub struct ScreenshotsPre {
     rx: mpsc::UnboundedReceiver<Event>,
+    status_tx: watch::Sender<ChannelStatus>,
 }
 
 pub struct Screenshots {
+    status_tx: watch::Sender<ChannelStatus>,
     orchestrator_tx: mpsc::UnboundedSender<orchestrator::Event>,
     notify_tx: mpsc::UnboundedSender<notify::Event>,
     screenshot_url: String,
     extra_directory: String,
-    digest_cache: Option<(PathBuf, String)>,
+    digests: Digests,
     is_online: bool,
+    retry_policy: RetryPolicy,
+    upload_timeout: Duration,
+    failed_directory: PathBuf,
+    resumable: Resumable,
 }
 
 pub fn prepare() -> (ScreenshotsPre, mpsc::UnboundedSender<Event>) {
     let (tx, rx) = mpsc::unbounded_channel();
-    (ScreenshotsPre { rx }, tx)
+    let (status_tx, _) = watch::channel(ChannelStatus::default());
+    (ScreenshotsPre { rx, status_tx }, tx)
 }
 
 impl ScreenshotsPre {
+    #![allow(clippy::too_many_arguments)]
+    pub fn status(&self) -> watch::Receiver<ChannelStatus> {
+        self.status_tx.subscribe()
+    }
+
     pub async fn start(
         self,
         orchestrator_tx: mpsc::UnboundedSender<orchestrator::Event>,
@@ -48,14 +63,23 @@ impl ScreenshotsPre {
         screenshot_url: String,
         extra_directory: String,
         is_online: bool,
+        retry_policy: RetryPolicy,
+        upload_timeout: Duration,
+        failed_directory: PathBuf,
+        chunk_size: u64,
     ) -> Result<()> {
         let mut screenshots = Screenshots {
+            status_tx: self.status_tx,
             orchestrator_tx,
             notify_tx,
             screenshot_url,
             extra_directory,
-            digest_cache: None,
+            digests: Digests::default(),
             is_online,
+            retry_policy,
+            upload_timeout,
+            failed_directory,
+            resumable: Resumable::new(chunk_size),
         };
         screenshots.start(self.rx).await
     }
@@ -68,8 +92,9 @@ impl Screenshots {
         Ok(())
     }
 
-    async fn upload_screenshot(&mut self, path: &Path, directory: &str) -> Result<()> {
-        let extension = path
+    async fn upload_screenshot(&mut self, upload: &Upload, directory: &str) -> Result<()> {
+        let extension = upload
+            .path
             .extension()
             .and_then(std::ffi::OsStr::to_str)
             .unwrap_or("png");
@@ -81,7 +106,7 @@ impl Screenshots {
         };
 
         let url = self.screenshot_url.clone();
-        self.upload_path_to_directory(&url, path, directory, Some(content_type))
+        self.upload_path_to_directory(&url, upload, directory, Some(content_type))
             .await
     }
 }
@@ -93,12 +118,20 @@ impl Notifier for Screenshots {
 }
 
 impl Uploader for Screenshots {
-    fn get_digest_cache(&self) -> &Option<(PathBuf, String)> {
-        &self.digest_cache
+    fn digests(&mut self) -> &mut Digests {
+        &mut self.digests
     }
 
-    fn set_digest_cache(&mut self, cache: Option<(PathBuf, String)>) {
-        self.digest_cache = cache;
+    fn resumable(&mut self) -> &mut Resumable {
+        &mut self.resumable
+    }
+
+    fn upload_timeout(&self) -> Duration {
+        self.upload_timeout
+    }
+
+    fn failed_directory(&self) -> &Path {
+        &self.failed_directory
     }
 }
 
@@ -119,17 +152,34 @@ impl PriorityRetryChannel for Screenshots {
         self.is_online
     }
 
+    fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    fn status_tx(&self) -> &watch::Sender<ChannelStatus> {
+        &self.status_tx
+    }
+
     fn is_high_priority(&self, event: &Event) -> bool {
         match event {
             Event::StartShutdown => true,
             Event::IsOnline(_) => true,
             Event::ForceSync => true,
 
-            Event::UploadScreenshot(_, _) => false,
+            Event::UploadScreenshot(_) => false,
             Event::UploadExtra(_) => false,
         }
     }
 
+    async fn dead_letter(&mut self, event: Event) {
+        match event {
+            Event::UploadScreenshot(upload) | Event::UploadExtra(upload) => {
+                self.dead_letter_upload(&upload).await
+            }
+            _ => error!("Giving up on {event:?}"),
+        }
+    }
+
     async fn handle(&mut self, event: &Event) -> Action {
         info!("Handling event {event:?}");
 
@@ -146,12 +196,16 @@ impl PriorityRetryChannel for Screenshots {
                 Action::ResetTimeout
             }
 
-            Event::UploadScreenshot(path, directory) => {
-                if let Err(e) = self.upload_screenshot(path, directory).await {
+            Event::UploadScreenshot(upload) => {
+                let path = &upload.path;
+                if let Err(e) = self.upload_screenshot(upload, &upload.directory).await {
                     error!("Could not upload {path:?}: {e:?}");
-                    return Action::Retry;
+                    self.report_upload_failed(upload, &e);
+                    return action_for(&e);
                 }
 
+                self.report_upload_finished(upload);
+
                 if let Err(e) = remove_file(&path).await {
                     self.notify_error(&format!(
                         "Could not remove uploaded screenshot file {path:?}: {e:?}"
@@ -163,13 +217,17 @@ impl PriorityRetryChannel for Screenshots {
                 Action::Continue
             }
 
-            Event::UploadExtra(path) => {
+            Event::UploadExtra(upload) => {
+                let path = &upload.path;
                 let directory = self.extra_directory.clone();
-                if let Err(e) = self.upload_screenshot(path, &directory).await {
+                if let Err(e) = self.upload_screenshot(upload, &directory).await {
                     error!("Could not upload {path:?}: {e:?}");
-                    return Action::Retry;
+                    self.report_upload_failed(upload, &e);
+                    return action_for(&e);
                 }
 
+                self.report_upload_finished(upload);
+
                 if let Err(e) = remove_file(&path).await {
                     self.notify_error(&format!(
                         "Could not remove extra screenshot file {path:?}: {e:?}"
@@ -1,15 +1,25 @@
-use crate::{internal::notifier::Notifier, notify, orchestrator};
+use crate::{
+    database::{Database, FailedPlay, FailedUpload},
+    export,
+    internal::{channel::ChannelStatus, notifier::Notifier},
+    notify, orchestrator,
+    plays::{self, Adjust, Edit},
+    stats,
+};
 use anyhow::{Result, anyhow};
 use axum::{
-    Router,
-    extract::{Query, Request, State},
-    http::StatusCode,
+    Json, Router,
+    extract::{Path, Query, Request, State},
+    http::{StatusCode, header::CONTENT_TYPE},
     response::{IntoResponse, Response},
-    routing::{get, post},
+    routing::{get, patch, post},
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{path::PathBuf, sync::Arc, time::Duration};
-use tokio::{fs::canonicalize, sync::mpsc};
+use tokio::{
+    fs::canonicalize,
+    sync::{mpsc, oneshot, watch},
+};
 use tower_http::trace::TraceLayer;
 use tracing::{Span, info, info_span, warn};
 
@@ -22,9 +32,19 @@ pub struct ServerPre {
     rx: mpsc::UnboundedReceiver<Event>,
 }
 
+pub struct Statuses {
+    pub orchestrator: watch::Receiver<orchestrator::Status>,
+    pub intake: watch::Receiver<ChannelStatus>,
+    pub screenshots: watch::Receiver<ChannelStatus>,
+    pub saves: watch::Receiver<ChannelStatus>,
+    pub notify: watch::Receiver<ChannelStatus>,
+}
+
 pub struct Server {
     orchestrator_tx: mpsc::UnboundedSender<orchestrator::Event>,
     notify_tx: mpsc::UnboundedSender<notify::Event>,
+    statuses: Statuses,
+    database: Database,
 }
 
 pub fn prepare() -> (ServerPre, mpsc::UnboundedSender<Event>) {
@@ -38,10 +58,14 @@ impl ServerPre {
         address: &std::net::SocketAddr,
         orchestrator_tx: mpsc::UnboundedSender<orchestrator::Event>,
         notify_tx: mpsc::UnboundedSender<notify::Event>,
+        statuses: Statuses,
+        database: Database,
     ) -> Result<()> {
         let server = Server {
             orchestrator_tx,
             notify_tx,
+            statuses,
+            database,
         };
 
         let listener = tokio::net::TcpListener::bind(address).await?;
@@ -72,6 +96,17 @@ fn router(server: Server) -> Router {
         .route("/online", post(online_post))
         .route("/offline", post(offline_post))
         .route("/sync", post(sync_post))
+        .route("/pause", post(pause_post))
+        .route("/resume", post(resume_post))
+        .route("/status", get(status_get))
+        .route("/plays", get(plays_get))
+        .route("/plays/{id}", patch(play_patch))
+        .route("/plays/{id}/skip", post(play_skip_post))
+        .route("/plays/{id}/split", post(play_split_post))
+        .route("/plays/{id}/merge", post(play_merge_post))
+        .route("/stats", get(stats_get))
+        .route("/dead-letters", get(dead_letters_get))
+        .route("/dead-letters/requeue", post(requeue_post))
         .with_state(Arc::new(server))
         .layer(
             TraceLayer::new_for_http()
@@ -170,6 +205,218 @@ async fn sync_post(State(server): State<Arc<Server>>) -> Response {
     StatusCode::NO_CONTENT.into_response()
 }
 
+async fn pause_post(State(server): State<Arc<Server>>) -> Response {
+    if let Err(e) = server.orchestrator_tx.send(orchestrator::Event::Paused) {
+        let e = anyhow!(e).context("failed to send event to orchestrator");
+        server.notify_error(&e.to_string());
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    StatusCode::NO_CONTENT.into_response()
+}
+
+async fn resume_post(State(server): State<Arc<Server>>) -> Response {
+    if let Err(e) = server.orchestrator_tx.send(orchestrator::Event::Resumed) {
+        let e = anyhow!(e).context("failed to send event to orchestrator");
+        server.notify_error(&e.to_string());
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    StatusCode::NO_CONTENT.into_response()
+}
+
+#[derive(Debug, Serialize)]
+struct StatusResponse {
+    orchestrator: orchestrator::Status,
+    intake: ChannelStatus,
+    screenshots: ChannelStatus,
+    saves: ChannelStatus,
+    notify: ChannelStatus,
+}
+
+async fn status_get(State(server): State<Arc<Server>>) -> Json<StatusResponse> {
+    let statuses = &server.statuses;
+    Json(StatusResponse {
+        orchestrator: statuses.orchestrator.borrow().clone(),
+        intake: statuses.intake.borrow().clone(),
+        screenshots: statuses.screenshots.borrow().clone(),
+        saves: statuses.saves.borrow().clone(),
+        notify: statuses.notify.borrow().clone(),
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct PlaysParams {
+    #[serde(default)]
+    format: export::Format,
+    #[serde(flatten)]
+    filter: export::Filter,
+}
+
+async fn plays_get(
+    Query(params): Query<PlaysParams>,
+    State(server): State<Arc<Server>>,
+) -> Response {
+    if let Err(e) = params.filter.range() {
+        return (StatusCode::BAD_REQUEST, format!("{e:#}")).into_response();
+    }
+
+    match export::export(&server.database, params.format, &params.filter).await {
+        Ok(body) => ([(CONTENT_TYPE, params.format.content_type())], body).into_response(),
+        Err(e) => {
+            let e = e.context("failed to export plays");
+            server.notify_error(&e.to_string());
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn edit_play(server: &Server, play_id: i64, edit: Edit) -> Response {
+    let (reply, rx) = oneshot::channel();
+    let event = orchestrator::Event::EditPlay {
+        play_id,
+        edit,
+        reply,
+    };
+    if let Err(e) = server.orchestrator_tx.send(event) {
+        let e = anyhow!(e).context("failed to send event to orchestrator");
+        server.notify_error(&e.to_string());
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    match rx.await {
+        Ok(Ok(plays)) => Json(plays).into_response(),
+        Ok(Err(e)) if plays::is_invalid(&e) => {
+            (StatusCode::BAD_REQUEST, format!("{e:#}")).into_response()
+        }
+        Ok(Err(e)) => {
+            let e = e.context("failed to edit play");
+            server.notify_error(&e.to_string());
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(e) => {
+            let e = anyhow!(e).context("orchestrator dropped play edit");
+            server.notify_error(&e.to_string());
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn play_patch(
+    Path(play_id): Path<i64>,
+    State(server): State<Arc<Server>>,
+    Json(adjust): Json<Adjust>,
+) -> Response {
+    edit_play(&server, play_id, Edit::Adjust(adjust)).await
+}
+
+async fn play_skip_post(Path(play_id): Path<i64>, State(server): State<Arc<Server>>) -> Response {
+    edit_play(&server, play_id, Edit::Skip).await
+}
+
+#[derive(Debug, Deserialize)]
+struct SplitParams {
+    at: u64,
+}
+
+async fn play_split_post(
+    Path(play_id): Path<i64>,
+    Query(params): Query<SplitParams>,
+    State(server): State<Arc<Server>>,
+) -> Response {
+    edit_play(&server, play_id, Edit::Split { at: params.at }).await
+}
+
+#[derive(Debug, Deserialize)]
+struct MergeParams {
+    with: i64,
+}
+
+async fn play_merge_post(
+    Path(play_id): Path<i64>,
+    Query(params): Query<MergeParams>,
+    State(server): State<Arc<Server>>,
+) -> Response {
+    edit_play(&server, play_id, Edit::Merge { with: params.with }).await
+}
+
+#[derive(Debug, Deserialize)]
+struct StatsParams {
+    #[serde(default)]
+    period: stats::Period,
+    #[serde(flatten)]
+    filter: export::Filter,
+}
+
+async fn stats_get(
+    Query(params): Query<StatsParams>,
+    State(server): State<Arc<Server>>,
+) -> Response {
+    if let Err(e) = params.filter.range() {
+        return (StatusCode::BAD_REQUEST, format!("{e:#}")).into_response();
+    }
+
+    match stats::stats(&server.database, params.period, &params.filter).await {
+        Ok(stats) => Json(stats).into_response(),
+        Err(e) => {
+            let e = e.context("failed to compute stats");
+            server.notify_error(&e.to_string());
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DeadLettersResponse {
+    uploads: Vec<FailedUpload>,
+    plays: Vec<FailedPlay>,
+}
+
+async fn dead_letters_get(State(server): State<Arc<Server>>) -> Response {
+    let uploads = server.database.failed_uploads().await;
+    let plays = server.database.failed_plays().await;
+
+    match (uploads, plays) {
+        (Ok(uploads), Ok(plays)) => Json(DeadLettersResponse { uploads, plays }).into_response(),
+        (Err(e), _) | (_, Err(e)) => {
+            let e = e.context("failed to load dead letters");
+            server.notify_error(&e.to_string());
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RequeueParams {
+    upload: Option<i64>,
+    play: Option<i64>,
+}
+
+async fn requeue_post(
+    Query(params): Query<RequeueParams>,
+    State(server): State<Arc<Server>>,
+) -> Response {
+    let event = match (params.upload, params.play) {
+        (Some(upload_id), None) => orchestrator::Event::RequeueUpload(upload_id),
+        (None, Some(play_id)) => orchestrator::Event::RequeuePlay(play_id),
+        _ => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "expected exactly one of upload or play",
+            )
+                .into_response();
+        }
+    };
+
+    if let Err(e) = server.orchestrator_tx.send(event) {
+        let e = anyhow!(e).context("failed to send event to orchestrator");
+        server.notify_error(&e.to_string());
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    StatusCode::NO_CONTENT.into_response()
+}
+
 impl Notifier for Server {
     fn notify_tx(&self) -> &mpsc::UnboundedSender<notify::Event> {
         &self.notify_tx
@@ -0,0 +1,336 @@
+use crate::{
+    database::Database,
+    export::{self, Filter, PlayRecord},
+};
+use anyhow::Result;
+use chrono::{Days, prelude::*};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+const LONGEST_SESSIONS: usize = 5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Total {
+    pub key: String,
+    pub plays: u64,
+    pub seconds: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Session {
+    pub id: i64,
+    pub label: String,
+    pub start_time: u64,
+    pub seconds: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub plays: u64,
+    pub seconds: u64,
+    pub games: Vec<Total>,
+    pub languages: Vec<Total>,
+    pub period: Period,
+    pub periods: Vec<Total>,
+    pub longest_sessions: Vec<Session>,
+    // Consecutive days played, ending today or yesterday
+    pub current_streak: u64,
+    pub longest_streak: u64,
+}
+
+pub async fn stats(database: &Database, period: Period, filter: &Filter) -> Result<Stats> {
+    let records = export::records(database, filter).await?;
+    Ok(compute(&records, period, &Local, Local::now().date_naive()))
+}
+
+// Only finished plays count, and skipped plays are ignored entirely. Time
+// spent paused isn't counted as playtime
+pub fn compute<Tz: TimeZone>(
+    records: &[PlayRecord],
+    period: Period,
+    tz: &Tz,
+    today: NaiveDate,
+) -> Stats {
+    let mut plays = 0;
+    let mut seconds = 0;
+    let mut games = BTreeMap::new();
+    let mut languages = BTreeMap::new();
+    let mut periods = BTreeMap::new();
+    let mut days = BTreeSet::new();
+    let mut sessions = vec![];
+
+    for record in records {
+        let Some(duration) = record.active_duration else {
+            continue;
+        };
+        if record.sync_state == "skipped" {
+            continue;
+        }
+
+        let Some(start) = tz.timestamp_opt(record.start_time as i64, 0).earliest() else {
+            continue;
+        };
+        let date = start.date_naive();
+
+        plays += 1;
+        seconds += duration;
+        add(&mut games, &record.label, duration);
+        add(&mut languages, &record.language, duration);
+        add(&mut periods, &period_key(period, date), duration);
+        days.insert(date);
+        sessions.push(Session {
+            id: record.id,
+            label: record.label.clone(),
+            start_time: record.start_time,
+            seconds: duration,
+        });
+    }
+
+    sessions.sort_by(|a, b| {
+        b.seconds
+            .cmp(&a.seconds)
+            .then(a.start_time.cmp(&b.start_time))
+    });
+    sessions.truncate(LONGEST_SESSIONS);
+
+    let (current_streak, longest_streak) = streaks(&days, today);
+
+    Stats {
+        plays,
+        seconds,
+        games: by_seconds(games),
+        languages: by_seconds(languages),
+        period,
+        periods: periods.into_values().collect(),
+        longest_sessions: sessions,
+        current_streak,
+        longest_streak,
+    }
+}
+
+fn add(totals: &mut BTreeMap<String, Total>, key: &str, seconds: u64) {
+    let total = totals.entry(key.to_owned()).or_insert_with(|| Total {
+        key: key.to_owned(),
+        plays: 0,
+        seconds: 0,
+    });
+    total.plays += 1;
+    total.seconds += seconds;
+}
+
+fn by_seconds(totals: BTreeMap<String, Total>) -> Vec<Total> {
+    let mut totals: Vec<_> = totals.into_values().collect();
+    totals.sort_by(|a, b| b.seconds.cmp(&a.seconds).then(a.key.cmp(&b.key)));
+    totals
+}
+
+fn period_key(period: Period, date: NaiveDate) -> String {
+    match period {
+        Period::Day => date.format("%Y-%m-%d").to_string(),
+        Period::Week => date.format("%G-W%V").to_string(),
+        Period::Month => date.format("%Y-%m").to_string(),
+    }
+}
+
+fn streaks(days: &BTreeSet<NaiveDate>, today: NaiveDate) -> (u64, u64) {
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+
+    for &day in days {
+        run = match previous {
+            Some(p) if p.checked_add_days(Days::new(1)) == Some(day) => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        previous = Some(day);
+    }
+
+    let yesterday = today.checked_sub_days(Days::new(1));
+    let current = match previous {
+        Some(last) if last == today || Some(last) == yesterday => run,
+        _ => 0,
+    };
+
+    (current, longest)
+}
+
+fn duration(seconds: u64) -> String {
+    format!("{}h {:02}m", seconds / 3600, seconds / 60 % 60)
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} plays, {}", self.plays, duration(self.seconds))?;
+        writeln!(
+            f,
+            "Streak: {} days (longest {} days)",
+            self.current_streak, self.longest_streak
+        )?;
+
+        for (title, totals) in [
+            ("Games", &self.games),
+            ("Languages", &self.languages),
+            (
+                match self.period {
+                    Period::Day => "Days",
+                    Period::Week => "Weeks",
+                    Period::Month => "Months",
+                },
+                &self.periods,
+            ),
+        ] {
+            writeln!(f, "\n{title}:")?;
+            for total in totals {
+                writeln!(
+                    f,
+                    "  {:>9}  {:>4} plays  {}",
+                    duration(total.seconds),
+                    total.plays,
+                    total.key
+                )?;
+            }
+        }
+
+        writeln!(f, "\nLongest sessions:")?;
+        for session in &self.longest_sessions {
+            let start = Local
+                .timestamp_opt(session.start_time as i64, 0)
+                .earliest()
+                .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default();
+            writeln!(
+                f,
+                "  {:>9}  {start}  {}",
+                duration(session.seconds),
+                session.label
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+    // 2024-03-01 00:00:00 UTC
+    const MARCH_1: u64 = 1709251200;
+
+    fn play(id: i64, label: &str, language: &str, start_time: u64, seconds: u64) -> PlayRecord {
+        PlayRecord {
+            id,
+            game: format!("gb/{label}.gb"),
+            label: label.to_owned(),
+            language: language.to_owned(),
+            start_time,
+            end_time: Some(start_time + seconds),
+            end_estimated: false,
+            duration: Some(seconds),
+            active_duration: Some(seconds),
+            intake_id: None,
+            sync_state: "synced",
+        }
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap()
+    }
+
+    #[test]
+    fn test_compute() {
+        let mut in_progress = play(5, "Tetris", "ja", MARCH_1 + 9 * DAY, 0);
+        in_progress.end_time = None;
+        in_progress.duration = None;
+        in_progress.active_duration = None;
+        let mut skipped = play(6, "Tetris", "ja", MARCH_1, 9000);
+        skipped.sync_state = "skipped";
+
+        let records = vec![
+            play(1, "Tetris", "ja", MARCH_1, 600),
+            play(2, "Zelda", "en", MARCH_1 + 100, 1200),
+            play(3, "Tetris", "ja", MARCH_1 + DAY, 300),
+            play(4, "Tetris", "ja", MARCH_1 + 8 * DAY, 60),
+            in_progress,
+            skipped,
+        ];
+
+        let stats = compute(&records, Period::Day, &Utc, date(10));
+        assert_eq!(stats.plays, 4);
+        assert_eq!(stats.seconds, 2160);
+        assert_eq!(
+            stats.games,
+            vec![
+                Total {
+                    key: "Zelda".to_owned(),
+                    plays: 1,
+                    seconds: 1200
+                },
+                Total {
+                    key: "Tetris".to_owned(),
+                    plays: 3,
+                    seconds: 960
+                },
+            ]
+        );
+        assert_eq!(stats.languages[0].key, "en");
+        assert_eq!(
+            stats
+                .periods
+                .iter()
+                .map(|t| (t.key.as_str(), t.seconds))
+                .collect::<Vec<_>>(),
+            vec![
+                ("2024-03-01", 1800),
+                ("2024-03-02", 300),
+                ("2024-03-09", 60)
+            ]
+        );
+        assert_eq!(
+            stats
+                .longest_sessions
+                .iter()
+                .map(|s| s.id)
+                .collect::<Vec<_>>(),
+            vec![2, 1, 3, 4]
+        );
+        assert_eq!(stats.current_streak, 1);
+        assert_eq!(stats.longest_streak, 2);
+
+        let stats = compute(&records, Period::Month, &Utc, date(20));
+        assert_eq!(stats.periods.len(), 1);
+        assert_eq!(stats.periods[0].key, "2024-03");
+        assert_eq!(stats.current_streak, 0);
+    }
+
+    #[test]
+    fn test_period_key() {
+        assert_eq!(period_key(Period::Day, date(1)), "2024-03-01");
+        assert_eq!(period_key(Period::Week, date(1)), "2024-W09");
+        assert_eq!(period_key(Period::Month, date(1)), "2024-03");
+    }
+
+    #[test]
+    fn test_streaks() {
+        let days = |ds: &[u32]| ds.iter().map(|&d| date(d)).collect::<BTreeSet<_>>();
+        assert_eq!(streaks(&days(&[]), date(5)), (0, 0));
+        assert_eq!(streaks(&days(&[1, 2, 3, 5]), date(5)), (1, 3));
+        assert_eq!(streaks(&days(&[1, 3, 4]), date(5)), (2, 2));
+        assert_eq!(streaks(&days(&[1, 2]), date(5)), (0, 2));
+    }
+}